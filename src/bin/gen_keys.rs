@@ -54,14 +54,14 @@ pub fn main() {
 
     let srs = ParamsKZG::<Bn256>::new(k);
     let srs_path = out_dir.join(format!("{}_srs", circuit_name));
-    write_srs(&srs, srs_path.as_path());
+    write_srs(&srs, srs_path.as_path()).expect("Unable to write SRS");
 
     let vk = keygen_vk(&srs, &circuit).expect("keygen_vk should not fail");
     let vk_path = out_dir.join(format!("{}_vk", circuit_name));
     let pk = keygen_pk(&srs, vk, &circuit).expect("keygen_pk should not fail");
     let pk_path = out_dir.join(format!("{}_pk", circuit_name));
 
-    write_keys(&pk, pk_path.as_path(), vk_path.as_path());
+    write_keys(&pk, pk_path.as_path(), vk_path.as_path()).expect("Unable to write keys");
 
     println!("Circuit file preparation finished successfully.");
     println!("SRS stored in {}", srs_path.display());
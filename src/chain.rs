@@ -0,0 +1,257 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, get_num_keccak_f};
+use crate::vanilla::KeccakConfigParams;
+
+/// Config of [`KeccakChainCircuit`]
+#[derive(Clone, Debug)]
+pub struct ChainConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving `h_n = keccak256^n(x)`, the digest of `n` iterated hashes of `x`.
+///
+/// Every hash after the first one has a 32 byte input, whose `word_value` cells are constrained to
+/// the bytes of the `hash_lo` and `hash_hi` cells of the hash before it. The public instance is
+/// `h_n` as `[lo, hi]`, followed by the bytes of `x`, one byte per instance cell, if
+/// [`KeccakChainCircuit::with_public_input`] is set. The length of `x` and `n` are part of the
+/// circuit.
+#[derive(Default, Clone, Debug)]
+pub struct KeccakChainCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    input: Vec<u8>,
+    length: usize,
+    public_input: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakChainCircuit<F> {
+    type Config = ChainConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            input: vec![0; self.input.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Decomposes both the input words and the digest halves
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        ChainConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        if self.length == 0 {
+            log::error!("A hash chain needs at least one hash");
+            return Err(Error::Synthesis);
+        }
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let links = self.links();
+        let inputs = std::iter::once(self.input.as_slice())
+            .chain(links.iter().map(|link| link.as_slice()))
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        let input_cells = layouter.assign_region(
+            || "hash chain links",
+            |mut region| {
+                let mut offset = 0;
+                for (hash, next_hash) in hashes.iter().zip(hashes.iter().skip(1)) {
+                    let digest = config.bytes.assign_digest(
+                        &mut region,
+                        offset,
+                        &hash.hash_lo,
+                        &hash.hash_hi,
+                    )?;
+                    let (next_input, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset + 2,
+                        keccak_config,
+                        &assigned_rows,
+                        next_hash.first_keccak_f,
+                        0..32,
+                    )?;
+                    offset = next_offset;
+                    for (digest_byte, input_byte) in digest.iter().zip(next_input.iter()) {
+                        region.constrain_equal(digest_byte.cell(), input_byte.cell())?;
+                    }
+                }
+                if !self.public_input {
+                    return Ok(vec![]);
+                }
+                let (input_cells, _) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    offset,
+                    keccak_config,
+                    &assigned_rows,
+                    hashes[0].first_keccak_f,
+                    0..self.input.len(),
+                )?;
+                Ok(input_cells)
+            },
+        )?;
+
+        let last = hashes.last().ok_or(Error::Synthesis)?;
+        let public_cells = [&last.hash_lo, &last.hash_hi]
+            .into_iter()
+            .chain(input_cells.iter());
+        for (idx, cell) in public_cells.enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> KeccakChainCircuit<F> {
+    /// Creates a circuit hashing `input` `length` times in `num_rows` rows.
+    pub fn new(config: KeccakConfigParams, num_rows: usize, input: Vec<u8>, length: usize) -> Self {
+        KeccakChainCircuit {
+            config,
+            num_rows,
+            input,
+            length,
+            public_input: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Makes the bytes of the first input public, see [`KeccakChainCircuit`]
+    pub fn with_public_input(self) -> Self {
+        Self {
+            public_input: true,
+            ..self
+        }
+    }
+
+    /// The longest chain starting from an input of `input_len` bytes that fits in `num_rows` rows of
+    /// `config`, 0 if not even the first hash fits.
+    ///
+    /// Every hash after the first one takes a single keccak_f.
+    pub fn max_length(config: KeccakConfigParams, num_rows: usize, input_len: usize) -> usize {
+        let capacity = get_keccak_capacity(num_rows, config.rows_per_round);
+        (capacity + 1).saturating_sub(get_num_keccak_f(input_len))
+    }
+
+    /// The last digest `h_n` of the chain
+    pub fn digest(&self) -> [u8; 32] {
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&Keccak256::digest(
+            self.links().last().unwrap_or(&self.input),
+        ));
+        digest
+    }
+
+    /// The public inputs of the circuit, see [`KeccakChainCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let digest = self.digest();
+        let mut instance = vec![
+            F::from_u128(u128::from_be_bytes(digest[16..].try_into().unwrap())),
+            F::from_u128(u128::from_be_bytes(digest[..16].try_into().unwrap())),
+        ];
+        if self.public_input {
+            instance.extend(self.input.iter().map(|byte| F::from(*byte as u64)));
+        }
+        instance
+    }
+
+    /// The inputs of all hashes after the first one, `h_1` to `h_{n-1}`
+    fn links(&self) -> Vec<Vec<u8>> {
+        let mut links: Vec<Vec<u8>> = Vec::with_capacity(self.length.saturating_sub(1));
+        for _ in 1..self.length {
+            let next = Keccak256::digest(links.last().unwrap_or(&self.input)).to_vec();
+            links.push(next);
+        }
+        links
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+
+    use super::KeccakChainCircuit;
+
+    #[test]
+    fn test_hash_chain() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let input = b"hash chain seed".to_vec();
+        let mut expected = input.clone();
+        for _ in 0..5 {
+            expected = Keccak256::digest(&expected).to_vec();
+        }
+        let circuit = KeccakChainCircuit::<Fr>::new(CONFIG, NUM_ROWS, input.clone(), 5);
+        assert_eq!(circuit.digest().to_vec(), expected);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let circuit = circuit.with_public_input();
+        assert_eq!(circuit.instance().len(), 2 + input.len());
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut instance = circuit.instance();
+        instance[2] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_hash_chain_wrong_digest() {
+        let circuit = KeccakChainCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![1, 2, 3], 3);
+        // The digest of a chain one hash shorter
+        let shorter = KeccakChainCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![1, 2, 3], 2);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![shorter.instance()]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_max_length() {
+        let max_length = KeccakChainCircuit::<Fr>::max_length(CONFIG, NUM_ROWS, 200);
+        // 31 keccak_f's, the first input takes 2 of them
+        assert_eq!(max_length, 30);
+        assert_eq!(
+            KeccakChainCircuit::<Fr>::max_length(CONFIG, NUM_ROWS, 32 * 136),
+            0
+        );
+
+        let circuit = KeccakChainCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![7; 200], max_length);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let circuit = KeccakChainCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![7; 200], max_length + 1);
+        assert!(MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).is_err());
+    }
+}
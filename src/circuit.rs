@@ -5,22 +5,25 @@ use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
 use halo2_proofs::halo2curves::ff::PrimeField;
 use halo2_proofs::plonk::{Circuit, Column, ConstraintSystem, create_proof, Error, Instance, ProvingKey, verify_proof, VerifyingKey};
-use halo2_proofs::poly::commitment::ParamsProver;
+use halo2_proofs::poly::commitment::{Params, ParamsProver};
 use halo2_proofs::poly::kzg::commitment::{KZGCommitmentScheme, ParamsKZG};
 use halo2_proofs::poly::kzg::multiopen::{ProverSHPLONK, VerifierSHPLONK};
 use halo2_proofs::poly::kzg::strategy::SingleStrategy;
 use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255, TranscriptReadBuffer, TranscriptWriterBuffer};
 use itertools::Itertools;
-use rand::thread_rng;
+use rand::RngCore;
 use sha3::{Digest, Keccak256};
 
-use crate::DEFAULT_CONFIG;
+use crate::{DEFAULT_CONFIG, Keccak256Error};
 use crate::util::{SKIP_FIRST_PASS, value_to_option};
 use crate::util::eth_types::Field;
 use crate::vanilla::{KeccakAssignedRow, KeccakCircuitConfig, KeccakConfigParams};
-use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, KeccakAssignedValue};
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, get_num_keccak_f, KeccakAssignedValue};
 use crate::vanilla::param::{NUM_BYTES_PER_WORD, NUM_ROUNDS, NUM_WORDS_TO_ABSORB};
-use crate::vanilla::witness::multi_keccak;
+use crate::vanilla::witness::{keccak_states, multi_keccak_bytes};
+
+/// Rows at the end of the circuit taken by the blinding factors
+pub(crate) const NUM_UNUSABLE_ROWS: usize = 109;
 
 #[derive(Clone, Debug)]
 pub struct CircuitConfig<F> {
@@ -29,7 +32,30 @@ pub struct CircuitConfig<F> {
     _marker: PhantomData<F>,
 }
 
+/// Parameters of [`KeccakCircuit`]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct KeccakCircuitParams {
+    pub config: KeccakConfigParams,
+    /// Enables equality on the state columns, for the states made public with
+    /// [`KeccakCircuit::with_public_states`]
+    pub public_states: bool,
+}
+
+impl From<KeccakConfigParams> for KeccakCircuitParams {
+    fn from(config: KeccakConfigParams) -> Self {
+        KeccakCircuitParams {
+            config,
+            public_states: false,
+        }
+    }
+}
+
 /// KeccakCircuit
+///
+/// The inputs are held in memory, both to generate the witness, in parallel over the inputs, and
+/// to lay out their words as public inputs. Inputs too large for that can be hashed by assigning
+/// the rows of [`multi_keccak`](crate::vanilla::witness::multi_keccak) from readers with
+/// [`KeccakCircuitConfig::assign`] in a circuit of their own.
 #[derive(Default, Clone, Debug)]
 pub struct KeccakCircuit<F: Field> {
     config: KeccakConfigParams,
@@ -37,20 +63,27 @@ pub struct KeccakCircuit<F: Field> {
     num_rows: Option<usize>,
     verify_output: bool,
     use_instance: bool,
+    public_states: Vec<usize>,
     _marker: PhantomData<F>,
 }
 
 impl<F: Field> Circuit<F> for KeccakCircuit<F> {
     type Config = CircuitConfig<F>;
     type FloorPlanner = SimpleFloorPlanner;
-    type Params = KeccakConfigParams;
+    type Params = KeccakCircuitParams;
 
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        Self {
+            public_states: self.public_states.clone(),
+            ..Self::default()
+        }
     }
 
     fn params(&self) -> Self::Params {
-        self.config
+        KeccakCircuitParams {
+            config: self.config,
+            public_states: !self.public_states.is_empty(),
+        }
     }
 
     fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
@@ -58,7 +91,12 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
         meta.advice_column();
 
         let input = meta.instance_column();
-        let keccak_config = KeccakCircuitConfig::new(meta, params);
+        // The input words and the public states are copied to the instance
+        meta.enable_equality(input);
+        let keccak_config = KeccakCircuitConfig::new(meta, params.config);
+        if params.public_states {
+            keccak_config.enable_state_equality(meta);
+        }
 
         CircuitConfig {
             input,
@@ -82,6 +120,7 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
             .load_aux_tables(&mut layouter, params.k)?;
         let mut first_pass = SKIP_FIRST_PASS;
         let mut cache = vec![];
+        let mut public_state_cells = vec![];
         layouter.assign_region(
             || "keccak circuit",
             |mut region| {
@@ -89,30 +128,53 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
                     first_pass = false;
                     return Ok(());
                 }
-                let (witness, _) = multi_keccak(
-                    &self.inputs,
+                let inputs = self.inputs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                let witness = multi_keccak_bytes(
+                    &inputs,
                     self.num_rows
                         .map(|nr| get_keccak_capacity(nr, params.rows_per_round)),
                     params,
-                );
-                let assigned_rows = config.keccak_config.assign(&mut region, &witness);
+                )?;
+                let assigned_rows = config.keccak_config.assign(&mut region, witness)?;
                 cache.push(assigned_rows.clone());
+                public_state_cells = self
+                    .public_states
+                    .iter()
+                    .map(|&idx| {
+                        config
+                            .keccak_config
+                            .state_cells_after_keccak_f(&assigned_rows, idx)
+                            .ok_or_else(|| {
+                                log::error!("Public state after keccak_f {} is out of range", idx);
+                                Error::Synthesis
+                            })
+                    })
+                    .flatten_ok()
+                    .collect::<Result<Vec<_>, _>>()?;
                 if self.verify_output {
-                    self.verify_output_witnesses(&assigned_rows);
-                    self.verify_input_witnesses(&assigned_rows);
+                    self.verify_output_witnesses(&assigned_rows)
+                        .and_then(|_| self.verify_input_witnesses(&assigned_rows))
+                        .map_err(|e| {
+                            log::error!("Keccak witness self-check failed: {}", e);
+                            Error::Synthesis
+                        })?;
                 }
 
                 Ok(())
             },
         )?;
 
+        for (idx, cell) in public_state_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.input, idx)?;
+        }
         if self.use_instance {
             for assigned_row in cache.iter() {
                 self.constraint_public_inputs(
                     layouter.namespace(|| "public inputs"),
                     assigned_row,
                     &config,
-                );
+                    public_state_cells.len(),
+                )?;
             }
         }
 
@@ -120,6 +182,46 @@ impl<F: Field> Circuit<F> for KeccakCircuit<F> {
     }
 }
 
+/// A mismatch between the assigned witness and the natively computed keccak inputs/outputs.
+#[derive(Debug, thiserror::Error)]
+pub enum WitnessCheckError {
+    #[error(
+        "digest of input {input} at row {row} is 0x{actual_hi:032x}{actual_lo:032x}, expected 0x{expected_hi:032x}{expected_lo:032x}"
+    )]
+    Digest {
+        input: usize,
+        row: usize,
+        expected_hi: u128,
+        expected_lo: u128,
+        actual_hi: u128,
+        actual_lo: u128,
+    },
+    #[error("bytes_left of input {input} at row {row} is {actual}, expected {expected}")]
+    BytesLeft {
+        input: usize,
+        row: usize,
+        expected: u128,
+        actual: u128,
+    },
+    #[error("word_value of input {input} at row {row} is {actual:#018x}, expected {expected:#018x}")]
+    WordValue {
+        input: usize,
+        row: usize,
+        expected: u128,
+        actual: u128,
+    },
+    #[error("padding row {row} is not empty: word_value {word_value:#x}, bytes_left {bytes_left}")]
+    NonEmptyPadding {
+        row: usize,
+        word_value: u128,
+        bytes_left: u128,
+    },
+    #[error("value at row {row} is not a known field element")]
+    UnknownValue { row: usize },
+    #[error("value at row {row} does not fit in 128 bits")]
+    Overflow { row: usize },
+}
+
 impl<F: Field> KeccakCircuit<F> {
     /// Creates a new circuit instance
     pub fn new(
@@ -136,16 +238,53 @@ impl<F: Field> KeccakCircuit<F> {
             _marker: PhantomData,
             verify_output,
             use_instance,
+            public_states: vec![],
+        }
+    }
+
+    /// Makes the sponge state after each of the given keccak_f's public, e.g. to continue a
+    /// transcript or an accumulator from a prefix of an input.
+    ///
+    /// The keccak_f's are counted over all inputs from the first one, the input with `n` bytes
+    /// takes `n / RATE + 1` of them. Each state takes 25 instance cells before the input words,
+    /// holding the lanes `s[i][j]` in packed form, ordered by `i * 5 + j`. See [`Self::instance`].
+    pub fn with_public_states(mut self, keccak_f_indices: Vec<usize>) -> Self {
+        self.public_states = keccak_f_indices;
+        self
+    }
+
+    /// The public inputs of the circuit: the states selected with [`Self::with_public_states`],
+    /// followed by the input words if the circuit uses the instance column for the inputs.
+    pub fn instance(&self) -> Vec<F> {
+        let states = self
+            .inputs
+            .iter()
+            .flat_map(|input| keccak_states::<F>(input))
+            .collect::<Vec<_>>();
+        let padding_state = keccak_states::<F>(&[])[0];
+        let mut instance = self
+            .public_states
+            .iter()
+            .flat_map(|&idx| states.get(idx).copied().unwrap_or(padding_state))
+            .flatten()
+            .collect::<Vec<_>>();
+        if self.use_instance {
+            instance.extend(pack_input_to_instance::<F>(&self.inputs));
         }
+        instance
     }
 
-    fn verify_output_witnesses(&self, assigned_rows: &[KeccakAssignedRow<F>]) {
+    fn verify_output_witnesses(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<F>],
+    ) -> Result<(), WitnessCheckError> {
         let mut input_offset = 0;
         // only look at last row in each round
         // first round is dummy, so ignore
         // only look at last round per absorb of RATE_IN_BITS
-        for assigned_row in assigned_rows
+        for (row, assigned_row) in assigned_rows
             .iter()
+            .enumerate()
             .step_by(self.config.rows_per_round)
             .step_by(NUM_ROUNDS + 1)
             .skip(1)
@@ -156,48 +295,62 @@ impl<F: Field> KeccakCircuit<F> {
                 hash_hi,
                 ..
             } = assigned_row.clone();
-            let is_final_val = extract_value(is_final).ne(&F::ZERO);
-            let hash_lo_val = extract_u128(hash_lo);
-            let hash_hi_val = extract_u128(hash_hi);
+            let is_final_val = extract_value(is_final, row)?.ne(&F::ZERO);
+            let hash_lo_val = extract_u128(hash_lo, row)?;
+            let hash_hi_val = extract_u128(hash_hi, row)?;
 
             if input_offset < self.inputs.len() && is_final_val {
                 // out is in big endian.
                 let out = Keccak256::digest(&self.inputs[input_offset]);
                 let lo = u128::from_be_bytes(out[16..].try_into().unwrap());
                 let hi = u128::from_be_bytes(out[..16].try_into().unwrap());
-                assert_eq!(lo, hash_lo_val);
-                assert_eq!(hi, hash_hi_val);
+                if lo != hash_lo_val || hi != hash_hi_val {
+                    return Err(WitnessCheckError::Digest {
+                        input: input_offset,
+                        row,
+                        expected_hi: hi,
+                        expected_lo: lo,
+                        actual_hi: hash_hi_val,
+                        actual_lo: hash_lo_val,
+                    });
+                }
                 input_offset += 1;
             }
         }
+        Ok(())
     }
 
+    /// Constrains the input words to the instance cells from `instance_offset` on.
     fn constraint_public_inputs(
         &self,
         mut layouter: impl Layouter<F>,
         assigned_rows: &[KeccakAssignedRow<F>],
         config: &<KeccakCircuit<F> as Circuit<F>>::Config,
-    ) {
+        instance_offset: usize,
+    ) -> Result<(), Error> {
         let rows_per_round = self.config.rows_per_round;
         let mut input_offset = 0;
-        let mut total_offset = 0;
+        let mut total_offset = instance_offset;
         let mut input_byte_offset = 0;
 
         // first round is dummy, so ignore
-        for absorb_chunk in &assigned_rows
+        for (chunk_idx, absorb_chunk) in (&assigned_rows
             .chunks(rows_per_round)
             .skip(1)
-            .chunks(NUM_ROUNDS + 1)
+            .chunks(NUM_ROUNDS + 1))
+            .into_iter()
+            .enumerate()
         {
             let mut absorbed = false;
             for (round_idx, assigned_rows) in absorb_chunk.enumerate() {
                 for (row_idx, assigned_row) in assigned_rows.iter().enumerate() {
+                    let row = (1 + chunk_idx * (NUM_ROUNDS + 1) + round_idx) * rows_per_round
+                        + row_idx;
                     let KeccakAssignedRow {
                         is_final,
                         word_value,
                         ..
                     } = assigned_row.clone();
-                    let is_final_val = extract_value(is_final).ne(&F::ZERO);
 
                     // If we reached to the end of this chunk, skip it
                     if input_offset >= self.inputs.len() {
@@ -209,8 +362,12 @@ impl<F: Field> KeccakCircuit<F> {
                     if input_byte_offset >= input_len {
                         continue;
                     }
-                    if round_idx == NUM_ROUNDS && row_idx == 0 && is_final_val {
-                        absorbed = true;
+                    if round_idx == NUM_ROUNDS && row_idx == 0 {
+                        let is_final_val = extract_value(is_final, row).map_err(|e| {
+                            log::error!("Cannot read is_final for the public inputs: {}", e);
+                            Error::Synthesis
+                        })?;
+                        absorbed = is_final_val != F::ZERO;
                     }
                     if row_idx == 0 {
                         // Only these rows could contain inputs.
@@ -220,9 +377,11 @@ impl<F: Field> KeccakCircuit<F> {
                             input_byte_offset
                         };
 
-                        layouter
-                            .constrain_instance(word_value.cell(), config.input, total_offset)
-                            .unwrap();
+                        layouter.constrain_instance(
+                            word_value.cell(),
+                            config.input,
+                            total_offset,
+                        )?;
                         total_offset += 1;
 
                         input_byte_offset = end;
@@ -234,34 +393,47 @@ impl<F: Field> KeccakCircuit<F> {
                 input_byte_offset = 0;
             }
         }
+        Ok(())
     }
 
-    fn verify_input_witnesses(&self, assigned_rows: &[KeccakAssignedRow<F>]) {
+    fn verify_input_witnesses(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<F>],
+    ) -> Result<(), WitnessCheckError> {
         let rows_per_round = self.config.rows_per_round;
         let mut input_offset = 0;
         let mut input_byte_offset = 0;
         // first round is dummy, so ignore
-        for absorb_chunk in &assigned_rows
+        for (chunk_idx, absorb_chunk) in (&assigned_rows
             .chunks(rows_per_round)
             .skip(1)
-            .chunks(NUM_ROUNDS + 1)
+            .chunks(NUM_ROUNDS + 1))
+            .into_iter()
+            .enumerate()
         {
             let mut absorbed = false;
             for (round_idx, assigned_rows) in absorb_chunk.enumerate() {
                 for (row_idx, assigned_row) in assigned_rows.iter().enumerate() {
+                    let row = (1 + chunk_idx * (NUM_ROUNDS + 1) + round_idx) * rows_per_round
+                        + row_idx;
                     let KeccakAssignedRow {
                         is_final,
                         word_value,
                         bytes_left,
                         ..
                     } = assigned_row.clone();
-                    let is_final_val = extract_value(is_final).ne(&F::ZERO);
-                    let word_value_val = extract_u128(word_value);
-                    let bytes_left_val = extract_u128(bytes_left);
+                    let is_final_val = extract_value(is_final, row)?.ne(&F::ZERO);
+                    let word_value_val = extract_u128(word_value, row)?;
+                    let bytes_left_val = extract_u128(bytes_left, row)?;
                     // Padded inputs - all empty.
                     if input_offset >= self.inputs.len() {
-                        assert_eq!(word_value_val, 0);
-                        assert_eq!(bytes_left_val, 0);
+                        if word_value_val != 0 || bytes_left_val != 0 {
+                            return Err(WitnessCheckError::NonEmptyPadding {
+                                row,
+                                word_value: word_value_val,
+                                bytes_left: bytes_left_val,
+                            });
+                        }
                         continue;
                     }
                     let input_len = self.inputs[input_offset].len();
@@ -269,10 +441,15 @@ impl<F: Field> KeccakCircuit<F> {
                         absorbed = true;
                     }
                     if row_idx == 0 {
-                        assert_eq!(
-                            bytes_left_val,
-                            input_len as u128 - input_byte_offset as u128
-                        );
+                        let expected_bytes_left = input_len as u128 - input_byte_offset as u128;
+                        if bytes_left_val != expected_bytes_left {
+                            return Err(WitnessCheckError::BytesLeft {
+                                input: input_offset,
+                                row,
+                                expected: expected_bytes_left,
+                                actual: bytes_left_val,
+                            });
+                        }
                         // Only these rows could contain inputs.
                         let end = if round_idx < NUM_WORDS_TO_ABSORB {
                             std::cmp::min(input_byte_offset + NUM_BYTES_PER_WORD, input_len)
@@ -284,10 +461,16 @@ impl<F: Field> KeccakCircuit<F> {
                             .to_vec()
                             .clone();
                         expected_val_le_bytes.resize(NUM_BYTES_PER_WORD, 0);
-                        assert_eq!(
-                            word_value_val,
-                            u64::from_le_bytes(expected_val_le_bytes.try_into().unwrap()) as u128,
-                        );
+                        let expected_word_value =
+                            u64::from_le_bytes(expected_val_le_bytes.try_into().unwrap()) as u128;
+                        if word_value_val != expected_word_value {
+                            return Err(WitnessCheckError::WordValue {
+                                input: input_offset,
+                                row,
+                                expected: expected_word_value,
+                                actual: word_value_val,
+                            });
+                        }
                         input_byte_offset = end;
                     }
                 }
@@ -297,23 +480,34 @@ impl<F: Field> KeccakCircuit<F> {
                 input_byte_offset = 0;
             }
         }
+        Ok(())
     }
 }
 
-fn extract_value<F: Field>(assigned_value: KeccakAssignedValue<F>) -> F {
-    let assigned = *value_to_option(assigned_value.value()).unwrap();
+fn extract_value<F: Field>(
+    assigned_value: KeccakAssignedValue<F>,
+    row: usize,
+) -> Result<F, WitnessCheckError> {
+    let assigned = value_to_option(assigned_value.value())
+        .copied()
+        .ok_or(WitnessCheckError::UnknownValue { row })?;
     match assigned {
-        halo2_proofs::plonk::Assigned::Zero => F::ZERO,
-        halo2_proofs::plonk::Assigned::Trivial(f) => f,
-        _ => panic!("value should be trival"),
+        halo2_proofs::plonk::Assigned::Zero => Ok(F::ZERO),
+        halo2_proofs::plonk::Assigned::Trivial(f) => Ok(f),
+        _ => Err(WitnessCheckError::UnknownValue { row }),
     }
 }
 
-fn extract_u128<F: Field>(assigned_value: KeccakAssignedValue<F>) -> u128 {
-    let le_bytes = extract_value(assigned_value).to_bytes_le();
+fn extract_u128<F: Field>(
+    assigned_value: KeccakAssignedValue<F>,
+    row: usize,
+) -> Result<u128, WitnessCheckError> {
+    let le_bytes = extract_value(assigned_value, row)?.to_bytes_le();
     let hi = u128::from_le_bytes(le_bytes[16..].try_into().unwrap());
-    assert_eq!(hi, 0);
-    u128::from_le_bytes(le_bytes[..16].try_into().unwrap())
+    if hi != 0 {
+        return Err(WitnessCheckError::Overflow { row });
+    }
+    Ok(u128::from_le_bytes(le_bytes[..16].try_into().unwrap()))
 }
 
 /// Packs each input byte array into field elements for use in cryptographic computations,
@@ -346,24 +540,47 @@ fn unpack_input<F: Field>(instance: &[F]) -> Vec<u8> {
 }
 
 
+/// Generates a proof for the `in` input.
+/// The blinding factors are drawn from `rng`, so passing a seeded RNG makes the proof reproducible.
+/// The input is checked against the circuit capacity and the SRS/key sizes before proving.
 pub(crate) fn generate_halo2_proof(
     inputs: HashMap<String, Vec<Fr>>,
     srs: &ParamsKZG<Bn256>,
     pk: &ProvingKey<G1Affine>,
     config: Option<KeccakConfigParams>,
-) -> Result<(Vec<Fr>, Vec<u8>), String> {
+    rng: impl RngCore,
+) -> Result<(Vec<Fr>, Vec<u8>), Keccak256Error> {
     // Get inputs by name "input" from the inputs hashmap
     let raw_inputs = inputs
         .get("in")
-        .ok_or("`in` value not found in proof input".to_string())?;
+        .ok_or_else(|| Keccak256Error::InputParsing {
+            name: "in".to_string(),
+            reason: "value not found in proof input".to_string(),
+        })?;
 
     // Convert the raw inputs to a vector of u8
     // TODO - can be optimized by packing multiple bytes into field elements
     let inputs = vec![unpack_input(raw_inputs)];
 
+    let config = config.unwrap_or(DEFAULT_CONFIG);
+    let vk_k = pk.get_vk().get_domain().k();
+    if srs.k() != config.k || vk_k != config.k {
+        return Err(Keccak256Error::ParameterMismatch(format!(
+            "circuit config has k = {}, but the SRS has k = {} and the proving key k = {}",
+            config.k,
+            srs.k(),
+            vk_k
+        )));
+    }
+
+    let required = inputs.iter().map(|input| get_num_keccak_f(input.len())).sum();
+    let capacity = get_keccak_capacity(2usize.pow(config.k), config.rows_per_round);
+    if required > capacity {
+        return Err(Keccak256Error::CapacityExceeded { required, capacity });
+    }
+
     let instance = pack_input_to_instance::<Fr>(&inputs);
 
-    let config = config.unwrap_or(DEFAULT_CONFIG);
     // Set up the circuit
     let circuit = KeccakCircuit::new(
         config,
@@ -387,24 +604,29 @@ pub(crate) fn generate_halo2_proof(
         &pk,
         &[circuit],
         &[&[&instance[..]]],
-        thread_rng(),
+        rng,
         &mut transcript,
     )
-        .unwrap();
+        .map_err(Keccak256Error::ProofGeneration)?;
 
     let proof = transcript.finalize();
     Ok((instance, proof))
 }
 
 
+/// Verifies `proof` against the public `inputs`.
+/// A proof that ends early is reported as [`Keccak256Error::TruncatedProof`], one that cannot be decoded
+/// as [`Keccak256Error::MalformedProof`] and public inputs that do not fit the instance column as
+/// [`Keccak256Error::MalformedPublicInputs`]. Only a well-formed proof that fails the checks is
+/// [`Keccak256Error::InvalidProof`].
 pub(crate) fn verify_halo2_proof(
     proof: Vec<u8>,
     inputs: &Vec<Fr>,
     srs: &ParamsKZG<Bn256>,
     vk: &VerifyingKey<G1Affine>,
-) -> Result<bool, ()> {
+) -> Result<(), Keccak256Error> {
     let mut transcript = Blake2bRead::<_, G1Affine, Challenge255<_>>::init(&proof[..]);
-    let proof_verified = verify_proof::<
+    verify_proof::<
         KZGCommitmentScheme<Bn256>,
         VerifierSHPLONK<'_, Bn256>,
         Challenge255<G1Affine>,
@@ -417,8 +639,16 @@ pub(crate) fn verify_halo2_proof(
         &[&[&inputs[..]]],
         &mut transcript,
     )
-        .is_ok();
-    Ok(proof_verified)
+        .map_err(|e| match e {
+            Error::Transcript(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Keccak256Error::TruncatedProof
+            }
+            Error::Transcript(e) => Keccak256Error::MalformedProof(e.to_string()),
+            Error::InvalidInstances | Error::InstanceTooLarge => {
+                Keccak256Error::MalformedPublicInputs(e.to_string())
+            }
+            e => Keccak256Error::InvalidProof(e),
+        })
 }
 
 
@@ -430,10 +660,12 @@ mod test {
     use halo2_proofs::plonk::{keygen_pk, keygen_vk};
     use halo2_proofs::poly::commitment::ParamsProver;
     use halo2_proofs::poly::kzg::commitment::{ParamsKZG, ParamsVerifierKZG};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
     use rand_core::OsRng;
     use test_case::test_case;
 
-    use crate::{DEFAULT_CONFIG, KeccakCircuit};
+    use crate::{DEFAULT_CONFIG, Keccak256Error, KeccakCircuit, KeccakConfigParams};
     use crate::circuit::{generate_halo2_proof, pack_input_to_instance, unpack_input, verify_halo2_proof};
 
     #[test_case(vec ! [0u8, 151u8, 200u8, 255u8]; "4 Different Elements")]
@@ -498,7 +730,7 @@ mod test {
         let vk = keygen_vk(&srs, &circuit).unwrap();
         let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
 
-        let (public_input, proof) = generate_halo2_proof(inputs, &srs, &pk, Some(config))
+        let (public_input, proof) = generate_halo2_proof(inputs, &srs, &pk, Some(config), OsRng)
             .map_err(|_| "Failed to prove")
             .unwrap();
         assert!(public_input.len() > 0, "Public input is empty");
@@ -536,13 +768,151 @@ mod test {
         let vk = keygen_vk(&srs, &circuit).unwrap();
         let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
 
-        let (public_input, proof) = generate_halo2_proof(inputs, &srs, &pk, Some(config))
+        let (public_input, proof) = generate_halo2_proof(inputs, &srs, &pk, Some(config), OsRng)
             .map_err(|_| "Failed to prove")
             .unwrap();
         let verifier_srs: ParamsVerifierKZG<Bn256> = srs.verifier_params().clone();
-        let result = verify_halo2_proof(proof, &public_input, &verifier_srs, &vk)
-            .map_err(|_| "Failed to verify")
-            .unwrap();
-        assert!(result, "Proof verification failed");
+        let result = verify_halo2_proof(proof, &public_input, &verifier_srs, &vk);
+        assert!(result.is_ok(), "Proof verification failed: {:?}", result);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_verify_distinguishes_failures() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let config = DEFAULT_CONFIG;
+        let input = [1u8, 10u8, 100u8].repeat(10);
+
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+
+        let mut inputs = HashMap::new();
+
+        inputs.insert(
+            "in".to_string(),
+            input
+                .iter()
+                .map(|x| Fr::from(*x as u64))
+                .collect::<Vec<_>>(),
+        );
+
+        // Generate the keys
+        let circuit = KeccakCircuit::new(
+            config,
+            Some(2usize.pow(config.k)),
+            vec![],
+            false,
+            false,
+        );
+
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk.clone(), &circuit).unwrap();
+
+        let (public_input, proof) =
+            generate_halo2_proof(inputs, &srs, &pk, Some(config), OsRng).unwrap();
+
+        // Public inputs that do not match the proof
+        let mut tampered_input = public_input.clone();
+        tampered_input[0] += Fr::from(1);
+        let result = verify_halo2_proof(proof.clone(), &tampered_input, &srs, &vk);
+        assert!(matches!(result, Err(Keccak256Error::InvalidProof(_))), "{:?}", result);
+
+        // A proof cut in half
+        let truncated = proof[..proof.len() / 2].to_vec();
+        let result = verify_halo2_proof(truncated, &public_input, &srs, &vk);
+        assert!(matches!(result, Err(Keccak256Error::TruncatedProof)), "{:?}", result);
+
+        // The untouched proof still verifies
+        assert!(verify_halo2_proof(proof, &public_input, &srs, &vk).is_ok());
+    }
+
+    #[test]
+    fn test_prove_rejects_input_over_capacity() {
+        let config = KeccakConfigParams {
+            k: 11,
+            rows_per_round: 28,
+        };
+        // 300 bytes need 3 keccak_f permutations, a 2^11 row circuit only fits 2
+        let input = [7u8].repeat(300);
+
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+
+        let mut inputs = HashMap::new();
+        inputs.insert(
+            "in".to_string(),
+            input
+                .iter()
+                .map(|x| Fr::from(*x as u64))
+                .collect::<Vec<_>>(),
+        );
+
+        let circuit = KeccakCircuit::new(
+            config,
+            Some(2usize.pow(config.k)),
+            vec![],
+            false,
+            false,
+        );
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk, &circuit).unwrap();
+
+        let result = generate_halo2_proof(inputs, &srs, &pk, Some(config), OsRng);
+        assert!(matches!(
+            result,
+            Err(Keccak256Error::CapacityExceeded {
+                required: 3,
+                capacity: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_seeded_proofs_are_identical() {
+        let _ = env_logger::builder().is_test(true).try_init();
+
+        let config = DEFAULT_CONFIG;
+        let input = [1u8, 10u8, 100u8].repeat(10);
+
+        let srs = ParamsKZG::<Bn256>::setup(config.k, OsRng);
+
+        let mut inputs = HashMap::new();
+
+        inputs.insert(
+            "in".to_string(),
+            input
+                .iter()
+                .map(|x| Fr::from(*x as u64))
+                .collect::<Vec<_>>(),
+        );
+
+        // Generate the keys
+        let circuit = KeccakCircuit::new(
+            config,
+            Some(2usize.pow(config.k)),
+            vec![],
+            false,
+            false,
+        );
+
+        let vk = keygen_vk(&srs, &circuit).unwrap();
+        let pk = keygen_pk(&srs, vk, &circuit).unwrap();
+
+        let prove = |seed: u64| {
+            generate_halo2_proof(
+                inputs.clone(),
+                &srs,
+                &pk,
+                Some(config),
+                StdRng::seed_from_u64(seed),
+            )
+            .unwrap()
+        };
+
+        let (first_instance, first_proof) = prove(42);
+        let (second_instance, second_proof) = prove(42);
+        assert_eq!(first_instance, second_instance);
+        assert_eq!(first_proof, second_proof, "Proofs with the same seed differ");
+
+        let (_, other_proof) = prove(43);
+        assert_ne!(first_proof, other_proof, "Proofs with different seeds are identical");
+    }
+}
@@ -0,0 +1,448 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::keccak::{configure_keccak, pin_hashes};
+use crate::util::eth_types::{Field, H256};
+use crate::util::word::Word;
+use crate::util::SKIP_FIRST_PASS;
+use crate::vanilla::keccak_packed_multi::{
+    get_keccak_capacity, get_num_keccak_f, KeccakAssignedValue,
+};
+use crate::vanilla::param::{
+    KECCAK_WIDTH, NUM_BYTES_PER_WORD, NUM_ROUNDS, NUM_WORDS_TO_ABSORB, NUM_WORDS_TO_SQUEEZE, RATE,
+};
+use crate::vanilla::util::{absorb_block, keccak_f1600, pack_u64, pad_keccak_input};
+use crate::vanilla::witness::{continuation_end_rows, keccak_chunk, multi_keccak_bytes};
+use crate::vanilla::{KeccakAssignedRow, KeccakCircuitConfig, KeccakConfigParams};
+use crate::Keccak256Error;
+
+/// Sponge state as the 25 lanes `s[i][j]` of 64 bits.
+pub type KeccakState = [[u64; 5]; 5];
+
+/// Number of bytes of a state as committed to by [`state_commitment`]
+const STATE_BYTES: usize = KECCAK_WIDTH * NUM_BYTES_PER_WORD;
+
+/// Number of keccak_f's hashing the start and the end state of a chunk, two for each
+const NUM_COMMITMENT_KECCAK_F: usize = 4;
+
+/// Commitment to a sponge state: the keccak digest of its lanes `s[i][j]` as little-endian words,
+/// ordered by `i * 5 + j`.
+pub fn state_commitment(state: &KeccakState) -> H256 {
+    H256::from_slice(&Keccak256::digest(state_bytes(state)))
+}
+
+fn state_bytes(state: &KeccakState) -> Vec<u8> {
+    state.iter().flatten().flat_map(|lane| lane.to_le_bytes()).collect()
+}
+
+/// The number of keccak_f's of the hash that a chunk of `num_rows` rows can absorb, next to the
+/// keccak_f's of its state commitments.
+pub fn get_chunk_capacity(num_rows: usize, rows_per_round: usize) -> usize {
+    let commitment_rows =
+        (1 + NUM_COMMITMENT_KECCAK_F * (NUM_ROUNDS + 1) + NUM_WORDS_TO_ABSORB) * rows_per_round;
+    get_keccak_capacity(num_rows.saturating_sub(commitment_rows), rows_per_round)
+}
+
+/// Circuit proving one chunk of a keccak hash that is too large for a single [`crate::KeccakCircuit`].
+///
+/// The chunk continues the sponge from a start state and absorbs a contiguous range of blocks of the
+/// input. Every chunk but the last fills the circuit capacity with whole blocks and ends in a state
+/// that the next chunk starts from, the last chunk pads the input and outputs the digest.
+///
+/// The states stay private, the chunk publishes their [`state_commitment`]s instead. The state
+/// cells are copied into the input words of the commitment hashes, which follow the chunk in a
+/// second run of keccak rows. The public instance is the commitment `[lo, hi]` to the start state
+/// followed by the commitment to the end state, or by the digest `[lo, hi]` for the last chunk. A
+/// chain of chunk proofs attests to the hash of the whole input when the first chunk starts from
+/// the commitment to the all-zero state and every chunk starts from the end commitment of the
+/// previous one.
+///
+/// The `is_final` of every keccak_f is a constant of the circuit, so a last chunk needs keys
+/// generated for its block count.
+#[derive(Default, Clone, Debug)]
+pub struct KeccakChunkCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    start_state: KeccakState,
+    input: Vec<u8>,
+    is_last: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakChunkCircuit<F> {
+    type Config = CircuitConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            start_state: KeccakState::default(),
+            input: vec![0; self.input.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let config = configure_keccak(meta, params);
+        config.keccak_config.enable_absorbed_word_equality(meta);
+        config.keccak_config.enable_state_equality(meta);
+        config
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let params = config.keccak_config.parameters;
+        config
+            .keccak_config
+            .load_aux_tables(&mut layouter, params.k)?;
+        let mut first_pass = SKIP_FIRST_PASS;
+        let mut public_cells = vec![];
+        layouter.assign_region(
+            || "keccak chunk",
+            |mut region| {
+                if first_pass {
+                    first_pass = false;
+                    return Ok(());
+                }
+                let witness = keccak_chunk(
+                    self.input.as_slice(),
+                    self.input.len(),
+                    self.start_state.map(|s| s.map(pack_u64)),
+                    self.is_last,
+                    get_chunk_capacity(self.num_rows, params.rows_per_round),
+                    params,
+                )?;
+                let assigned_rows = config.keccak_config.assign(&mut region, witness)?;
+
+                let rows_per_round = params.rows_per_round;
+                let num_keccak_f = self.num_keccak_f();
+                let last_round_offset =
+                    |idx: usize| (1 + idx * (NUM_ROUNDS + 1) + NUM_ROUNDS) * rows_per_round;
+                // The hash may only be finished by the last keccak_f of the last chunk
+                for idx in 0..num_keccak_f {
+                    let is_final = self.is_last && idx == num_keccak_f - 1;
+                    region.constrain_constant(
+                        assigned_rows[last_round_offset(idx)].is_final.cell(),
+                        F::from(is_final),
+                    )?;
+                }
+
+                let end = last_round_offset(num_keccak_f - 1);
+                let mut states = vec![state_cells(&config.keccak_config, &assigned_rows, 0)?];
+                if !self.is_last {
+                    states.push(state_cells(&config.keccak_config, &assigned_rows, end)?);
+                }
+
+                // The commitments hash the states in a second run of keccak rows after the chunk
+                let preimages = self.committed_states().iter().map(state_bytes).collect::<Vec<_>>();
+                let preimages = preimages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                let zero_state = [[F::ZERO; 5]; 5];
+                let witness = multi_keccak_bytes(&preimages, Some(NUM_COMMITMENT_KECCAK_F), params)?
+                    .chain(continuation_end_rows(&zero_state, params).into_iter().map(Ok));
+                let commitment_rows =
+                    config.keccak_config.assign_from(&mut region, assigned_rows.len(), witness)?;
+                let commitments = pin_hashes(
+                    &mut region,
+                    &config.keccak_config,
+                    &commitment_rows,
+                    &vec![STATE_BYTES; states.len()],
+                )?;
+                for (state, commitment) in states.iter().zip(commitments.iter()) {
+                    for (word_idx, lane) in state.iter().enumerate() {
+                        let word = config
+                            .keccak_config
+                            .absorbed_word_cell(
+                                &commitment_rows,
+                                commitment.first_keccak_f,
+                                word_idx,
+                            )
+                            .ok_or_else(|| {
+                                log::error!("Committed state word {} is not assigned", word_idx);
+                                Error::Synthesis
+                            })?;
+                        region.constrain_equal(lane.cell(), word.cell())?;
+                    }
+                }
+
+                public_cells = vec![commitments[0].hash_lo.clone(), commitments[0].hash_hi.clone()];
+                if self.is_last {
+                    public_cells.push(assigned_rows[end].hash_lo.clone());
+                    public_cells.push(assigned_rows[end].hash_hi.clone());
+                } else {
+                    public_cells.push(commitments[1].hash_lo.clone());
+                    public_cells.push(commitments[1].hash_hi.clone());
+                }
+                Ok(())
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn state_cells<'v, F: Field>(
+    config: &KeccakCircuitConfig<F>,
+    assigned_rows: &[KeccakAssignedRow<'v, F>],
+    round_offset: usize,
+) -> Result<Vec<KeccakAssignedValue<'v, F>>, Error> {
+    config.state_cells(assigned_rows, round_offset).ok_or_else(|| {
+        log::error!("Keccak state at row {} is not assigned", round_offset);
+        Error::Synthesis
+    })
+}
+
+impl<F: Field> KeccakChunkCircuit<F> {
+    /// Creates a chunk that absorbs `input` starting from `start_state`.
+    /// A chunk that is not the last one has to fill the capacity of `num_rows` rows with whole blocks.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if a chunk that is not the last one is empty or
+    /// does not absorb whole blocks of `RATE` bytes.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        start_state: KeccakState,
+        input: Vec<u8>,
+        is_last: bool,
+    ) -> Result<Self, Keccak256Error> {
+        if !is_last && (input.is_empty() || input.len() % RATE != 0) {
+            return Err(Keccak256Error::InputParsing {
+                name: "input".to_string(),
+                reason: format!(
+                    "{} bytes are not whole blocks of a chunk that does not finish the hash",
+                    input.len()
+                ),
+            });
+        }
+        Ok(Self::from_blocks(config, num_rows, start_state, input, is_last))
+    }
+
+    /// Creates a chunk without checking its input, [`Self::split`] only cuts whole blocks.
+    fn from_blocks(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        start_state: KeccakState,
+        input: Vec<u8>,
+        is_last: bool,
+    ) -> Self {
+        KeccakChunkCircuit {
+            config,
+            num_rows,
+            start_state,
+            input,
+            is_last,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Splits the hash of `input` into the chunks of a continuation proof, each using `num_rows` rows.
+    /// The first chunk starts from the all-zero state.
+    pub fn split(config: KeccakConfigParams, num_rows: usize, input: &[u8]) -> Vec<Self> {
+        let capacity = get_chunk_capacity(num_rows, config.rows_per_round);
+        let chunk_len = capacity * RATE;
+        let mut chunks = Vec::new();
+        let mut state = KeccakState::default();
+        let mut offset = 0;
+        // A chunk that does not finish the hash is full, the padding always needs a block of its own
+        while chunk_len > 0 && input.len() - offset >= chunk_len {
+            let chunk = Self::from_blocks(
+                config,
+                num_rows,
+                state,
+                input[offset..offset + chunk_len].to_vec(),
+                false,
+            );
+            state = chunk.end_state();
+            chunks.push(chunk);
+            offset += chunk_len;
+        }
+        chunks.push(Self::from_blocks(config, num_rows, state, input[offset..].to_vec(), true));
+        chunks
+    }
+
+    /// Number of keccak_f's of the hash in this chunk
+    pub fn num_keccak_f(&self) -> usize {
+        if self.is_last {
+            get_num_keccak_f(self.input.len())
+        } else {
+            self.input.len() / RATE
+        }
+    }
+
+    /// The sponge state after the last keccak_f of this chunk
+    pub fn end_state(&self) -> KeccakState {
+        let mut state = self.start_state;
+        let input = if self.is_last {
+            pad_keccak_input(&self.input)
+        } else {
+            self.input.clone()
+        };
+        for block in input.chunks(RATE) {
+            absorb_block(&mut state, block);
+            keccak_f1600(&mut state);
+        }
+        state
+    }
+
+    /// The states committed to by the chunk: the start state, and the end state unless this is the
+    /// last chunk
+    fn committed_states(&self) -> Vec<KeccakState> {
+        if self.is_last {
+            vec![self.start_state]
+        } else {
+            vec![self.start_state, self.end_state()]
+        }
+    }
+
+    /// The commitment to the start state, see [`state_commitment`]
+    pub fn start_commitment(&self) -> H256 {
+        state_commitment(&self.start_state)
+    }
+
+    /// The commitment to the end state, see [`state_commitment`]. The next chunk starts from it.
+    pub fn end_commitment(&self) -> H256 {
+        state_commitment(&self.end_state())
+    }
+
+    /// The keccak digest, only meaningful for the last chunk
+    pub fn digest(&self) -> [u8; 32] {
+        let state = self.end_state();
+        let mut digest = [0u8; 32];
+        for (bytes, lane) in digest.chunks_mut(8).zip(state.iter().take(NUM_WORDS_TO_SQUEEZE)) {
+            bytes.copy_from_slice(&lane[0].to_le_bytes());
+        }
+        digest
+    }
+
+    /// The public inputs of the chunk, see [`KeccakChunkCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let end = if self.is_last {
+            H256::from(self.digest())
+        } else {
+            self.end_commitment()
+        };
+        [self.start_commitment(), end]
+            .into_iter()
+            .flat_map(|word| {
+                let word = Word::<F>::from(word);
+                [word.lo(), word.hi()]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::CONFIG;
+    use crate::util::eth_types::H256;
+    use crate::util::word::Word;
+    use crate::vanilla::param::{NUM_ROUNDS, NUM_WORDS_TO_ABSORB, RATE};
+    use crate::Keccak256Error;
+
+    use super::{state_commitment, KeccakChunkCircuit, KeccakState, NUM_COMMITMENT_KECCAK_F};
+
+    // Two keccak_f's per chunk, followed by the keccak_f's of the state commitments
+    const NUM_ROWS: usize =
+        (2 * (1 + NUM_WORDS_TO_ABSORB) + (2 + NUM_COMMITMENT_KECCAK_F) * (NUM_ROUNDS + 1)) * 5;
+
+    fn word_instance(word: H256) -> Vec<Fr> {
+        let word = Word::<Fr>::from(word);
+        vec![word.lo(), word.hi()]
+    }
+
+    #[test]
+    fn test_native_digest_matches_keccak256() {
+        for len in [0, 1, 135, 136, 300] {
+            let input = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let chunks = KeccakChunkCircuit::<Fr>::split(CONFIG, NUM_ROWS, &input);
+            assert_eq!(chunks.len(), len / (2 * 136) + 1);
+            assert_eq!(
+                chunks.last().unwrap().digest()[..],
+                Keccak256::digest(&input)[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_chunked_hash() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // Five keccak_f's: two full chunks and a last chunk with a single block
+        let input = (0..600).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let chunks = KeccakChunkCircuit::<Fr>::split(CONFIG, NUM_ROWS, &input);
+        assert_eq!(chunks.len(), 3);
+
+        let mut prev_end = word_instance(state_commitment(&KeccakState::default()));
+        for chunk in chunks.iter() {
+            let instance = chunk.instance();
+            assert_eq!(instance[..2], prev_end[..]);
+            prev_end = instance[2..].to_vec();
+
+            let prover = MockProver::<Fr>::run(CONFIG.k, chunk, vec![instance]).unwrap();
+            prover.assert_satisfied();
+        }
+
+        let digest = H256::from_slice(&Keccak256::digest(&input));
+        assert_eq!(prev_end, word_instance(digest));
+    }
+
+    #[test]
+    fn test_chunk_rejects_wrong_start_state() {
+        let input = (0..600).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+        let chunks = KeccakChunkCircuit::<Fr>::split(CONFIG, NUM_ROWS, &input);
+
+        let mut instance = chunks[1].instance();
+        instance[1] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &chunks[1], vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // The commitment of another start state
+        let mut instance = chunks[1].instance();
+        instance[..2].copy_from_slice(&chunks[2].instance()[..2]);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &chunks[1], vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_new_rejects_empty_chunk() {
+        let state = KeccakState::default();
+        let result = KeccakChunkCircuit::<Fr>::new(CONFIG, NUM_ROWS, state, vec![], false);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+        // The last chunk pads the empty input to a block
+        assert!(KeccakChunkCircuit::<Fr>::new(CONFIG, NUM_ROWS, state, vec![], true).is_ok());
+    }
+
+    #[test]
+    fn test_new_rejects_partial_block() {
+        let state = KeccakState::default();
+        for len in [1, RATE - 1, RATE + 1] {
+            let result =
+                KeccakChunkCircuit::<Fr>::new(CONFIG, NUM_ROWS, state, vec![7; len], false);
+            assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+        }
+        let chunk =
+            KeccakChunkCircuit::<Fr>::new(CONFIG, NUM_ROWS, state, vec![7; 2 * RATE], false);
+        assert_eq!(chunk.unwrap().num_keccak_f(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::vanilla::KeccakConfigParams;
+
+/// Config of [`KeccakDisclosureCircuit`]
+#[derive(Clone, Debug)]
+pub struct DisclosureConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the keccak digest of a private input while disclosing selected byte ranges of it.
+///
+/// The public instance is `[hash_lo, hash_hi, length]` followed by the disclosed bytes, one byte per
+/// instance cell, range after range. The ranges are byte offsets into the input and do not have to
+/// be aligned to the packed input words, overlapping ranges disclose their common bytes twice.
+/// The length is public so the disclosed offsets are meaningful, all other input bytes stay private.
+///
+/// Every range has to lie within the input, otherwise synthesis fails.
+#[derive(Default, Clone, Debug)]
+pub struct KeccakDisclosureCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    input: Vec<u8>,
+    ranges: Vec<Range<usize>>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakDisclosureCircuit<F> {
+    type Config = DisclosureConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            input: vec![0; self.input.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, NUM_BYTES_PER_WORD);
+        DisclosureConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        let params = keccak_config.parameters;
+        if let Some(range) = self.ranges.iter().find(|range| range.end > self.input.len()) {
+            log::error!(
+                "Disclosed range {:?} is out of the input of {} bytes",
+                range,
+                self.input.len()
+            );
+            return Err(Error::Synthesis);
+        }
+
+        keccak_config.load_aux_tables(&mut layouter, params.k)?;
+        // The input is the only hash, pinned to its length and keccak_f's
+        let (assigned_rows, hashed_inputs) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &[self.input.as_slice()])?;
+        let first_word = keccak_config
+            .input_word_row(&assigned_rows, 0, 0)
+            .ok_or(Error::Synthesis)?;
+        let public_cells = [
+            hashed_inputs[0].hash_lo.clone(),
+            hashed_inputs[0].hash_hi.clone(),
+            first_word.bytes_left.clone(),
+        ];
+
+        let mut disclosed_cells = vec![];
+        layouter.assign_region(
+            || "disclosed bytes",
+            |mut region| {
+                disclosed_cells.clear();
+                // Every input word holding a disclosed byte is decomposed once
+                let mut words = BTreeMap::new();
+                for byte_idx in self.ranges.iter().cloned().flatten() {
+                    let word_idx = byte_idx / NUM_BYTES_PER_WORD;
+                    if !words.contains_key(&word_idx) {
+                        let word_row = keccak_config
+                            .input_word_row(&assigned_rows, 0, word_idx)
+                            .ok_or(Error::Synthesis)?;
+                        let offset = words.len();
+                        let bytes =
+                            config.bytes.assign_word(&mut region, offset, &word_row.word_value)?;
+                        words.insert(word_idx, bytes);
+                    }
+                    disclosed_cells.push(words[&word_idx][byte_idx % NUM_BYTES_PER_WORD].clone());
+                }
+                Ok(())
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().chain(disclosed_cells.iter()).enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> KeccakDisclosureCircuit<F> {
+    /// Creates a circuit hashing `input` in `num_rows` rows that discloses the bytes in `ranges`.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        input: Vec<u8>,
+        ranges: Vec<Range<usize>>,
+    ) -> Self {
+        KeccakDisclosureCircuit {
+            config,
+            num_rows,
+            input,
+            ranges,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The public inputs of the circuit, see [`KeccakDisclosureCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let digest = Keccak256::digest(&self.input);
+        let mut instance = vec![
+            F::from_u128(u128::from_be_bytes(digest[16..].try_into().unwrap())),
+            F::from_u128(u128::from_be_bytes(digest[..16].try_into().unwrap())),
+            F::from(self.input.len() as u64),
+        ];
+        for range in self.ranges.iter() {
+            let bytes = self.input.get(range.clone()).unwrap_or_default();
+            instance.extend(bytes.iter().map(|byte| F::from(*byte as u64)));
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+
+    use super::KeccakDisclosureCircuit;
+
+    fn circuit(ranges: Vec<std::ops::Range<usize>>) -> KeccakDisclosureCircuit<Fr> {
+        let input = (0..300).map(|i| (i * 13 + 7) as u8).collect::<Vec<_>>();
+        KeccakDisclosureCircuit::new(CONFIG, NUM_ROWS, input, ranges)
+    }
+
+    #[test]
+    fn test_disclose_unaligned_ranges() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // Ranges inside a word, across words, across blocks, overlapping, and the last input byte
+        let circuit = circuit(vec![3..5, 30..70, 130..140, 60..66, 299..300]);
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 3 + 2 + 40 + 10 + 6 + 1);
+        assert_eq!(instance[2], Fr::from(300));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_disclosure_rejects_wrong_bytes() {
+        let circuit = circuit(vec![32..64]);
+        let mut instance = circuit.instance();
+        instance[3 + 5] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+
+        // A wrong length breaks the disclosed offsets
+        let mut instance = circuit.instance();
+        instance[2] = Fr::from(299);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_disclosure_out_of_input_fails() {
+        let circuit = circuit(vec![290..301]);
+        let result = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]);
+        assert!(result.is_err());
+    }
+}
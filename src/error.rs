@@ -0,0 +1,50 @@
+use thiserror::Error;
+
+/// Errors returned by the proving and verification API.
+#[derive(Debug, Error)]
+pub enum Keccak256Error {
+    /// A circuit input could not be parsed.
+    #[error("failed to parse circuit input `{name}`: {reason}")]
+    InputParsing { name: String, reason: String },
+    /// The inputs need more keccak_f permutations than the circuit can fit.
+    #[error("inputs need {required} keccak_f permutations but the circuit only fits {capacity}")]
+    CapacityExceeded { required: usize, capacity: usize },
+    /// Reading or writing a key or SRS failed.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The SRS, the keys and the circuit configuration do not belong together.
+    #[error("parameter mismatch: {0}")]
+    ParameterMismatch(String),
+    /// halo2 failed to synthesize the circuit or to create the proof.
+    #[error("failed to generate the proof: {0}")]
+    ProofGeneration(#[source] halo2_proofs::plonk::Error),
+    /// The proof could be decoded but does not verify against the public inputs.
+    #[error("invalid proof: {0}")]
+    InvalidProof(#[source] halo2_proofs::plonk::Error),
+    /// The proof bytes could not be decoded.
+    #[error("malformed proof: {0}")]
+    MalformedProof(String),
+    /// The proof ended before the verifier read everything it expected.
+    #[error("truncated proof: the transcript ended early")]
+    TruncatedProof,
+    /// The public inputs could not be decoded, are not canonical field elements
+    /// or do not fit the circuit's instance column.
+    #[error("malformed public inputs: {0}")]
+    MalformedPublicInputs(String),
+    /// The public inputs could not be serialised.
+    #[error("failed to serialise the public inputs: {0}")]
+    Serialisation(String),
+}
+
+impl Keccak256Error {
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Keccak256Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use ethers_core::types::{Address, H256, U256};
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::{Field, ToScalar};
+use crate::util::expression::not;
+use crate::util::word::Word;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Size of a head or tail word of `abi.encode`
+const ABI_WORD: usize = 32;
+
+/// A value of an ABI tuple
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AbiValue {
+    /// `uint256`
+    Uint(U256),
+    Address(Address),
+    Bool(bool),
+    /// `bytesN`, with 1 to 32 bytes
+    FixedBytes(Vec<u8>),
+    /// Dynamic `bytes`
+    Bytes(Vec<u8>),
+    String(String),
+}
+
+impl AbiValue {
+    /// The bytes of the value without padding, big-endian for numbers
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            AbiValue::Uint(value) => be_bytes(*value),
+            AbiValue::Address(address) => address.as_bytes().to_vec(),
+            AbiValue::Bool(value) => vec![*value as u8],
+            AbiValue::FixedBytes(bytes) | AbiValue::Bytes(bytes) => bytes.clone(),
+            AbiValue::String(string) => string.as_bytes().to_vec(),
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        matches!(self, AbiValue::Bytes(_) | AbiValue::String(_))
+    }
+
+    /// Whether the value is padded on the right in its word, like `bytesN` and the tails of
+    /// dynamic values, instead of on the left
+    fn is_left_aligned(&self) -> bool {
+        matches!(
+            self,
+            AbiValue::FixedBytes(_) | AbiValue::Bytes(_) | AbiValue::String(_)
+        )
+    }
+}
+
+/// The layout of an ABI encoded tuple
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AbiEncoding {
+    /// `abi.encode`: 32 byte heads, dynamic values as offsets to their length prefixed tails
+    #[default]
+    Standard,
+    /// `abi.encodePacked`: the values back to back without padding
+    Packed,
+}
+
+/// An ABI encoded tuple and the positions of its values
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AbiLayout {
+    pub bytes: Vec<u8>,
+    /// Byte range of every value in `bytes`, everything else is padding, offsets or lengths
+    pub values: Vec<Range<usize>>,
+}
+
+impl AbiEncoding {
+    /// Encodes the tuple `values`, fails for `bytesN` values of more than 32 or no bytes.
+    pub fn encode(&self, values: &[AbiValue]) -> Result<AbiLayout, Keccak256Error> {
+        if let Some(value) = values.iter().find(|value| {
+            matches!(value, AbiValue::FixedBytes(bytes) if bytes.is_empty() || bytes.len() > 32)
+        }) {
+            return Err(Keccak256Error::InputParsing {
+                name: "abi".to_string(),
+                reason: format!("{:?} is not a bytesN value", value),
+            });
+        }
+
+        let mut layout = AbiLayout::default();
+        match self {
+            AbiEncoding::Packed => {
+                for value in values {
+                    let start = layout.bytes.len();
+                    layout.bytes.extend(value.bytes());
+                    layout.values.push(start..layout.bytes.len());
+                }
+            }
+            AbiEncoding::Standard => {
+                let mut tails = vec![];
+                let mut tail_values = vec![];
+                let heads_len = values.len() * ABI_WORD;
+                for value in values {
+                    let bytes = value.bytes();
+                    if value.is_dynamic() {
+                        let offset = heads_len + tails.len();
+                        layout.bytes.extend(be_bytes(U256::from(offset)));
+                        tails.extend(be_bytes(U256::from(bytes.len())));
+                        tail_values.push(tails.len()..tails.len() + bytes.len());
+                        tails.extend(pad_word(&bytes, true));
+                        // Placeholder, shifted behind the heads below
+                        layout.values.push(0..0);
+                    } else {
+                        let start = layout.bytes.len();
+                        let padding = ABI_WORD - bytes.len();
+                        let value_start = if value.is_left_aligned() {
+                            start
+                        } else {
+                            start + padding
+                        };
+                        layout
+                            .bytes
+                            .extend(pad_word(&bytes, value.is_left_aligned()));
+                        layout.values.push(value_start..value_start + bytes.len());
+                    }
+                }
+                let mut tail_values = tail_values.into_iter();
+                for (value, range) in values.iter().zip(layout.values.iter_mut()) {
+                    if value.is_dynamic() {
+                        let tail = tail_values.next().expect("a tail per dynamic value");
+                        *range = heads_len + tail.start..heads_len + tail.end;
+                    }
+                }
+                layout.bytes.extend(tails);
+            }
+        }
+        Ok(layout)
+    }
+}
+
+/// The 32 big-endian bytes of `value`
+fn be_bytes(value: U256) -> Vec<u8> {
+    let mut bytes = vec![0; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+}
+
+/// Pads `bytes` with zeros to a multiple of 32 bytes, on the right if `left_aligned`
+fn pad_word(bytes: &[u8], left_aligned: bool) -> Vec<u8> {
+    let padding = vec![0; (ABI_WORD - bytes.len() % ABI_WORD) % ABI_WORD];
+    match left_aligned {
+        true => [bytes, &padding[..]].concat(),
+        false => [&padding[..], bytes].concat(),
+    }
+}
+
+/// Config of [`AbiCircuit`]
+#[derive(Clone, Debug)]
+pub struct AbiConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    /// Constrains `bool_byte` to 0 or 1
+    pub q_bool: Column<Fixed>,
+    pub bool_byte: Column<Advice>,
+}
+
+impl<F: Field> AbiConfig<F> {
+    /// Constrains the byte of a bool to 0 or 1 at `offset`
+    fn assign_bool<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        byte: &Halo2AssignedCell<'v, F>,
+    ) -> Result<(), Error> {
+        raw_assign_fixed(region, self.q_bool, offset, F::ONE)?;
+        let byte_value = byte.value().map(|byte| byte.evaluate());
+        let copied = raw_assign_advice(region, self.bool_byte, offset, byte_value)?;
+        region.constrain_equal(byte.cell(), copied.cell())
+    }
+}
+
+/// Circuit proving the keccak digest of an ABI encoded tuple while making chosen values public.
+///
+/// The encoded tuple is hashed by the keccak circuit. Every byte outside the values, the padding,
+/// offsets and lengths, is constrained to its constant, so the values are read from their
+/// positions in the encoding, and the byte of every bool is constrained to 0 or 1. The public
+/// instance is the digest `[lo, hi]` followed by the public values in the order of the tuple:
+/// `uint256` and `bytesN` values as the `[lo, hi]` halves of their big-endian bytes, an address as
+/// the scalar of [`ToScalar`] for [`Address`], a bool as 0 or 1 and `bytes` and `string` values
+/// with one byte per cell.
+///
+/// The types and the lengths of the dynamic values are part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct AbiCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    values: Vec<AbiValue>,
+    layout: AbiLayout,
+    /// Indices of the public values
+    public: BTreeSet<usize>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for AbiCircuit<F> {
+    type Config = AbiConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        // The layout outside the values is constant
+        let mut bytes = self.layout.bytes.clone();
+        for range in self.layout.values.iter() {
+            bytes[range.clone()].fill(0);
+        }
+        Self {
+            layout: AbiLayout {
+                bytes,
+                values: self.layout.values.clone(),
+            },
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Wide enough to compose an address
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, Address::len_bytes());
+        let q_bool = meta.fixed_column();
+        let bool_byte = meta.advice_column();
+        meta.enable_equality(bool_byte);
+        meta.create_gate("abi bool", |meta| {
+            let q_bool = meta.query_fixed(q_bool, Rotation::cur());
+            let bool_byte = meta.query_advice(bool_byte, Rotation::cur());
+            vec![q_bool * bool_byte.clone() * not::expr(bool_byte)]
+        });
+        AbiConfig {
+            keccak,
+            bytes,
+            q_bool,
+            bool_byte,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[self.layout.bytes.as_slice()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let public_cells = layouter.assign_region(
+            || "abi values",
+            |mut region| {
+                let (cells, mut offset) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    0,
+                    keccak_config,
+                    &assigned_rows,
+                    hash.first_keccak_f,
+                    0..self.layout.bytes.len(),
+                )?;
+                let mut is_value = vec![false; cells.len()];
+                for range in self.layout.values.iter() {
+                    is_value[range.clone()].fill(true);
+                }
+                for (idx, cell) in cells.iter().enumerate() {
+                    if !is_value[idx] {
+                        let constant = F::from(self.layout.bytes[idx] as u64);
+                        region.constrain_constant(cell.cell(), constant)?;
+                    }
+                }
+                // Every bool, public or not, is 0 or 1 instead of any byte
+                for (value, range) in self.values.iter().zip(self.layout.values.iter()) {
+                    if let AbiValue::Bool(_) = value {
+                        config.assign_bool(&mut region, offset, &cells[range.start])?;
+                        offset += 1;
+                    }
+                }
+
+                let mut public_cells = vec![hash.hash_lo.clone(), hash.hash_hi.clone()];
+                for &idx in self.public.iter() {
+                    let bytes = &cells[self.layout.values[idx].clone()];
+                    match &self.values[idx] {
+                        AbiValue::Uint(_) | AbiValue::FixedBytes(_) => {
+                            // The `[lo, hi]` halves, split at the 16 low bytes
+                            let split = bytes.len().saturating_sub(16);
+                            for half in [&bytes[split..], &bytes[..split]] {
+                                public_cells.push(config.bytes.assign_from_bytes(
+                                    &mut region,
+                                    offset,
+                                    half,
+                                )?);
+                                offset += 1;
+                            }
+                        }
+                        AbiValue::Address(_) | AbiValue::Bool(_) => {
+                            public_cells.push(config.bytes.assign_from_bytes(
+                                &mut region,
+                                offset,
+                                bytes,
+                            )?);
+                            offset += 1;
+                        }
+                        AbiValue::Bytes(_) | AbiValue::String(_) => {
+                            public_cells.extend(bytes.iter().cloned());
+                        }
+                    }
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> AbiCircuit<F> {
+    /// Creates a circuit hashing the tuple `values` with `encoding` in `num_rows` rows, all values
+    /// are private.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        encoding: AbiEncoding,
+        values: Vec<AbiValue>,
+    ) -> Result<Self, Keccak256Error> {
+        let layout = encoding.encode(&values)?;
+        Ok(AbiCircuit {
+            config,
+            num_rows,
+            values,
+            layout,
+            public: BTreeSet::new(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Makes the values at `indices` of the tuple public.
+    pub fn with_public_values(mut self, indices: &[usize]) -> Result<Self, Keccak256Error> {
+        if let Some(idx) = indices.iter().find(|idx| **idx >= self.values.len()) {
+            return Err(Keccak256Error::InputParsing {
+                name: "abi".to_string(),
+                reason: format!("the tuple has no value {}", idx),
+            });
+        }
+        self.public.extend(indices.iter().copied());
+        Ok(self)
+    }
+
+    /// The encoded tuple
+    pub fn encoded(&self) -> &[u8] {
+        &self.layout.bytes
+    }
+
+    /// The digest of the encoded tuple
+    pub fn digest(&self) -> H256 {
+        H256::from_slice(&Keccak256::digest(&self.layout.bytes))
+    }
+
+    /// The public inputs of the circuit, see [`AbiCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let digest = Word::<F>::from(self.digest());
+        let mut instance = vec![digest.lo(), digest.hi()];
+        for &idx in self.public.iter() {
+            let value = &self.values[idx];
+            match value {
+                AbiValue::Uint(value) => {
+                    let value = Word::<F>::from(*value);
+                    instance.extend([value.lo(), value.hi()]);
+                }
+                AbiValue::FixedBytes(bytes) => {
+                    let value = Word::<F>::from(U256::from_big_endian(bytes));
+                    instance.extend([value.lo(), value.hi()]);
+                }
+                AbiValue::Address(address) => instance.push(
+                    address
+                        .to_scalar()
+                        .expect("an address is smaller than the field"),
+                ),
+                AbiValue::Bool(value) => instance.push(F::from(*value)),
+                AbiValue::Bytes(_) | AbiValue::String(_) => {
+                    instance.extend(value.bytes().iter().map(|byte| F::from(*byte as u64)))
+                }
+            }
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::abi::{encode, encode_packed, Token};
+    use ethers_core::types::{Address, U256};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{AbiCircuit, AbiEncoding, AbiValue};
+
+    fn values() -> Vec<AbiValue> {
+        vec![
+            AbiValue::Uint(U256::exp10(18)),
+            AbiValue::Address(Address::repeat_byte(0x35)),
+            AbiValue::FixedBytes(Keccak256::digest(b"slot").to_vec()),
+            AbiValue::Bytes(b"dynamic bytes longer than a single abi word".to_vec()),
+            AbiValue::Bool(true),
+            AbiValue::FixedBytes(vec![0x12, 0x34]),
+            AbiValue::String("abi".to_string()),
+        ]
+    }
+
+    fn tokens() -> Vec<Token> {
+        values()
+            .into_iter()
+            .map(|value| match value {
+                AbiValue::Uint(value) => Token::Uint(value),
+                AbiValue::Address(address) => Token::Address(address),
+                AbiValue::Bool(value) => Token::Bool(value),
+                AbiValue::FixedBytes(bytes) => Token::FixedBytes(bytes),
+                AbiValue::Bytes(bytes) => Token::Bytes(bytes),
+                AbiValue::String(string) => Token::String(string),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_matches_ethers() {
+        let layout = AbiEncoding::Standard.encode(&values()).unwrap();
+        assert_eq!(layout.bytes, encode(&tokens()));
+        assert_eq!(layout.values[3], 7 * 32 + 32..7 * 32 + 32 + 43);
+
+        let layout = AbiEncoding::Packed.encode(&values()).unwrap();
+        assert_eq!(layout.bytes, encode_packed(&tokens()).unwrap());
+        assert_eq!(layout.values[1], 32..52);
+
+        let result = AbiEncoding::Standard.encode(&[AbiValue::FixedBytes(vec![0; 33])]);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_abi_encode_public_values() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        for encoding in [AbiEncoding::Standard, AbiEncoding::Packed] {
+            let circuit = AbiCircuit::<Fr>::new(CONFIG, NUM_ROWS, encoding, values())
+                .unwrap()
+                .with_public_values(&[0, 1, 5, 6])
+                .unwrap();
+            let instance = circuit.instance();
+            // The digest, the uint256 and bytes2 halves, the address and the string bytes
+            assert_eq!(instance.len(), 2 + 2 + 1 + 2 + 3);
+            assert_eq!(instance[5], Fr::from(0x1234));
+
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+            prover.assert_satisfied();
+
+            let mut wrong = instance;
+            wrong[4] += Fr::from(1);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_bool_is_not_any_byte() {
+        for encoding in [AbiEncoding::Standard, AbiEncoding::Packed] {
+            let mut circuit = AbiCircuit::<Fr>::new(CONFIG, NUM_ROWS, encoding, values())
+                .unwrap()
+                .with_public_values(&[4])
+                .unwrap();
+            // Encode the bool as 2, consistently in the digest and the public value
+            let position = circuit.layout.values[4].start;
+            circuit.layout.bytes[position] = 2;
+            let mut instance = circuit.instance();
+            instance[2] = Fr::from(2);
+
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_public_index() {
+        let circuit =
+            AbiCircuit::<Fr>::new(CONFIG, NUM_ROWS, AbiEncoding::Packed, values()).unwrap();
+        assert!(circuit.with_public_values(&[7]).is_err());
+    }
+}
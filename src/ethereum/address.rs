@@ -0,0 +1,203 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::{Address, Field, ToScalar};
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::KeccakConfigParams;
+
+/// Length of an uncompressed secp256k1 public key without the `0x04` prefix
+pub const PUBLIC_KEY_LENGTH: usize = 64;
+
+/// Computes `address = keccak256(pubkey_x || pubkey_y)[12..]` natively.
+pub fn address_from_public_key(public_key: &[u8; PUBLIC_KEY_LENGTH]) -> Address {
+    Address::from_slice(&Keccak256::digest(public_key)[12..])
+}
+
+/// Derives the address from the digest `[hash_lo, hash_hi]` of a public key.
+///
+/// The digest is decomposed by `bytes`, a decomposition into at least 20 bytes, from `offset` on,
+/// and its last 20 bytes are composed into the address, as the scalar [`ToScalar`] gives for an
+/// [`Address`]. The circuit has to enable a constant column. Returns the address cell and the next
+/// free offset.
+pub fn assign_address<'v, F: Field>(
+    region: &mut Region<F>,
+    bytes: &BytesConfig<F>,
+    offset: usize,
+    hash_lo: &Halo2AssignedCell<'v, F>,
+    hash_hi: &Halo2AssignedCell<'v, F>,
+) -> Result<(Halo2AssignedCell<'v, F>, usize), Error> {
+    let digest = bytes.assign_digest(region, offset, hash_lo, hash_hi)?;
+    let address_bytes = &digest[digest.len() - Address::len_bytes()..];
+    let address = bytes.assign_from_bytes(region, offset + 2, address_bytes)?;
+    Ok((address, offset + 3))
+}
+
+/// Config of [`EthAddressCircuit`]
+#[derive(Clone, Debug)]
+pub struct EthAddressConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit deriving the Ethereum addresses of uncompressed public keys.
+///
+/// Every public key `pubkey_x || pubkey_y` is hashed as its own input of the keccak circuit, the
+/// public instance holds the addresses in the same order, each as the scalar of
+/// [`ToScalar`] for [`Address`]. The public keys stay private.
+#[derive(Default, Clone, Debug)]
+pub struct EthAddressCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    public_keys: Vec<[u8; PUBLIC_KEY_LENGTH]>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for EthAddressCircuit<F> {
+    type Config = EthAddressConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            public_keys: vec![[0; PUBLIC_KEY_LENGTH]; self.public_keys.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Wide enough to compose an address
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, Address::len_bytes());
+        EthAddressConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let public_keys = self.public_keys.iter().map(|key| &key[..]).collect::<Vec<_>>();
+        let (_, digests) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &public_keys)?;
+
+        let addresses = layouter.assign_region(
+            || "addresses",
+            |mut region| {
+                let mut offset = 0;
+                let mut addresses = vec![];
+                for digest in digests.iter() {
+                    let (address, next_offset) = assign_address(
+                        &mut region,
+                        &config.bytes,
+                        offset,
+                        &digest.hash_lo,
+                        &digest.hash_hi,
+                    )?;
+                    addresses.push(address);
+                    offset = next_offset;
+                }
+                Ok(addresses)
+            },
+        )?;
+
+        for (idx, address) in addresses.iter().enumerate() {
+            layouter.constrain_instance(address.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> EthAddressCircuit<F> {
+    /// Creates a circuit deriving the addresses of `public_keys` in `num_rows` rows.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        public_keys: Vec<[u8; PUBLIC_KEY_LENGTH]>,
+    ) -> Self {
+        EthAddressCircuit {
+            config,
+            num_rows,
+            public_keys,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The addresses of the public keys
+    pub fn addresses(&self) -> Vec<Address> {
+        self.public_keys.iter().map(address_from_public_key).collect()
+    }
+
+    /// The public inputs of the circuit, see [`EthAddressCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        self.addresses()
+            .iter()
+            .map(|address| address.to_scalar().expect("an address is smaller than the field"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::k256::ecdsa::SigningKey;
+    use ethers_core::k256::elliptic_curve::sec1::ToEncodedPoint;
+    use ethers_core::k256::PublicKey;
+    use ethers_core::utils::secret_key_to_address;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+
+    use super::{EthAddressCircuit, PUBLIC_KEY_LENGTH};
+
+    fn keys() -> Vec<SigningKey> {
+        (1u8..4)
+            .map(|i| SigningKey::from_bytes(&[i; 32]).unwrap())
+            .collect()
+    }
+
+    fn public_key(key: &SigningKey) -> [u8; PUBLIC_KEY_LENGTH] {
+        let point = PublicKey::from(key.verifying_key()).to_encoded_point(false);
+        // Drop the 0x04 prefix of the uncompressed encoding
+        point.as_bytes()[1..].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_address_matches_ethers() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let keys = keys();
+        let circuit =
+            EthAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, keys.iter().map(public_key).collect());
+        let expected = keys.iter().map(secret_key_to_address).collect::<Vec<_>>();
+        assert_eq!(circuit.addresses(), expected);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_wrong_address_fails() {
+        let keys = keys();
+        let circuit =
+            EthAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, keys.iter().map(public_key).collect());
+        let mut instance = circuit.instance();
+        instance.swap(0, 1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,570 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::{Bloom, H256};
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{
+    Advice, Circuit, Column, ConstraintSystem, Error, Expression, Fixed, TableColumn, VirtualCells,
+};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::{Address, Field};
+use crate::util::expression::not;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::KeccakConfigParams;
+
+/// Number of bits of a logs bloom
+const BLOOM_BITS: usize = 2048;
+
+/// Number of bits of the bloom packed into a public field element
+const BITS_PER_LIMB: usize = 128;
+
+/// Number of bits set for every address and topic
+const NUM_INDICES: usize = 3;
+
+/// The address and topics of a log, the parts of a log that end up in the bloom
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct BloomLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+}
+
+impl BloomLog {
+    /// The items added to the bloom: the address followed by the topics
+    fn items(&self) -> Vec<Vec<u8>> {
+        let mut items = vec![self.address.as_bytes().to_vec()];
+        items.extend(self.topics.iter().map(|topic| topic.as_bytes().to_vec()));
+        items
+    }
+}
+
+/// The 11 bit indices of the bloom bits of `item`, taken from the first three pairs of bytes of
+/// `keccak256(item)`
+fn bloom_indices(item: &[u8]) -> [usize; NUM_INDICES] {
+    let hash = Keccak256::digest(item);
+    [0, 2, 4].map(|idx| (((hash[idx] as usize) << 8) | hash[idx + 1] as usize) % BLOOM_BITS)
+}
+
+/// Whether bit `index` is set, bit 0 is the least significant bit of the last byte
+fn bloom_bit(bloom: &Bloom, index: usize) -> bool {
+    (bloom.as_bytes()[BLOOM_BITS / 8 - 1 - index / 8] >> (index % 8)) & 1 == 1
+}
+
+/// Computes the logs bloom of `logs` natively: the three bits of the address and of every topic
+/// of every log are set.
+pub fn logs_bloom(logs: &[BloomLog]) -> Bloom {
+    let mut bytes = [0u8; BLOOM_BITS / 8];
+    for item in logs.iter().flat_map(|log| log.items()) {
+        for index in bloom_indices(&item) {
+            bytes[BLOOM_BITS / 8 - 1 - index / 8] |= 1 << (index % 8);
+        }
+    }
+    Bloom::from(bytes)
+}
+
+/// Whether all three bits of `item`, an address or a topic, are set in `bloom`. Like any bloom
+/// filter this has false positives.
+pub fn bloom_contains(bloom: &Bloom, item: &[u8]) -> bool {
+    bloom_indices(item)
+        .iter()
+        .all(|index| bloom_bit(bloom, *index))
+}
+
+/// Config of the bloom bits and the bit indices of the items.
+///
+/// The bloom takes 2048 rows, one per bit from bit 0 on, with the fixed `table_index = index + 1`.
+/// Every bit is boolean and accumulated into its limb, `acc = q_continue * acc_prev + weight * bit`
+/// with `weight = 2^(index % 128)`, so the last row of every 128 holds a limb.
+///
+/// An item takes three rows, one per index. Every row copies the two digest bytes of the index,
+/// splits the low three bits off the first one and looks up `(index + 1, item_bit)` in
+/// `(table_index, bit)`. The first row constrains `contained` to the product of the three item
+/// bits. If the bloom is built from the items, every set bit with a nonzero `exact_index` is in
+/// turn looked up in the indices of the items, so no other bit can be set.
+#[derive(Clone, Debug)]
+pub struct BloomConfig<F> {
+    q_bloom: Column<Fixed>,
+    table_index: Column<Fixed>,
+    weight: Column<Fixed>,
+    q_continue: Column<Fixed>,
+    exact_index: Column<Fixed>,
+    bit: Column<Advice>,
+    acc: Column<Advice>,
+    q_index: Column<Fixed>,
+    q_item: Column<Fixed>,
+    hi: Column<Advice>,
+    lo: Column<Advice>,
+    high: Column<Advice>,
+    low: Column<Advice>,
+    item_bit: Column<Advice>,
+    contained: Column<Advice>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> BloomConfig<F> {
+    /// Configures the gadget, `byte_table` has to hold exactly the values `0..256`.
+    pub fn configure(meta: &mut ConstraintSystem<F>, byte_table: TableColumn) -> Self {
+        let q_bloom = meta.fixed_column();
+        let table_index = meta.fixed_column();
+        let weight = meta.fixed_column();
+        let q_continue = meta.fixed_column();
+        let exact_index = meta.fixed_column();
+        let bit = meta.advice_column();
+        let acc = meta.advice_column();
+        let q_index = meta.fixed_column();
+        let q_item = meta.fixed_column();
+        let hi = meta.advice_column();
+        let lo = meta.advice_column();
+        let high = meta.advice_column();
+        let low = meta.advice_column();
+        let item_bit = meta.advice_column();
+        let contained = meta.advice_column();
+        for column in [acc, hi, lo, contained] {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("bloom bits", |meta| {
+            let q_bloom = meta.query_fixed(q_bloom, Rotation::cur());
+            let weight = meta.query_fixed(weight, Rotation::cur());
+            let q_continue = meta.query_fixed(q_continue, Rotation::cur());
+            let bit = meta.query_advice(bit, Rotation::cur());
+            let acc = meta.query_advice(acc, Rotation::cur());
+            let acc_prev = meta.query_advice(acc, Rotation::prev());
+            vec![
+                q_bloom.clone() * bit.clone() * not::expr(bit.clone()),
+                q_bloom * (acc - q_continue * acc_prev - weight * bit),
+            ]
+        });
+        meta.create_gate("bloom item", |meta| {
+            let q_index = meta.query_fixed(q_index, Rotation::cur());
+            let q_item = meta.query_fixed(q_item, Rotation::cur());
+            let hi = meta.query_advice(hi, Rotation::cur());
+            let high = meta.query_advice(high, Rotation::cur());
+            let low = meta.query_advice(low, Rotation::cur());
+            let contained = meta.query_advice(contained, Rotation::cur());
+            let item_bits = [0, 1, 2].map(|idx| meta.query_advice(item_bit, Rotation(idx)));
+            let [bit_0, bit_1, bit_2] = item_bits;
+            vec![
+                q_index * (hi - high * Expression::Constant(F::from(8)) - low),
+                q_item * (contained - bit_0 * bit_1 * bit_2),
+            ]
+        });
+        // The low three bits are in 0..8, the remaining five in 0..32
+        for (column, shift) in [(low, 0), (low, 248), (high, 0), (high, 224)] {
+            meta.lookup("bloom index range", |meta| {
+                let q_index = meta.query_fixed(q_index, Rotation::cur());
+                let value = meta.query_advice(column, Rotation::cur());
+                vec![(
+                    q_index * (value + Expression::Constant(F::from(shift))),
+                    byte_table,
+                )]
+            });
+        }
+        meta.lookup_any("bloom item bit", |meta| {
+            let q_index = meta.query_fixed(q_index, Rotation::cur());
+            let index = Self::index_expr(meta, low, lo);
+            let item_bit = meta.query_advice(item_bit, Rotation::cur());
+            let table_index = meta.query_fixed(table_index, Rotation::cur());
+            let bit = meta.query_advice(bit, Rotation::cur());
+            vec![
+                (q_index.clone() * index, table_index),
+                (q_index * item_bit, bit),
+            ]
+        });
+        meta.lookup_any("bloom exact bit", |meta| {
+            let exact_index = meta.query_fixed(exact_index, Rotation::cur());
+            let bit = meta.query_advice(bit, Rotation::cur());
+            let q_index = meta.query_fixed(q_index, Rotation::cur());
+            let index = Self::index_expr(meta, low, lo);
+            vec![(exact_index * bit, q_index * index)]
+        });
+
+        BloomConfig {
+            q_bloom,
+            table_index,
+            weight,
+            q_continue,
+            exact_index,
+            bit,
+            acc,
+            q_index,
+            q_item,
+            hi,
+            lo,
+            high,
+            low,
+            item_bit,
+            contained,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The bit index of an index row plus one, `low * 256 + lo + 1`
+    fn index_expr(
+        meta: &mut VirtualCells<'_, F>,
+        low: Column<Advice>,
+        lo: Column<Advice>,
+    ) -> Expression<F> {
+        let low = meta.query_advice(low, Rotation::cur());
+        let lo = meta.query_advice(lo, Rotation::cur());
+        low * Expression::Constant(F::from(256)) + lo + Expression::Constant(F::ONE)
+    }
+
+    /// Assigns the bits of `bloom` in the 2048 rows from `offset` on, returns the cells of the 16
+    /// limbs of 128 bits, lowest bits first. If `exact` every set bit has to be an index of an
+    /// item.
+    pub fn assign_bloom<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bloom: &Bloom,
+        exact: bool,
+    ) -> Result<Vec<Halo2AssignedCell<'v, F>>, Error> {
+        let mut limbs = vec![];
+        let mut acc = F::ZERO;
+        for index in 0..BLOOM_BITS {
+            let row = offset + index;
+            let position = index % BITS_PER_LIMB;
+            let weight = F::from_u128(1 << position);
+            raw_assign_fixed(region, self.q_bloom, row, F::ONE)?;
+            raw_assign_fixed(region, self.table_index, row, F::from(index as u64 + 1))?;
+            raw_assign_fixed(region, self.weight, row, weight)?;
+            raw_assign_fixed(region, self.q_continue, row, F::from(position != 0))?;
+            if exact {
+                raw_assign_fixed(region, self.exact_index, row, F::from(index as u64 + 1))?;
+            }
+
+            let bit = F::from(bloom_bit(bloom, index));
+            acc = if position == 0 { F::ZERO } else { acc } + weight * bit;
+            raw_assign_advice(region, self.bit, row, Value::known(bit))?;
+            let acc_cell = raw_assign_advice(region, self.acc, row, Value::known(acc))?;
+            if position == BITS_PER_LIMB - 1 {
+                limbs.push(acc_cell);
+            }
+        }
+        Ok(limbs)
+    }
+
+    /// Looks up the bits of an item in `bloom` in the three rows from `offset` on. `digest_bytes`
+    /// are the first six big-endian bytes of the digest of the item. Returns the cell that is 1 if
+    /// all three bits are set and 0 otherwise.
+    pub fn assign_item<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bloom: &Bloom,
+        digest_bytes: &[Halo2AssignedCell<'v, F>],
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        if digest_bytes.len() != 2 * NUM_INDICES {
+            log::error!("A bloom item needs {} digest bytes", 2 * NUM_INDICES);
+            return Err(Error::Synthesis);
+        }
+        raw_assign_fixed(region, self.q_item, offset, F::ONE)?;
+        let mut contained = Value::known(F::ONE);
+        for (idx, pair) in digest_bytes.chunks(2).enumerate() {
+            let row = offset + idx;
+            raw_assign_fixed(region, self.q_index, row, F::ONE)?;
+            let mut copy = |column, cell: &Halo2AssignedCell<'v, F>| {
+                let value = cell.value().map(|value| value.evaluate());
+                let copied = raw_assign_advice(region, column, row, value)?;
+                region.constrain_equal(cell.cell(), copied.cell())?;
+                Ok::<_, Error>(value.map(|value| value.to_bytes_le()[0] as usize))
+            };
+            let hi = copy(self.hi, &pair[0])?;
+            let lo = copy(self.lo, &pair[1])?;
+
+            raw_assign_advice(region, self.high, row, hi.map(|hi| F::from(hi as u64 >> 3)))?;
+            raw_assign_advice(region, self.low, row, hi.map(|hi| F::from(hi as u64 & 7)))?;
+            let item_bit = hi
+                .zip(lo)
+                .map(|(hi, lo)| F::from(bloom_bit(bloom, ((hi & 7) << 8) | lo)));
+            raw_assign_advice(region, self.item_bit, row, item_bit)?;
+            contained = contained * item_bit;
+        }
+        raw_assign_advice(region, self.contained, offset, contained)
+    }
+}
+
+/// Config of [`LogsBloomCircuit`]
+#[derive(Clone, Debug)]
+pub struct LogsBloomConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    pub bloom: BloomConfig<F>,
+}
+
+/// Circuit proving the logs bloom of a list of logs, or whether an address or topic is in a logs
+/// bloom.
+///
+/// Every item, the address and the topics of every log, is hashed by the keccak circuit. The high
+/// half of every digest is decomposed into bytes and the 11 bit indices are taken from its first
+/// six bytes by [`BloomConfig`]. The public instance starts with the bloom as 16 limbs of 128 bits,
+/// limb `j` holds the bits `128 * j..128 * (j + 1)` with bit `i` at weight `2^(i % 128)`.
+///
+/// If the bloom is built from logs, every item has all three bits set and no other bit is set. For
+/// a membership query the instance continues with 1 if the item is in the bloom and 0 otherwise,
+/// and the bytes of the item, one byte per cell.
+///
+/// The number of items, their lengths and whether the bloom is built from logs are part of the
+/// circuit.
+#[derive(Default, Clone, Debug)]
+pub struct LogsBloomCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    items: Vec<Vec<u8>>,
+    bloom: Bloom,
+    membership: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for LogsBloomCircuit<F> {
+    type Config = LogsBloomConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            items: self.items.iter().map(|item| vec![0; item.len()]).collect(),
+            bloom: Bloom::zero(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        let byte_table = keccak.keccak_config.byte_table();
+        let bloom = BloomConfig::configure(meta, byte_table);
+        LogsBloomConfig {
+            keccak,
+            bytes,
+            bloom,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self
+            .items
+            .iter()
+            .map(|item| item.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        let public_cells = layouter.assign_region(
+            || "logs bloom",
+            |mut region| {
+                let mut public_cells =
+                    config
+                        .bloom
+                        .assign_bloom(&mut region, 0, &self.bloom, !self.membership)?;
+                for (idx, hash) in hashes.iter().enumerate() {
+                    let hi_bytes = config.bytes.assign_word(&mut region, idx, &hash.hash_hi)?;
+                    let digest_bytes = hi_bytes
+                        .iter()
+                        .rev()
+                        .take(2 * NUM_INDICES)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    let contained = config.bloom.assign_item(
+                        &mut region,
+                        BLOOM_BITS + NUM_INDICES * idx,
+                        &self.bloom,
+                        &digest_bytes,
+                    )?;
+                    if self.membership {
+                        public_cells.push(contained);
+                    } else {
+                        region.constrain_constant(contained.cell(), F::ONE)?;
+                    }
+                }
+                if self.membership {
+                    let hash = hashes.first().ok_or(Error::Synthesis)?;
+                    let (bytes, _) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        hashes.len(),
+                        keccak_config,
+                        &assigned_rows,
+                        hash.first_keccak_f,
+                        0..self.items[0].len(),
+                    )?;
+                    public_cells.extend(bytes);
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> LogsBloomCircuit<F> {
+    /// Creates a circuit proving the logs bloom of `logs` in `num_rows` rows, the logs are private.
+    pub fn from_logs(config: KeccakConfigParams, num_rows: usize, logs: &[BloomLog]) -> Self {
+        LogsBloomCircuit {
+            config,
+            num_rows,
+            items: logs.iter().flat_map(|log| log.items()).collect(),
+            bloom: logs_bloom(logs),
+            membership: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a circuit proving whether `item`, an address or a topic, is in `bloom` in `num_rows`
+    /// rows. The item is public.
+    pub fn membership(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        bloom: Bloom,
+        item: Vec<u8>,
+    ) -> Self {
+        LogsBloomCircuit {
+            config,
+            num_rows,
+            items: vec![item],
+            bloom,
+            membership: true,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The logs bloom
+    pub fn bloom(&self) -> Bloom {
+        self.bloom
+    }
+
+    /// Whether the item of a membership query is in the bloom
+    pub fn contains(&self) -> bool {
+        self.membership && bloom_contains(&self.bloom, &self.items[0])
+    }
+
+    /// The public inputs of the circuit, see [`LogsBloomCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let bytes = self.bloom.as_bytes();
+        let mut instance = (0..BLOOM_BITS / BITS_PER_LIMB)
+            .map(|limb| {
+                let end = bytes.len() - limb * BITS_PER_LIMB / 8;
+                let limb_bytes = bytes[end - BITS_PER_LIMB / 8..end].try_into().unwrap();
+                F::from_u128(u128::from_be_bytes(limb_bytes))
+            })
+            .collect::<Vec<_>>();
+        if self.membership {
+            instance.push(F::from(self.contains()));
+            instance.extend(self.items[0].iter().map(|byte| F::from(*byte as u64)));
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::types::{Bloom, H256};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::util::eth_types::Address;
+
+    use super::{bloom_contains, logs_bloom, BloomLog, LogsBloomCircuit};
+
+    /// An ERC-20 `Transfer` log of USDC
+    fn transfer_log() -> BloomLog {
+        let topic = |hex: &str| H256::from_slice(&hex::decode(hex).unwrap());
+        BloomLog {
+            address: "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48"
+                .parse()
+                .unwrap(),
+            topics: vec![
+                topic("ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"),
+                topic("0000000000000000000000003535353535353535353535353535353535353535"),
+                topic("0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_logs_bloom() {
+        let bloom = logs_bloom(&[transfer_log()]);
+        let expected = "\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000000000040000000000000000000000000\
+            0000000000000000080000080000000000000000000000000000000000040000\
+            0000000000000200000000000000000000000000000000000000201000000000\
+            0000000000000000000000000000000000000000010000000000000000000000\
+            0000000000002000001000000000000000000000000000000000000000000000\
+            0000000200000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000020000000000000000000000000000000";
+        assert_eq!(bloom, Bloom::from_slice(&hex::decode(expected).unwrap()));
+        assert_eq!(logs_bloom(&[]), Bloom::zero());
+    }
+
+    #[test]
+    fn test_logs_bloom_circuit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let circuit = LogsBloomCircuit::<Fr>::from_logs(CONFIG, NUM_ROWS, &[transfer_log()]);
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 16);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A bloom with an extra bit set
+        let mut wrong = instance;
+        wrong[0] += Fr::from(1 << 3);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_bloom_membership() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let log = transfer_log();
+        let bloom = logs_bloom(&[log.clone()]);
+        let absent: Address = "0xef2d6d194084c2de36e0dabfce45d046b37d1106"
+            .parse()
+            .unwrap();
+        assert!(!bloom_contains(&bloom, absent.as_bytes()));
+
+        for (item, contained) in [
+            (log.address.as_bytes().to_vec(), true),
+            (log.topics[0].as_bytes().to_vec(), true),
+            (absent.as_bytes().to_vec(), false),
+        ] {
+            let circuit = LogsBloomCircuit::<Fr>::membership(CONFIG, NUM_ROWS, bloom, item);
+            assert_eq!(circuit.contains(), contained);
+            let instance = circuit.instance();
+            assert_eq!(instance[16], Fr::from(contained));
+
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+            prover.assert_satisfied();
+
+            // Claiming the opposite fails
+            let mut wrong = instance;
+            wrong[16] = Fr::from(!contained);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::{CircuitConfig, NUM_UNUSABLE_ROWS};
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, get_num_keccak_f};
+use crate::vanilla::param::NUM_WORDS_TO_ABSORB;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Largest size of deployed contract code (EIP-170)
+pub const MAX_CODE_SIZE: usize = 24576;
+
+/// Number of code bytes in a chunk that can be made public
+pub const CODE_CHUNK_SIZE: usize = 32;
+
+/// Smallest and largest `k` tried when sizing the circuit for the code
+const MIN_K: u32 = 10;
+const MAX_K: u32 = 28;
+
+/// Config of [`CodeHashCircuit`]
+#[derive(Clone, Debug)]
+pub struct CodeHashConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the code hash `keccak256(code)` of a contract.
+///
+/// The code is hashed by the keccak circuit, its length pinned to the public length. The public
+/// instance is the code hash `[lo, hi]` and the code length, followed by the bytes of every public
+/// chunk, one byte per cell, chunk after chunk. Chunk `i` holds the code bytes
+/// `32 * i..32 * (i + 1)`, the last chunk can be shorter. All other code bytes stay private.
+///
+/// The code length and the public chunks are part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct CodeHashCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    code: Vec<u8>,
+    public_chunks: Vec<usize>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for CodeHashCircuit<F> {
+    type Config = CodeHashConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            code: vec![0; self.code.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        CodeHashConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[self.code.as_slice()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let public_cells = layouter.assign_region(
+            || "code chunks",
+            |mut region| {
+                let first_word = keccak_config
+                    .input_word_row(&assigned_rows, hash.first_keccak_f, 0)
+                    .ok_or(Error::Synthesis)?;
+                let mut public_cells = vec![
+                    hash.hash_lo.clone(),
+                    hash.hash_hi.clone(),
+                    first_word.bytes_left.clone(),
+                ];
+                let chunk_bytes = self
+                    .public_chunks
+                    .iter()
+                    .flat_map(|chunk| self.chunk_range(*chunk));
+                let (bytes, _) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    0,
+                    keccak_config,
+                    &assigned_rows,
+                    hash.first_keccak_f,
+                    chunk_bytes,
+                )?;
+                public_cells.extend(bytes);
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> CodeHashCircuit<F> {
+    /// Creates a circuit proving the code hash of `code` in `num_rows` rows, the code is private.
+    ///
+    /// Fails if the code is longer than [`MAX_CODE_SIZE`] or needs more keccak_f's than fit.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        code: Vec<u8>,
+    ) -> Result<Self, Keccak256Error> {
+        if code.len() > MAX_CODE_SIZE {
+            return Err(Keccak256Error::InputParsing {
+                name: "code".to_string(),
+                reason: format!(
+                    "{} bytes exceed the maximal code size of {} bytes",
+                    code.len(),
+                    MAX_CODE_SIZE
+                ),
+            });
+        }
+        let required = get_num_keccak_f(code.len());
+        let capacity = get_keccak_capacity(num_rows, config.rows_per_round);
+        if required > capacity {
+            return Err(Keccak256Error::CapacityExceeded { required, capacity });
+        }
+        Ok(CodeHashCircuit {
+            config,
+            num_rows,
+            code,
+            public_chunks: vec![],
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates a circuit proving the code hash of `code` with the smallest `k` whose usable rows
+    /// fit the keccak_f's of the code with `rows_per_round` rows per round.
+    pub fn sized(rows_per_round: usize, code: Vec<u8>) -> Result<Self, Keccak256Error> {
+        let required = get_num_keccak_f(code.len());
+        let mut capacity = 0;
+        for k in MIN_K..=MAX_K {
+            let num_rows = (1 << k) - NUM_UNUSABLE_ROWS;
+            // Too few rows for even the dummy round and the absorb lookahead
+            if num_rows / rows_per_round <= 1 + NUM_WORDS_TO_ABSORB {
+                continue;
+            }
+            capacity = get_keccak_capacity(num_rows, rows_per_round);
+            if capacity >= required {
+                let config = KeccakConfigParams { k, rows_per_round };
+                return Self::new(config, num_rows, code);
+            }
+        }
+        Err(Keccak256Error::CapacityExceeded { required, capacity })
+    }
+
+    /// Makes the chunks with the indices in `chunks` public, see [`CodeHashCircuit`]. Fails if a
+    /// chunk is beyond the end of the code.
+    pub fn with_public_chunks(mut self, chunks: Vec<usize>) -> Result<Self, Keccak256Error> {
+        if let Some(chunk) = chunks.iter().find(|chunk| **chunk >= self.num_chunks()) {
+            return Err(Keccak256Error::InputParsing {
+                name: format!("chunk {}", chunk),
+                reason: format!("the code only has {} chunks", self.num_chunks()),
+            });
+        }
+        self.public_chunks = chunks;
+        Ok(self)
+    }
+
+    /// The parameters the circuit was created with
+    pub fn config(&self) -> KeccakConfigParams {
+        self.config
+    }
+
+    /// The number of usable rows of the circuit
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// The number of chunks of the code, the last one can be shorter than [`CODE_CHUNK_SIZE`]
+    pub fn num_chunks(&self) -> usize {
+        self.code.len().div_ceil(CODE_CHUNK_SIZE)
+    }
+
+    /// The byte indices of chunk `chunk` of the code
+    fn chunk_range(&self, chunk: usize) -> std::ops::Range<usize> {
+        let start = chunk * CODE_CHUNK_SIZE;
+        start..(start + CODE_CHUNK_SIZE).min(self.code.len())
+    }
+
+    /// The code hash of the code
+    pub fn code_hash(&self) -> H256 {
+        H256::from_slice(&Keccak256::digest(&self.code))
+    }
+
+    /// The public inputs of the circuit, see [`CodeHashCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let hash = Word::<F>::from(self.code_hash());
+        let mut instance = vec![hash.lo(), hash.hi(), F::from(self.code.len() as u64)];
+        for chunk in self.public_chunks.iter() {
+            let bytes = &self.code[self.chunk_range(*chunk)];
+            instance.extend(bytes.iter().map(|byte| F::from(*byte as u64)));
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use ethers_core::types::{Bytes, H256};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use serde::Deserialize;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{CodeHashCircuit, MAX_CODE_SIZE};
+
+    /// Deployed code of a contract and its code hash
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ContractCode {
+        code: Bytes,
+        code_hash: H256,
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/{}.json", name))
+    }
+
+    fn contract_codes() -> Vec<ContractCode> {
+        let file = std::fs::File::open(fixture("contract_code")).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    #[test]
+    fn test_code_hash() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        for contract in contract_codes() {
+            let circuit = CodeHashCircuit::<Fr>::sized(5, contract.code.to_vec()).unwrap();
+            assert_eq!(circuit.code_hash(), contract.code_hash);
+            assert_eq!(circuit.config().k, 10);
+
+            let prover =
+                MockProver::<Fr>::run(circuit.config().k, &circuit, vec![circuit.instance()])
+                    .unwrap();
+            prover.assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_deposit_contract_code_hash() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // The 6358 byte code of the deposit contract in the Holesky genesis takes 47 keccak_f's
+        let file = std::fs::File::open(fixture("deposit_contract_code")).unwrap();
+        let contract: ContractCode = serde_json::from_reader(file).unwrap();
+        let circuit = CodeHashCircuit::<Fr>::sized(5, contract.code.to_vec())
+            .unwrap()
+            .with_public_chunks(vec![0, 100])
+            .unwrap();
+        assert_eq!(circuit.code_hash(), contract.code_hash);
+        assert_eq!(circuit.config().k, 13);
+
+        let instance = circuit.instance();
+        let prover =
+            MockProver::<Fr>::run(circuit.config().k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut wrong = instance;
+        wrong[0] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(circuit.config().k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_empty_code_hash() {
+        // The account of the proof fixture has no code
+        let file = std::fs::File::open(fixture("eth_get_proof")).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(file).unwrap();
+        let code_hash: H256 = serde_json::from_value(json["codeHash"].clone()).unwrap();
+
+        let circuit = CodeHashCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![]).unwrap();
+        assert_eq!(circuit.code_hash(), code_hash);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_public_chunks() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // A code over several keccak_f's, the chunks are not aligned to the input words
+        let mut code = vec![];
+        for contract in contract_codes() {
+            code.extend_from_slice(&contract.code);
+        }
+        let last_chunk = (code.len() - 1) / 32;
+        let circuit = CodeHashCircuit::<Fr>::new(CONFIG, NUM_ROWS, code.clone())
+            .unwrap()
+            .with_public_chunks(vec![0, 5, last_chunk])
+            .unwrap();
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 3 + 32 + 32 + (code.len() - 1) % 32 + 1);
+        assert_eq!(instance[3 + 32], Fr::from(code[5 * 32] as u64));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut wrong = instance;
+        wrong[3 + 32] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+
+        let result = CodeHashCircuit::<Fr>::new(CONFIG, NUM_ROWS, code)
+            .unwrap()
+            .with_public_chunks(vec![last_chunk + 1]);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_sizing() {
+        // The largest code takes 181 keccak_f's
+        let circuit = CodeHashCircuit::<Fr>::sized(5, vec![0; MAX_CODE_SIZE]).unwrap();
+        assert_eq!(circuit.config().k, 15);
+        let circuit = CodeHashCircuit::<Fr>::sized(28, vec![0; MAX_CODE_SIZE]).unwrap();
+        assert_eq!(circuit.config().k, 17);
+
+        let result = CodeHashCircuit::<Fr>::sized(5, vec![0; MAX_CODE_SIZE + 1]);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+        let result = CodeHashCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![0; 5000]);
+        assert!(matches!(
+            result,
+            Err(Keccak256Error::CapacityExceeded { .. })
+        ));
+    }
+}
@@ -0,0 +1,522 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::ethereum::address::assign_address;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes, link_digest};
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::{Address, Field, ToScalar};
+use crate::util::word::Word;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::KeccakConfigParams;
+
+/// RLP prefix of the 20 byte sender
+const SENDER_PREFIX: u8 = 0x80 + 20;
+
+/// Prefix of the CREATE2 input
+const CREATE2_PREFIX: u8 = 0xff;
+
+/// Length of the CREATE2 input `0xff || sender || salt || keccak256(init_code)`
+const CREATE2_INPUT_LENGTH: usize = 1 + 20 + 32 + 32;
+
+/// A contract creation, by the values its address is derived from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContractCreation {
+    /// `keccak256(rlp([sender, nonce]))[12..]`
+    Create { sender: Address, nonce: u64 },
+    /// `keccak256(0xff || sender || salt || keccak256(init_code))[12..]`
+    Create2 {
+        sender: Address,
+        salt: H256,
+        init_code: Vec<u8>,
+    },
+}
+
+impl ContractCreation {
+    /// The keccak inputs of the derivation, the init code before the CREATE2 input
+    pub fn inputs(&self) -> Vec<Vec<u8>> {
+        match self {
+            ContractCreation::Create { sender, nonce } => {
+                let nonce = encode_nonce(*nonce);
+                let mut input = vec![0xc0 + (21 + nonce.len()) as u8, SENDER_PREFIX];
+                input.extend_from_slice(sender.as_bytes());
+                input.extend(nonce);
+                vec![input]
+            }
+            ContractCreation::Create2 {
+                sender,
+                salt,
+                init_code,
+            } => {
+                let mut input = vec![CREATE2_PREFIX];
+                input.extend_from_slice(sender.as_bytes());
+                input.extend_from_slice(salt.as_bytes());
+                input.extend_from_slice(&Keccak256::digest(init_code));
+                vec![init_code.clone(), input]
+            }
+        }
+    }
+
+    /// The address of the created contract
+    pub fn address(&self) -> Address {
+        let inputs = self.inputs();
+        let input = inputs.last().expect("a derivation has an input");
+        Address::from_slice(&Keccak256::digest(input)[12..])
+    }
+
+    /// The public values of the derivation: the sender, the nonce or the salt `[lo, hi]` and the
+    /// address, each address as the scalar of [`ToScalar`] for [`Address`]
+    pub fn instance<F: Field>(&self) -> Vec<F> {
+        let to_scalar = |address: &Address| -> F {
+            address
+                .to_scalar()
+                .expect("an address is smaller than the field")
+        };
+        match self {
+            ContractCreation::Create { sender, nonce } => {
+                vec![
+                    to_scalar(sender),
+                    F::from(*nonce),
+                    to_scalar(&self.address()),
+                ]
+            }
+            ContractCreation::Create2 { sender, salt, .. } => {
+                let salt = Word::<F>::from(*salt);
+                vec![
+                    to_scalar(sender),
+                    salt.lo(),
+                    salt.hi(),
+                    to_scalar(&self.address()),
+                ]
+            }
+        }
+    }
+}
+
+/// RLP encoding of the nonce: `0x80` for zero, a single byte below `0x80`, otherwise the prefix
+/// `0x80 + len` followed by the big-endian bytes without leading zeros
+fn encode_nonce(nonce: u64) -> Vec<u8> {
+    match nonce {
+        0 => vec![0x80],
+        1..=0x7f => vec![nonce as u8],
+        _ => {
+            let bytes = nonce.to_be_bytes();
+            let bytes = &bytes[nonce.leading_zeros() as usize / 8..];
+            std::iter::once(0x80 + bytes.len() as u8)
+                .chain(bytes.iter().copied())
+                .collect()
+        }
+    }
+}
+
+/// Range checks `(byte index, shift)` of the RLP encoded `nonce` in the CREATE input, `byte +
+/// shift` has to be a byte, so that the encoding is canonical
+fn nonce_ranges<F: Field>(nonce: &[u8]) -> Vec<(usize, F)> {
+    match nonce {
+        // Zero is the constant prefix
+        [0x80] => vec![],
+        // A single byte in `1..0x80`
+        [_] => vec![(22, F::from(0x80)), (22, -F::ONE)],
+        // A single byte after a prefix is at least `0x80`
+        [_, _] => vec![(23, -F::from(0x80))],
+        // No leading zero
+        _ => vec![(23, -F::ONE)],
+    }
+}
+
+/// Config of [`ContractAddressCircuit`]
+#[derive(Clone, Debug)]
+pub struct ContractAddressConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    /// Looks up `nonce_byte + nonce_shift` in the byte table
+    pub q_nonce: Column<Fixed>,
+    pub nonce_shift: Column<Fixed>,
+    pub nonce_byte: Column<Advice>,
+}
+
+impl<F: Field> ContractAddressConfig<F> {
+    /// Constrains `byte + shift` to a byte at `offset`
+    fn assign_nonce_range<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        byte: &Halo2AssignedCell<'v, F>,
+        shift: F,
+    ) -> Result<(), Error> {
+        raw_assign_fixed(region, self.q_nonce, offset, F::ONE)?;
+        raw_assign_fixed(region, self.nonce_shift, offset, shift)?;
+        let byte_value = byte.value().map(|byte| byte.evaluate());
+        let copied = raw_assign_advice(region, self.nonce_byte, offset, byte_value)?;
+        region.constrain_equal(byte.cell(), copied.cell())
+    }
+}
+
+/// Circuit deriving the addresses of contracts created with CREATE or CREATE2.
+///
+/// The inputs of every [`ContractCreation`] are hashed by the keccak circuit. The prefixes of the
+/// inputs are constrained to constants, the sender, the nonce and the salt are composed from the
+/// input bytes, and the digest of the init code is constrained to the last 32 bytes of the CREATE2
+/// input. The public instance holds [`ContractCreation::instance`] of every creation in order, the
+/// init code stays private.
+///
+/// The RLP encoding of the nonce is constrained to be canonical: a single byte is in `1..0x80`, a
+/// single byte after a prefix is at least `0x80`, and longer nonces have no leading zero byte. The
+/// lengths of the RLP encoded nonce and of the init code are part of the circuit, so a nonce of a
+/// different length can not be proven.
+#[derive(Default, Clone, Debug)]
+pub struct ContractAddressCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    creations: Vec<ContractCreation>,
+    /// The keccak inputs of all creations, see [`ContractCreation::inputs`]
+    inputs: Vec<Vec<u8>>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for ContractAddressCircuit<F> {
+    type Config = ContractAddressConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        let creations = self
+            .creations
+            .iter()
+            .map(|creation| match creation {
+                // The nonce is public and its length shapes the circuit
+                ContractCreation::Create { nonce, .. } => ContractCreation::Create {
+                    sender: Address::zero(),
+                    nonce: *nonce,
+                },
+                ContractCreation::Create2 { init_code, .. } => ContractCreation::Create2 {
+                    sender: Address::zero(),
+                    salt: H256::zero(),
+                    init_code: vec![0; init_code.len()],
+                },
+            })
+            .collect();
+        Self {
+            creations,
+            inputs: self
+                .inputs
+                .iter()
+                .map(|input| vec![0; input.len()])
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Wide enough to compose a sender
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, Address::len_bytes());
+        let byte_table = keccak.keccak_config.byte_table();
+        let q_nonce = meta.fixed_column();
+        let nonce_shift = meta.fixed_column();
+        let nonce_byte = meta.advice_column();
+        meta.enable_equality(nonce_byte);
+        meta.lookup("nonce byte range", |meta| {
+            let q_nonce = meta.query_fixed(q_nonce, Rotation::cur());
+            let nonce_shift = meta.query_fixed(nonce_shift, Rotation::cur());
+            let nonce_byte = meta.query_advice(nonce_byte, Rotation::cur());
+            vec![(q_nonce * (nonce_byte + nonce_shift), byte_table)]
+        });
+        ContractAddressConfig {
+            keccak,
+            bytes,
+            q_nonce,
+            nonce_shift,
+            nonce_byte,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let input_refs = self
+            .inputs
+            .iter()
+            .map(|input| input.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &input_refs)?;
+
+        let public_cells = layouter.assign_region(
+            || "contract addresses",
+            |mut region| {
+                let mut offset = 0;
+                let mut hash_idx = 0;
+                let mut public_cells = vec![];
+                for creation in self.creations.iter() {
+                    let (prefixes, values, ranges, outer) = match creation {
+                        ContractCreation::Create { nonce, .. } => {
+                            let nonce = encode_nonce(*nonce);
+                            let length = 22 + nonce.len();
+                            // The nonce has a prefix unless it is a single byte below 0x80
+                            let nonce_start = if nonce.len() == 1 && nonce[0] < 0x80 {
+                                22
+                            } else {
+                                23
+                            };
+                            let mut prefixes =
+                                vec![(0, 0xc0 + length as u8 - 1), (1, SENDER_PREFIX)];
+                            if nonce_start == 23 {
+                                prefixes.push((22, nonce[0]));
+                            }
+                            let values = vec![2..22, nonce_start..length];
+                            (prefixes, values, nonce_ranges(&nonce), hash_idx)
+                        }
+                        ContractCreation::Create2 { .. } => {
+                            let prefixes = vec![(0, CREATE2_PREFIX)];
+                            // The sender and the salt `[hi, lo]`
+                            let values = vec![1..21, 21..37, 37..53];
+                            (prefixes, values, vec![], hash_idx + 1)
+                        }
+                    };
+                    let outer_hash = &hashes[outer];
+                    let length = values.last().map_or(0, |range| range.end);
+                    let (cells, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        outer_hash.first_keccak_f,
+                        0..length,
+                    )?;
+                    offset = next_offset;
+                    for (idx, prefix) in prefixes {
+                        region.constrain_constant(cells[idx].cell(), F::from(prefix as u64))?;
+                    }
+                    for (idx, shift) in ranges {
+                        config.assign_nonce_range(&mut region, offset, &cells[idx], shift)?;
+                        offset += 1;
+                    }
+                    let mut value_cells = vec![];
+                    for range in values {
+                        value_cells.push(config.bytes.assign_from_bytes(
+                            &mut region,
+                            offset,
+                            &cells[range],
+                        )?);
+                        offset += 1;
+                    }
+                    if outer != hash_idx {
+                        // The salt is public as `[lo, hi]`
+                        value_cells.swap(1, 2);
+                        offset = link_digest(
+                            &mut region,
+                            &config.bytes,
+                            offset,
+                            keccak_config,
+                            &assigned_rows,
+                            &hashes[hash_idx],
+                            outer_hash,
+                            CREATE2_INPUT_LENGTH - 32,
+                        )?;
+                    }
+                    let (address, next_offset) = assign_address(
+                        &mut region,
+                        &config.bytes,
+                        offset,
+                        &outer_hash.hash_lo,
+                        &outer_hash.hash_hi,
+                    )?;
+                    offset = next_offset;
+                    public_cells.extend(value_cells);
+                    public_cells.push(address);
+                    hash_idx = outer + 1;
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> ContractAddressCircuit<F> {
+    /// Creates a circuit deriving the addresses of `creations` in `num_rows` rows.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        creations: Vec<ContractCreation>,
+    ) -> Self {
+        let inputs = creations
+            .iter()
+            .flat_map(|creation| creation.inputs())
+            .collect();
+        ContractAddressCircuit {
+            config,
+            num_rows,
+            creations,
+            inputs,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The addresses of the created contracts
+    pub fn addresses(&self) -> Vec<Address> {
+        self.creations
+            .iter()
+            .map(ContractCreation::address)
+            .collect()
+    }
+
+    /// The public inputs of the circuit, see [`ContractAddressCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        self.creations
+            .iter()
+            .flat_map(|creation| creation.instance())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use ethers_core::types::H256;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::util::eth_types::{Address, ToScalar};
+
+    use super::{ContractAddressCircuit, ContractCreation};
+
+    fn address(hex: &str) -> Address {
+        Address::from_str(hex).unwrap()
+    }
+
+    #[test]
+    fn test_create_addresses() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let sender = address("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        // Every length of the RLP encoded nonce: zero, a single byte, one and four bytes
+        let creations = [0, 1, 0x80, 0x1234567]
+            .map(|nonce| ContractCreation::Create { sender, nonce })
+            .to_vec();
+        let circuit = ContractAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, creations);
+        assert_eq!(
+            circuit.addresses(),
+            [
+                "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d",
+                "0x343c43a37d37dff08ae8c4a11544c718abb4fcf8",
+                "0x08e190dcb7b73f5fcdabb43e102215c83659a76d",
+                "0x190d1182a337644a231fa9dc8a1b45993b6af594",
+            ]
+            .map(address)
+        );
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        // A different nonce
+        let mut instance = circuit.instance();
+        instance[4] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_non_canonical_nonce_fails() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let sender = address("0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+        // A single byte nonce as the byte 0x85, and a four byte nonce with a leading zero
+        for (nonce, content, forged) in [
+            (5, vec![0x85], 0x85),
+            (0x1234567, vec![0x00, 0x12, 0x34, 0x56], 0x123456),
+        ] {
+            let creation = ContractCreation::Create { sender, nonce };
+            let mut circuit = ContractAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![creation]);
+            let input = &mut circuit.inputs[0];
+            let start = input.len() - content.len();
+            input[start..].copy_from_slice(&content);
+            let forged_address = Address::from_slice(&Keccak256::digest(input.as_slice())[12..]);
+            let instance = vec![
+                sender.to_scalar().unwrap(),
+                Fr::from(forged),
+                forged_address.to_scalar().unwrap(),
+            ];
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_create2_addresses() {
+        // The examples of EIP-1014 and an empty init code
+        let sender = address("0x00000000000000000000000000000000deadbeef");
+        let salt = H256::from_low_u64_be(0xcafebabe);
+        let creations = vec![
+            ContractCreation::Create2 {
+                sender: Address::zero(),
+                salt: H256::zero(),
+                init_code: vec![0],
+            },
+            ContractCreation::Create2 {
+                sender,
+                salt,
+                init_code: [0xde, 0xad, 0xbe, 0xef].repeat(11),
+            },
+            ContractCreation::Create2 {
+                sender,
+                salt,
+                init_code: vec![],
+            },
+        ];
+        let circuit = ContractAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, creations);
+        assert_eq!(
+            circuit.addresses(),
+            [
+                "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38",
+                "0x1d8bfdc5d46dc4f61d6b6115972536ebe6a8854c",
+                "0x2ea189ed9ec7e3bed201c93503d891575b36adc7",
+            ]
+            .map(address)
+        );
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_wrong_create2_values_fail() {
+        let creation = ContractCreation::Create2 {
+            sender: address("0x00000000000000000000000000000000deadbeef"),
+            salt: H256::from_low_u64_be(0xcafebabe),
+            init_code: vec![0x60, 0x00],
+        };
+        let circuit = ContractAddressCircuit::<Fr>::new(CONFIG, NUM_ROWS, vec![creation]);
+        // The sender, the salt and the address
+        for idx in [0, 1, 3] {
+            let mut instance = circuit.instance();
+            instance[idx] += Fr::from(1);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+}
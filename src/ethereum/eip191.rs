@@ -0,0 +1,199 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::decimal::DecimalConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+
+/// Prefix of a message signed with `personal_sign`, followed by the decimal message length
+pub const PERSONAL_MESSAGE_PREFIX: &[u8] = b"\x19Ethereum Signed Message:\n";
+
+/// Computes the EIP-191 signing hash of `message` natively:
+/// `keccak256("\x19Ethereum Signed Message:\n" || decimal(len) || message)`.
+pub fn personal_message_hash(message: &[u8]) -> H256 {
+    H256::from_slice(&Keccak256::digest(personal_message(message)))
+}
+
+/// The prefixed message hashed by [`personal_message_hash`]
+fn personal_message(message: &[u8]) -> Vec<u8> {
+    let mut input = PERSONAL_MESSAGE_PREFIX.to_vec();
+    input.extend_from_slice(message.len().to_string().as_bytes());
+    input.extend_from_slice(message);
+    input
+}
+
+/// Config of [`PersonalSignCircuit`]
+#[derive(Clone, Debug)]
+pub struct PersonalSignConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    pub decimal: DecimalConfig<F>,
+}
+
+/// Circuit proving the signing hash of a message signed with `personal_sign` (EIP-191 version
+/// `0x45`).
+///
+/// The prefixed message is hashed by the keccak circuit. The prefix is constrained to constants and
+/// the decimal digits after it are parsed by [`DecimalConfig`], which constrains the length of the
+/// whole input in `bytes_left` to the prefix, the digits and the parsed message length. The public
+/// instance is the signing hash `[lo, hi]` followed by the message length, the message stays
+/// private.
+///
+/// The message length is part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct PersonalSignCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    message: Vec<u8>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for PersonalSignCircuit<F> {
+    type Config = PersonalSignConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            message: vec![0; self.message.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        let byte_table = keccak.keccak_config.byte_table();
+        let decimal = DecimalConfig::configure(meta, byte_table);
+        PersonalSignConfig {
+            keccak,
+            bytes,
+            decimal,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let input = personal_message(&self.message);
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[input.as_slice()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let length = layouter.assign_region(
+            || "message length",
+            |mut region| {
+                let prefix_len = PERSONAL_MESSAGE_PREFIX.len();
+                let num_digits = self.message.len().to_string().len();
+                let (bytes, offset) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    0,
+                    keccak_config,
+                    &assigned_rows,
+                    hash.first_keccak_f,
+                    0..prefix_len + num_digits,
+                )?;
+                for (byte, prefix) in bytes.iter().zip(PERSONAL_MESSAGE_PREFIX) {
+                    region.constrain_constant(byte.cell(), F::from(*prefix as u64))?;
+                }
+                let first_word = keccak_config
+                    .input_word_row(&assigned_rows, hash.first_keccak_f, 0)
+                    .ok_or(Error::Synthesis)?;
+                config.decimal.assign(
+                    &mut region,
+                    offset,
+                    &bytes[prefix_len..],
+                    &first_word.bytes_left,
+                    (prefix_len + num_digits) as u64,
+                )
+            },
+        )?;
+
+        let public_cells = [&hash.hash_lo, &hash.hash_hi, &length];
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> PersonalSignCircuit<F> {
+    /// Creates a circuit proving the signing hash of `message` in `num_rows` rows.
+    pub fn new(config: KeccakConfigParams, num_rows: usize, message: Vec<u8>) -> Self {
+        PersonalSignCircuit {
+            config,
+            num_rows,
+            message,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The signing hash of the message
+    pub fn signing_hash(&self) -> H256 {
+        personal_message_hash(&self.message)
+    }
+
+    /// The public inputs of the circuit, see [`PersonalSignCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let hash = Word::<F>::from(self.signing_hash());
+        vec![hash.lo(), hash.hi(), F::from(self.message.len() as u64)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::utils::hash_message;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+
+    use super::PersonalSignCircuit;
+
+    #[test]
+    fn test_personal_sign_hash() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // An empty message, a single digit and a message over several keccak_f's with 3 digits
+        for message in [vec![], b"hello".to_vec(), vec![b'x'; 300]] {
+            let circuit = PersonalSignCircuit::<Fr>::new(CONFIG, NUM_ROWS, message.clone());
+            assert_eq!(circuit.signing_hash(), hash_message(&message));
+
+            let prover =
+                MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+            prover.assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_wrong_length_fails() {
+        let circuit = PersonalSignCircuit::<Fr>::new(CONFIG, NUM_ROWS, b"hello world".to_vec());
+        let mut instance = circuit.instance();
+        instance[2] = Fr::from(10);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use ethers_core::types::{Address, Bytes, H256, U256};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use serde_json::Value as JsonValue;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes, link_digest};
+use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Name of the struct type of the domain separator
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// A keccak input of an EIP-712 signing hash and the digests of other inputs in it
+#[derive(Default, Clone, Debug)]
+pub struct Eip712Input {
+    pub input: Vec<u8>,
+    /// Byte offsets in `input` and the indices of the inputs whose digests are there
+    pub digests: Vec<(usize, usize)>,
+    /// The bytes `input` starts with that are fixed by the types, the `\x19\x01` prefix of the
+    /// signing input or the whole `encodeType` string of a type hash
+    pub constant_prefix: Vec<u8>,
+}
+
+/// The tree of keccak inputs of the EIP-712 signing hash
+/// `keccak256("\x19\x01" || domainSeparator || hashStruct(message))`.
+///
+/// Type hashes, `string` and `bytes` values, arrays and structs each have their own input, every
+/// input comes after the inputs of the digests in it and the signing input is the last one.
+#[derive(Default, Clone, Debug)]
+pub struct Eip712Encoding {
+    pub inputs: Vec<Eip712Input>,
+}
+
+impl Eip712Encoding {
+    /// Encodes a typed data document with `types`, `primaryType`, `domain` and `message`, as
+    /// passed to `eth_signTypedData_v4`.
+    pub fn from_typed_data(typed_data: &JsonValue) -> Result<Self, Keccak256Error> {
+        let mut types = BTreeMap::new();
+        let json_types = typed_data["types"]
+            .as_object()
+            .ok_or_else(|| invalid_typed_data("types", "is not an object".to_string()))?;
+        for (name, fields) in json_types.iter() {
+            let fields = fields
+                .as_array()
+                .ok_or_else(|| invalid_typed_data(name, "is not an array".to_string()))?
+                .iter()
+                .map(
+                    |field| match (field["name"].as_str(), field["type"].as_str()) {
+                        (Some(name), Some(ty)) => Ok((name.to_string(), ty.to_string())),
+                        _ => Err(invalid_typed_data(
+                            name,
+                            format!("has an invalid field {}", field),
+                        )),
+                    },
+                )
+                .collect::<Result<Vec<_>, _>>()?;
+            types.insert(name.clone(), fields);
+        }
+        let primary_type = typed_data["primaryType"]
+            .as_str()
+            .ok_or_else(|| invalid_typed_data("primaryType", "is not a string".to_string()))?;
+
+        let mut encoder = Encoder {
+            types,
+            type_hashes: HashMap::new(),
+            encoding: Eip712Encoding::default(),
+            digests: vec![],
+        };
+        let domain = encoder.hash_struct(DOMAIN_TYPE, &typed_data["domain"])?;
+        let message = encoder.hash_struct(primary_type, &typed_data["message"])?;
+        let mut signing_input = Eip712Input {
+            input: vec![0x19, 0x01],
+            digests: vec![],
+            constant_prefix: vec![0x19, 0x01],
+        };
+        encoder.append_digest(&mut signing_input, domain);
+        encoder.append_digest(&mut signing_input, message);
+        encoder.push(signing_input);
+        Ok(encoder.encoding)
+    }
+
+    /// The signing hash, the digest of the last input
+    pub fn signing_hash(&self) -> H256 {
+        let input = self
+            .inputs
+            .last()
+            .map(|input| input.input.as_slice())
+            .unwrap_or_default();
+        H256::from_slice(&Keccak256::digest(input))
+    }
+}
+
+fn invalid_typed_data(name: &str, reason: String) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: name.to_string(),
+        reason,
+    }
+}
+
+/// Builds the inputs of an [`Eip712Encoding`], with the digests of all inputs so far
+struct Encoder {
+    /// The fields `(name, type)` of every struct type
+    types: BTreeMap<String, Vec<(String, String)>>,
+    /// Indices of the inputs of the type hashes
+    type_hashes: HashMap<String, usize>,
+    encoding: Eip712Encoding,
+    digests: Vec<[u8; 32]>,
+}
+
+impl Encoder {
+    fn push(&mut self, input: Eip712Input) -> usize {
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&Keccak256::digest(&input.input));
+        self.digests.push(digest);
+        self.encoding.inputs.push(input);
+        self.encoding.inputs.len() - 1
+    }
+
+    fn append_digest(&self, parent: &mut Eip712Input, idx: usize) {
+        parent.digests.push((parent.input.len(), idx));
+        parent.input.extend_from_slice(&self.digests[idx]);
+    }
+
+    /// `encodeType` of the struct type `name`: the type followed by the types it references,
+    /// sorted by name
+    fn encode_type(&self, name: &str) -> String {
+        let mut referenced = BTreeSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(ty) = pending.pop() {
+            for (_, field_type) in self.types.get(&ty).into_iter().flatten() {
+                let base_type = field_type.split('[').next().unwrap_or_default();
+                if self.types.contains_key(base_type) && referenced.insert(base_type.to_string()) {
+                    pending.push(base_type.to_string());
+                }
+            }
+        }
+        referenced.remove(name);
+        std::iter::once(name)
+            .chain(referenced.iter().map(|ty| ty.as_str()))
+            .map(|ty| {
+                let fields = self.types[ty]
+                    .iter()
+                    .map(|(field, field_type)| format!("{} {}", field_type, field))
+                    .collect::<Vec<_>>();
+                format!("{}({})", ty, fields.join(","))
+            })
+            .collect()
+    }
+
+    /// Pushes the inputs of `hashStruct(value)` for the struct type `name`, returns the index of
+    /// its last input
+    fn hash_struct(&mut self, name: &str, value: &JsonValue) -> Result<usize, Keccak256Error> {
+        let fields = self
+            .types
+            .get(name)
+            .cloned()
+            .ok_or_else(|| invalid_typed_data(name, "is not a defined type".to_string()))?;
+        let type_hash = match self.type_hashes.get(name) {
+            Some(idx) => *idx,
+            None => {
+                let encoded_type = self.encode_type(name).into_bytes();
+                let input = Eip712Input {
+                    input: encoded_type.clone(),
+                    digests: vec![],
+                    constant_prefix: encoded_type,
+                };
+                let idx = self.push(input);
+                self.type_hashes.insert(name.to_string(), idx);
+                idx
+            }
+        };
+
+        let mut data = Eip712Input::default();
+        self.append_digest(&mut data, type_hash);
+        for (field, field_type) in fields.iter() {
+            let field_value = value.get(field).ok_or_else(|| {
+                invalid_typed_data(name, format!("has no value for field {}", field))
+            })?;
+            self.encode_value(field_type, field_value, &mut data)?;
+        }
+        Ok(self.push(data))
+    }
+
+    /// Appends `encodeData` of `value` of type `ty` to `data`
+    fn encode_value(
+        &mut self,
+        ty: &str,
+        value: &JsonValue,
+        data: &mut Eip712Input,
+    ) -> Result<(), Keccak256Error> {
+        let idx = if let Some(bracket) = ty.rfind('[') {
+            let elements = value
+                .as_array()
+                .ok_or_else(|| invalid_typed_data(ty, format!("{} is not an array", value)))?;
+            let length = ty[bracket + 1..].trim_end_matches(']');
+            if !length.is_empty() && length.parse::<usize>().ok() != Some(elements.len()) {
+                let reason = format!("has {} elements", elements.len());
+                return Err(invalid_typed_data(ty, reason));
+            }
+            let mut array = Eip712Input::default();
+            for element in elements.iter() {
+                self.encode_value(&ty[..bracket], element, &mut array)?;
+            }
+            self.push(array)
+        } else if self.types.contains_key(ty) {
+            self.hash_struct(ty, value)?
+        } else if ty == "string" {
+            let string = value
+                .as_str()
+                .ok_or_else(|| invalid_typed_data(ty, format!("{} is not a string", value)))?;
+            self.push(Eip712Input {
+                input: string.as_bytes().to_vec(),
+                digests: vec![],
+                constant_prefix: vec![],
+            })
+        } else if ty == "bytes" {
+            self.push(Eip712Input {
+                input: parse_json::<Bytes>(ty, value)?.to_vec(),
+                digests: vec![],
+                constant_prefix: vec![],
+            })
+        } else {
+            data.input.extend_from_slice(&encode_atomic(ty, value)?);
+            return Ok(());
+        };
+        self.append_digest(data, idx);
+        Ok(())
+    }
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(
+    ty: &str,
+    value: &JsonValue,
+) -> Result<T, Keccak256Error> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| invalid_typed_data(ty, format!("{} is invalid: {}", value, e)))
+}
+
+/// The 32 byte encoding of `value` of the atomic type `ty`
+fn encode_atomic(ty: &str, value: &JsonValue) -> Result<[u8; 32], Keccak256Error> {
+    let mut encoded = [0; 32];
+    if ty == "address" {
+        encoded[12..].copy_from_slice(parse_json::<Address>(ty, value)?.as_bytes());
+    } else if ty == "bool" {
+        encoded[31] = parse_json::<bool>(ty, value)? as u8;
+    } else if let Some(size) = ty.strip_prefix("bytes") {
+        let bytes = parse_json::<Bytes>(ty, value)?;
+        if size
+            .parse::<usize>()
+            .map_or(true, |size| size > 32 || bytes.len() > size)
+        {
+            return Err(invalid_typed_data(ty, format!("{} does not fit", value)));
+        }
+        encoded[..bytes.len()].copy_from_slice(&bytes);
+    } else if ty.starts_with("uint") || ty.starts_with("int") {
+        parse_integer(ty, value)?.to_big_endian(&mut encoded);
+    } else {
+        return Err(invalid_typed_data(
+            ty,
+            "is not a supported type".to_string(),
+        ));
+    }
+    Ok(encoded)
+}
+
+/// Parses a JSON number or a decimal or `0x` prefixed hexadecimal string, negative integers in
+/// two's complement
+fn parse_integer(ty: &str, value: &JsonValue) -> Result<U256, Keccak256Error> {
+    let invalid = || invalid_typed_data(ty, format!("{} is not an integer", value));
+    let string = match value {
+        JsonValue::Number(number) => number.to_string(),
+        JsonValue::String(string) => string.clone(),
+        _ => return Err(invalid()),
+    };
+    let (negative, digits) = match string.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, string.as_str()),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).map_err(|_| invalid())?,
+        None => U256::from_dec_str(digits).map_err(|_| invalid())?,
+    };
+    if negative && !ty.starts_with("int") {
+        return Err(invalid());
+    }
+    Ok(match negative {
+        true => (!magnitude).overflowing_add(U256::one()).0,
+        false => magnitude,
+    })
+}
+
+/// Config of [`Eip712Circuit`]
+#[derive(Clone, Debug)]
+pub struct Eip712Config<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the EIP-712 signing hash of typed data.
+///
+/// All inputs of an [`Eip712Encoding`] are hashed by the keccak circuit, the digest of every inner
+/// input is constrained to the bytes of the input words where it appears in its outer inputs. The
+/// public instance is the signing hash `[lo, hi]`, the typed data stays private.
+///
+/// The types, the lengths of the dynamic values and arrays are part of the circuit: the bytes of
+/// the `\x19\x01` prefix and of the `encodeType` strings behind the type hashes are constants.
+#[derive(Default, Clone, Debug)]
+pub struct Eip712Circuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    encoding: Eip712Encoding,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for Eip712Circuit<F> {
+    type Config = Eip712Config<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        let inputs = self
+            .encoding
+            .inputs
+            .iter()
+            .map(|input| Eip712Input {
+                input: vec![0; input.input.len()],
+                digests: input.digests.clone(),
+                constant_prefix: input.constant_prefix.clone(),
+            })
+            .collect();
+        Self {
+            encoding: Eip712Encoding { inputs },
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        Eip712Config { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self
+            .encoding
+            .inputs
+            .iter()
+            .map(|input| input.input.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        layouter.assign_region(
+            || "eip712 digests",
+            |mut region| {
+                let mut offset = 0;
+                for (input, parent) in self.encoding.inputs.iter().zip(hashes.iter()) {
+                    let (cells, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        parent.first_keccak_f,
+                        0..input.constant_prefix.len(),
+                    )?;
+                    offset = next_offset;
+                    for (cell, &byte) in cells.iter().zip(input.constant_prefix.iter()) {
+                        region.constrain_constant(cell.cell(), F::from(byte as u64))?;
+                    }
+                    for &(byte_offset, child) in input.digests.iter() {
+                        offset = link_digest(
+                            &mut region,
+                            &config.bytes,
+                            offset,
+                            keccak_config,
+                            &assigned_rows,
+                            &hashes[child],
+                            parent,
+                            byte_offset,
+                        )?;
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        let signing_hash = hashes.last().ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(signing_hash.hash_lo.cell(), config.keccak.input, 0)?;
+        layouter.constrain_instance(signing_hash.hash_hi.cell(), config.keccak.input, 1)?;
+
+        Ok(())
+    }
+}
+
+impl<F: Field> Eip712Circuit<F> {
+    /// Creates a circuit hashing the inputs of `encoding` in `num_rows` rows.
+    pub fn new(config: KeccakConfigParams, num_rows: usize, encoding: Eip712Encoding) -> Self {
+        Eip712Circuit {
+            config,
+            num_rows,
+            encoding,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a circuit for the typed data document stored as JSON at `path`.
+    pub fn from_json(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        path: &Path,
+    ) -> Result<Self, Keccak256Error> {
+        let file = File::open(path).map_err(|e| {
+            Keccak256Error::io(
+                format!("failed to read typed data from {}", path.display()),
+                e,
+            )
+        })?;
+        let typed_data: JsonValue = serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            Keccak256Error::InputParsing {
+                name: path.display().to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+        let encoding = Eip712Encoding::from_typed_data(&typed_data)?;
+        Ok(Self::new(config, num_rows, encoding))
+    }
+
+    /// The signing hash of the typed data
+    pub fn signing_hash(&self) -> H256 {
+        self.encoding.signing_hash()
+    }
+
+    /// The public inputs of the circuit, see [`Eip712Circuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let hash = Word::<F>::from(self.signing_hash());
+        vec![hash.lo(), hash.hi()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{Eip712Circuit, Eip712Encoding};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/{}.json", name))
+    }
+
+    #[test]
+    fn test_eip712_mail() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // The example of the EIP
+        let circuit =
+            Eip712Circuit::<Fr>::from_json(CONFIG, NUM_ROWS, &fixture("eip712_mail")).unwrap();
+        assert_eq!(
+            hex::encode(circuit.signing_hash()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut instance = circuit.instance();
+        instance[1] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_eip712_tampered_constants() {
+        let circuit =
+            Eip712Circuit::<Fr>::from_json(CONFIG, NUM_ROWS, &fixture("eip712_mail")).unwrap();
+
+        // Another prefix of the signing input
+        let mut tampered_prefix = circuit.clone();
+        tampered_prefix.encoding.inputs.last_mut().unwrap().input[0] = 0x18;
+
+        // Another type string of the same length, hashed consistently
+        let mut typed_data: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(fixture("eip712_mail")).unwrap()).unwrap();
+        let types = typed_data["types"].as_object_mut().unwrap();
+        let mail = types.remove("Mail").unwrap();
+        types.insert("Mall".to_string(), mail);
+        typed_data["primaryType"] = serde_json::json!("Mall");
+        let mut encoding = Eip712Encoding::from_typed_data(&typed_data).unwrap();
+        for (input, expected) in encoding.inputs.iter_mut().zip(&circuit.encoding.inputs) {
+            input.constant_prefix = expected.constant_prefix.clone();
+        }
+        let tampered_type = Eip712Circuit::<Fr>::new(CONFIG, NUM_ROWS, encoding);
+
+        for tampered in [tampered_prefix, tampered_type] {
+            let instance = tampered.instance();
+            let prover = MockProver::<Fr>::run(CONFIG.k, &tampered, vec![instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_eip712_arrays_and_atomic_types() {
+        let circuit =
+            Eip712Circuit::<Fr>::from_json(CONFIG, NUM_ROWS, &fixture("eip712_group")).unwrap();
+        assert_eq!(
+            hex::encode(circuit.signing_hash()),
+            "f81b2915e0f2508c10656ca1c3bf2362a4923395baf35cc1d343c416c9841c3d"
+        );
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_eip712_invalid_typed_data() {
+        let typed_data: serde_json::Value =
+            serde_json::from_reader(std::fs::File::open(fixture("eip712_group")).unwrap()).unwrap();
+        // A static array of the wrong length
+        let mut wrong_length = typed_data.clone();
+        wrong_length["message"]["scores"] = serde_json::json!([1, 2, 3]);
+        let result = Eip712Encoding::from_typed_data(&wrong_length);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+
+        // A negative unsigned integer
+        let mut negative = typed_data;
+        negative["message"]["nonce"] = serde_json::json!(-1);
+        let result = Eip712Encoding::from_typed_data(&negative);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+}
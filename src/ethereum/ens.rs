@@ -0,0 +1,331 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes, link_digest};
+use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Splits a dotted name into its labels, the empty name has none. Fails for empty labels.
+fn split_labels(name: &str) -> Result<Vec<Vec<u8>>, Keccak256Error> {
+    if name.is_empty() {
+        return Ok(vec![]);
+    }
+    let labels = name
+        .split('.')
+        .map(|label| label.as_bytes().to_vec())
+        .collect::<Vec<_>>();
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(Keccak256Error::InputParsing {
+            name: name.to_string(),
+            reason: "the name has an empty label".to_string(),
+        });
+    }
+    Ok(labels)
+}
+
+/// Computes the ENS `namehash` of `name` natively: starting from 32 zero bytes,
+/// `node = keccak256(node || keccak256(label))` for every label from the right. The namehash of
+/// the empty name is the zero node, as defined in EIP-137.
+///
+/// The name is hashed as given, it has to be normalized beforehand.
+pub fn namehash(name: &str) -> Result<H256, Keccak256Error> {
+    let mut node = H256::zero();
+    for label in split_labels(name)?.iter().rev() {
+        let mut input = node.as_bytes().to_vec();
+        input.extend_from_slice(&Keccak256::digest(label));
+        node = H256::from_slice(&Keccak256::digest(&input));
+    }
+    Ok(node)
+}
+
+/// Config of [`EnsNamehashCircuit`]
+#[derive(Clone, Debug)]
+pub struct EnsNamehashConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the ENS `namehash` of a name.
+///
+/// Every label and every 64 byte node input `node || keccak256(label)` is hashed by the keccak
+/// circuit, from the rightmost label on. The digest of every label and of every node is constrained
+/// to the bytes of the next node input, the first node input starts with 32 bytes constrained to
+/// zero. The public instance is the final node `[lo, hi]`, followed by the bytes of the labels in
+/// the order of the name, one byte per cell, if the labels are public.
+///
+/// The number of labels and their lengths are part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct EnsNamehashCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    /// The labels in the order of the name
+    labels: Vec<Vec<u8>>,
+    public_labels: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for EnsNamehashCircuit<F> {
+    type Config = EnsNamehashConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            labels: self
+                .labels
+                .iter()
+                .map(|label| vec![0; label.len()])
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        EnsNamehashConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self.inputs();
+        let input_refs = inputs
+            .iter()
+            .map(|input| input.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &input_refs)?;
+
+        let public_cells = layouter.assign_region(
+            || "namehash",
+            |mut region| {
+                // The inputs alternate between a label and its node, from the rightmost label on
+                let mut offset = 0;
+                for (idx, pair) in hashes.chunks(2).enumerate() {
+                    let (label, node) = (&pair[0], &pair[1]);
+                    if idx == 0 {
+                        let (zero_node, next_offset) = config.bytes.assign_input_bytes(
+                            &mut region,
+                            offset,
+                            keccak_config,
+                            &assigned_rows,
+                            node.first_keccak_f,
+                            0..32,
+                        )?;
+                        for byte in zero_node {
+                            region.constrain_constant(byte.cell(), F::ZERO)?;
+                        }
+                        offset = next_offset;
+                    } else {
+                        let parent = &hashes[2 * idx - 1];
+                        offset = link_digest(
+                            &mut region,
+                            &config.bytes,
+                            offset,
+                            keccak_config,
+                            &assigned_rows,
+                            parent,
+                            node,
+                            0,
+                        )?;
+                    }
+                    offset = link_digest(
+                        &mut region,
+                        &config.bytes,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        label,
+                        node,
+                        32,
+                    )?;
+                }
+
+                let node = hashes.last().ok_or(Error::Synthesis)?;
+                let mut public_cells = vec![node.hash_lo.clone(), node.hash_hi.clone()];
+                if self.public_labels {
+                    for (idx, label) in self.labels.iter().enumerate() {
+                        let hash = &hashes[2 * (self.labels.len() - 1 - idx)];
+                        let (bytes, next_offset) = config.bytes.assign_input_bytes(
+                            &mut region,
+                            offset,
+                            keccak_config,
+                            &assigned_rows,
+                            hash.first_keccak_f,
+                            0..label.len(),
+                        )?;
+                        public_cells.extend(bytes);
+                        offset = next_offset;
+                    }
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> EnsNamehashCircuit<F> {
+    /// Creates a circuit computing the namehash of the dotted `name` in `num_rows` rows, the
+    /// labels are private. The name is hashed as given, it has to be normalized beforehand.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the name has an empty label, or is empty: the
+    /// namehash of the empty name is the zero node, there is nothing to hash.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        name: &str,
+    ) -> Result<Self, Keccak256Error> {
+        let labels = split_labels(name)?;
+        if labels.is_empty() {
+            return Err(Keccak256Error::InputParsing {
+                name: name.to_string(),
+                reason: "the empty name has no label to hash".to_string(),
+            });
+        }
+        Ok(EnsNamehashCircuit {
+            config,
+            num_rows,
+            labels,
+            public_labels: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Makes the bytes of the labels public.
+    pub fn with_public_labels(mut self) -> Self {
+        self.public_labels = true;
+        self
+    }
+
+    /// The keccak inputs: every label followed by its node input, from the rightmost label on
+    fn inputs(&self) -> Vec<Vec<u8>> {
+        let mut node = [0; 32];
+        let mut inputs = vec![];
+        for label in self.labels.iter().rev() {
+            let mut node_input = node.to_vec();
+            node_input.extend_from_slice(&Keccak256::digest(label));
+            node.copy_from_slice(&Keccak256::digest(&node_input));
+            inputs.push(label.clone());
+            inputs.push(node_input);
+        }
+        inputs
+    }
+
+    /// The namehash of the name
+    pub fn namehash(&self) -> H256 {
+        let inputs = self.inputs();
+        let node_input = inputs.last().expect("a name has a label");
+        H256::from_slice(&Keccak256::digest(node_input))
+    }
+
+    /// The public inputs of the circuit, see [`EnsNamehashCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let node = Word::<F>::from(self.namehash());
+        let mut instance = vec![node.lo(), node.hi()];
+        if self.public_labels {
+            for label in self.labels.iter() {
+                instance.extend(label.iter().map(|byte| F::from(*byte as u64)));
+            }
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::types::H256;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{namehash, EnsNamehashCircuit};
+
+    #[test]
+    fn test_namehash() {
+        for (name, node) in [
+            (
+                "eth",
+                "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae",
+            ),
+            (
+                "foo.eth",
+                "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f",
+            ),
+            (
+                "vitalik.eth",
+                "ee6c4522aab0003e8d14cd40a6af439055fd2577951148c14b6cea9a53475835",
+            ),
+        ] {
+            assert_eq!(hex::encode(namehash(name).unwrap()), node);
+        }
+        assert_eq!(namehash("").unwrap(), H256::zero());
+        for name in ["foo..eth", ".eth", "eth."] {
+            let result = namehash(name);
+            assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+        }
+        let result = EnsNamehashCircuit::<Fr>::new(CONFIG, NUM_ROWS, "");
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_namehash_circuit() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let circuit = EnsNamehashCircuit::<Fr>::new(CONFIG, NUM_ROWS, "alice.wallet.eth").unwrap();
+        assert_eq!(circuit.namehash(), namehash("alice.wallet.eth").unwrap());
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut instance = circuit.instance();
+        instance[0] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_public_labels() {
+        let circuit = EnsNamehashCircuit::<Fr>::new(CONFIG, NUM_ROWS, "vitalik.eth")
+            .unwrap()
+            .with_public_labels();
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 2 + 7 + 3);
+        assert_eq!(instance[2], Fr::from(b'v' as u64));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // Another name with labels of the same lengths
+        let mut wrong = instance;
+        wrong[2] = Fr::from(b'w' as u64);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use ethers_core::types::{Bytes, H256};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::ethereum::rlp::{decode_list, RlpItem};
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Indices of the public fields in the RLP list of a header: parent hash, state root, number and
+/// timestamp
+const PUBLIC_FIELDS: [usize; 4] = [0, 3, 8, 11];
+
+/// Number of fields of a header before the fork specific ones, like `baseFeePerGas`
+const NUM_BASE_FIELDS: usize = 15;
+
+/// The public fields of a block header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderFields {
+    pub hash: H256,
+    pub parent_hash: H256,
+    pub state_root: H256,
+    pub number: u64,
+    pub timestamp: u64,
+}
+
+/// A header and the positions of its public fields in the RLP encoding
+#[derive(Clone, Debug)]
+struct Header {
+    rlp: Vec<u8>,
+    fields: Vec<RlpItem>,
+}
+
+/// A block header stored as JSON, only the raw header is read
+#[derive(Deserialize)]
+struct HeaderJson {
+    rlp: Bytes,
+}
+
+/// Decodes the public fields of the RLP encoded block `header`.
+pub fn decode_header(header: &[u8]) -> Result<HeaderFields, Keccak256Error> {
+    let fields = public_fields(header)?;
+    let value = |idx: usize| fields[idx].value(header);
+    let number = |idx: usize| {
+        value(idx)
+            .iter()
+            .fold(0, |number, byte| (number << 8) | *byte as u64)
+    };
+    Ok(HeaderFields {
+        hash: H256::from_slice(&Keccak256::digest(header)),
+        parent_hash: H256::from_slice(value(0)),
+        state_root: H256::from_slice(value(1)),
+        number: number(2),
+        timestamp: number(3),
+    })
+}
+
+fn invalid_header(reason: String) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: "header".to_string(),
+        reason,
+    }
+}
+
+/// The items of the public fields of `header`, checked to be 32 byte hashes and integers that fit
+/// in a `u64`
+fn public_fields(header: &[u8]) -> Result<Vec<RlpItem>, Keccak256Error> {
+    let items = decode_list(header)?;
+    if items.len() < NUM_BASE_FIELDS {
+        return Err(invalid_header(format!(
+            "{} fields are too few",
+            items.len()
+        )));
+    }
+    let fields = PUBLIC_FIELDS.map(|idx| items[idx].clone());
+    for (idx, field) in fields.iter().enumerate() {
+        let value = field.value(header);
+        let valid = match idx {
+            0 | 1 => value.len() == 32,
+            _ => value.len() <= 8 && value.first() != Some(&0),
+        };
+        if field.is_list || !valid {
+            return Err(invalid_header(format!(
+                "field {} is malformed",
+                PUBLIC_FIELDS[idx]
+            )));
+        }
+    }
+    Ok(fields.to_vec())
+}
+
+/// Config of [`BlockHeaderCircuit`]
+#[derive(Clone, Debug)]
+pub struct BlockHeaderConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the block hashes of RLP encoded block headers and publishing their fields.
+///
+/// Every header is hashed by the keccak circuit. The bytes of the parent hash, state root, number
+/// and timestamp are taken from the input words at their offsets in the header, their prefixes
+/// are constrained to the RLP prefix of a string of their length. The public instance is
+/// `[hash_lo, hash_hi, parent_hash_lo, parent_hash_hi, state_root_lo, state_root_hi, number,
+/// timestamp]` for every header. With [`BlockHeaderCircuit::with_chain`] the parent hash of every
+/// header after the first one is also constrained to the block hash of the header before it, so
+/// the headers form a chain, otherwise the headers are independent.
+///
+/// The offsets are part of the circuit, so keys are specific to the lengths of the fields of the
+/// headers, which change with the size of the difficulty, number, timestamp and extra data.
+#[derive(Default, Clone, Debug)]
+pub struct BlockHeaderCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    headers: Vec<Header>,
+    chain: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for BlockHeaderCircuit<F> {
+    type Config = BlockHeaderConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            headers: self
+                .headers
+                .iter()
+                .map(|header| Header {
+                    rlp: vec![0; header.rlp.len()],
+                    fields: header.fields.clone(),
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        BlockHeaderConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self
+            .headers
+            .iter()
+            .map(|header| header.rlp.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        let public_cells = layouter.assign_region(
+            || "header fields",
+            |mut region| {
+                let mut public_cells = vec![];
+                let mut offset = 0;
+                for (idx, (header, hash)) in self.headers.iter().zip(hashes.iter()).enumerate() {
+                    // The prefix, if any, and the content of every field
+                    let byte_indices = header.fields.iter().flat_map(|field| {
+                        let prefix = field.has_prefix().then_some(field.offset);
+                        prefix.into_iter().chain(field.content.clone())
+                    });
+                    let (bytes, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        hash.first_keccak_f,
+                        byte_indices,
+                    )?;
+                    offset = next_offset;
+
+                    public_cells.extend([hash.hash_lo.clone(), hash.hash_hi.clone()]);
+                    let mut bytes = bytes.as_slice();
+                    for field in header.fields.iter() {
+                        if field.has_prefix() {
+                            let prefix = F::from(0x80 + field.content.len() as u64);
+                            region.constrain_constant(bytes[0].cell(), prefix)?;
+                            bytes = &bytes[1..];
+                        }
+                        let (content, rest) = bytes.split_at(field.content.len());
+                        bytes = rest;
+                        // Hashes are split into `[lo, hi]`, integers are a single value
+                        let halves = match content.len() {
+                            32 => vec![&content[16..], &content[..16]],
+                            _ => vec![content],
+                        };
+                        for half in halves {
+                            public_cells.push(config.bytes.assign_from_bytes(
+                                &mut region,
+                                offset,
+                                half,
+                            )?);
+                            offset += 1;
+                        }
+                    }
+
+                    // The parent hash is the block hash of the previous header
+                    if self.chain && idx > 0 {
+                        let previous = &hashes[idx - 1];
+                        let fields = &public_cells[public_cells.len() - 6..];
+                        region.constrain_equal(fields[0].cell(), previous.hash_lo.cell())?;
+                        region.constrain_equal(fields[1].cell(), previous.hash_hi.cell())?;
+                    }
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> BlockHeaderCircuit<F> {
+    /// Creates a circuit hashing the RLP encoded `headers` in `num_rows` rows.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if a header can not be decoded.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        headers: Vec<Vec<u8>>,
+    ) -> Result<Self, Keccak256Error> {
+        let headers = headers
+            .into_iter()
+            .map(|rlp| {
+                let fields = public_fields(&rlp)?;
+                Ok(Header { rlp, fields })
+            })
+            .collect::<Result<Vec<_>, Keccak256Error>>()?;
+
+        Ok(BlockHeaderCircuit {
+            config,
+            num_rows,
+            headers,
+            chain: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Constrains the parent hash of every header after the first one to the block hash of the
+    /// header before it.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the parent hash of a header is not the block
+    /// hash of the header before it.
+    pub fn with_chain(self) -> Result<Self, Keccak256Error> {
+        for (idx, pair) in self.headers.windows(2).enumerate() {
+            let (previous, header) = (&pair[0], &pair[1]);
+            let parent_hash = header.fields[0].value(&header.rlp);
+            if parent_hash != Keccak256::digest(&previous.rlp).as_slice() {
+                let reason = format!("header {} is not a child of header {}", idx + 1, idx);
+                return Err(invalid_header(reason));
+            }
+        }
+        Ok(Self {
+            chain: true,
+            ..self
+        })
+    }
+
+    /// Creates a circuit hashing the headers stored as JSON at `paths`. Every file holds an object
+    /// with the RLP encoded header in `rlp`, other fields are ignored.
+    pub fn from_json(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        paths: &[&Path],
+    ) -> Result<Self, Keccak256Error> {
+        let mut headers = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = File::open(path).map_err(|e| {
+                Keccak256Error::io(format!("failed to read header from {}", path.display()), e)
+            })?;
+            let header: HeaderJson =
+                serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+                    Keccak256Error::InputParsing {
+                        name: path.display().to_string(),
+                        reason: e.to_string(),
+                    }
+                })?;
+            headers.push(header.rlp.to_vec());
+        }
+        Self::new(config, num_rows, headers)
+    }
+
+    /// The public fields of every header
+    pub fn fields(&self) -> Vec<HeaderFields> {
+        self.headers
+            .iter()
+            .map(|header| decode_header(&header.rlp).unwrap())
+            .collect()
+    }
+
+    /// The public inputs of the circuit, see [`BlockHeaderCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let halves = |hash: H256| {
+            [
+                F::from_u128(u128::from_be_bytes(hash[16..].try_into().unwrap())),
+                F::from_u128(u128::from_be_bytes(hash[..16].try_into().unwrap())),
+            ]
+        };
+        self.fields()
+            .into_iter()
+            .flat_map(|fields| {
+                [
+                    halves(fields.hash),
+                    halves(fields.parent_hash),
+                    halves(fields.state_root),
+                ]
+                .concat()
+                .into_iter()
+                .chain([F::from(fields.number), F::from(fields.timestamp)])
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use ethers_core::types::H256;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{decode_header, BlockHeaderCircuit};
+
+    fn fixture(number: u64) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(format!("tests/fixtures/mainnet_header_{}.json", number))
+    }
+
+    fn fixture_json(number: u64) -> serde_json::Value {
+        serde_json::from_reader(std::fs::File::open(fixture(number)).unwrap()).unwrap()
+    }
+
+    fn hash(json: &serde_json::Value, field: &str) -> H256 {
+        serde_json::from_value(json[field].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_decode_mainnet_headers() {
+        for number in 0..3 {
+            let json = fixture_json(number);
+            let rlp = hex::decode(json["rlp"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+            let fields = decode_header(&rlp).unwrap();
+            assert_eq!(fields.hash, hash(&json, "hash"));
+            assert_eq!(fields.parent_hash, hash(&json, "parentHash"));
+            assert_eq!(fields.state_root, hash(&json, "stateRoot"));
+            assert_eq!(fields.number, number);
+            let timestamp = json["timestamp"].as_str().unwrap().trim_start_matches("0x");
+            assert_eq!(
+                fields.timestamp,
+                u64::from_str_radix(timestamp, 16).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_block_header() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let path = fixture(1);
+        let circuit =
+            BlockHeaderCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, &[path.as_path()]).unwrap();
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 8);
+        assert_eq!(instance[6], Fr::from(1));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A wrong timestamp
+        let mut wrong_instance = instance;
+        wrong_instance[7] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong_instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_header_chain() {
+        let paths = (0..3).map(fixture).collect::<Vec<_>>();
+        let paths = paths.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+        let circuit = BlockHeaderCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, &paths)
+            .unwrap()
+            .with_chain()
+            .unwrap();
+        let fields = circuit.fields();
+        assert_eq!(fields[1].parent_hash, fields[0].hash);
+        assert_eq!(fields[2].parent_hash, fields[1].hash);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        // Block 2 is not a child of the genesis block
+        let result = BlockHeaderCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, &[paths[0], paths[2]])
+            .unwrap()
+            .with_chain();
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_unchained_headers() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let paths = [fixture(0), fixture(2)];
+        let paths = paths.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+        let circuit = BlockHeaderCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, &paths).unwrap();
+        let fields = circuit.fields();
+        assert_ne!(fields[1].parent_hash, fields[0].hash);
+
+        // Headers that are not a chain are proven without the parent hash constraint
+        let instance = circuit.instance();
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // The parent hash is still bound to the header
+        let mut wrong_instance = instance;
+        wrong_instance[10] = wrong_instance[0];
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong_instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
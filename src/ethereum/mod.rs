@@ -0,0 +1,16 @@
+//! Ethereum specific circuits built on the keccak circuit.
+
+pub mod abi;
+pub mod address;
+pub mod bloom;
+pub mod code;
+pub mod create;
+pub mod eip191;
+pub mod eip712;
+pub mod ens;
+pub mod header;
+pub mod mpt;
+pub mod rlp;
+pub mod signature;
+pub mod storage;
+pub mod transaction;
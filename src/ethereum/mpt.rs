@@ -0,0 +1,313 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use ethers_core::types::{Bytes, EIP1186ProofResponse, H256};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// RLP prefix of a 32 byte string, every hashed child reference starts with it
+const HASH_REFERENCE_PREFIX: u8 = 0xa0;
+
+/// A trie node of the proof and where its digest is referenced in its parent
+#[derive(Default, Clone, Debug)]
+struct TrieNode {
+    rlp: Vec<u8>,
+    /// Index of the parent node and offset of the digest in it, `None` for the root
+    parent: Option<(usize, usize)>,
+}
+
+/// Config of [`MptProofCircuit`]
+#[derive(Clone, Debug)]
+pub struct MptProofConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit verifying the hash links of an `eth_getProof` account and storage proof.
+///
+/// Every trie node of the proof is hashed by the keccak circuit. The digest of every node but the
+/// account root is constrained to appear, after the `0xa0` prefix of a hash reference, at a fixed
+/// offset in its parent: the previous node of the same proof, or the account leaf for the first
+/// node of a storage proof. The public instance is the state root `[lo, hi]`, the digest of the
+/// first account proof node.
+///
+/// The circuit checks the chain of digests, not that the path through the nodes follows the
+/// hashed key, nor the decoded account and storage values. Nodes shorter than 32 bytes are
+/// embedded in their parent instead of hashed and are not supported. The offsets are part of the
+/// circuit, so keys are specific to the shape of a proof.
+#[derive(Default, Clone, Debug)]
+pub struct MptProofCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    nodes: Vec<TrieNode>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for MptProofCircuit<F> {
+    type Config = MptProofConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| TrieNode {
+                    rlp: vec![0; node.rlp.len()],
+                    parent: node.parent,
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Decomposes both the input words and the digest halves
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        MptProofConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self.nodes.iter().map(|node| node.rlp.as_slice()).collect::<Vec<_>>();
+        let (assigned_rows, hashed_nodes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        layouter.assign_region(
+            || "trie node links",
+            |mut region| {
+                let mut offset = 0;
+                for (node, hashed_node) in self.nodes.iter().zip(hashed_nodes.iter()) {
+                    let (parent_idx, digest_offset) = match node.parent {
+                        Some(parent) => parent,
+                        None => continue,
+                    };
+                    let digest = config.bytes.assign_digest(
+                        &mut region,
+                        offset,
+                        &hashed_node.hash_lo,
+                        &hashed_node.hash_hi,
+                    )?;
+                    offset += 2;
+                    let (reference, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        hashed_nodes[parent_idx].first_keccak_f,
+                        digest_offset - 1..digest_offset + 32,
+                    )?;
+                    offset = next_offset;
+
+                    let prefix = F::from(HASH_REFERENCE_PREFIX as u64);
+                    region.constrain_constant(reference[0].cell(), prefix)?;
+                    for (parent_byte, digest_byte) in reference[1..].iter().zip(digest.iter()) {
+                        region.constrain_equal(parent_byte.cell(), digest_byte.cell())?;
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+        let root = hashed_nodes.first().ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(root.hash_lo.cell(), config.keccak.input, 0)?;
+        layouter.constrain_instance(root.hash_hi.cell(), config.keccak.input, 1)?;
+
+        Ok(())
+    }
+}
+
+impl<F: Field> MptProofCircuit<F> {
+    /// Creates a circuit verifying `proof` against `state_root` in `num_rows` rows.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the proof does not hash to `state_root` or a
+    /// node is not referenced by its parent.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        proof: &EIP1186ProofResponse,
+        state_root: H256,
+    ) -> Result<Self, Keccak256Error> {
+        let mut nodes = Vec::new();
+        link_nodes(&mut nodes, "accountProof", &proof.account_proof, None)?;
+        let root = nodes
+            .first()
+            .ok_or_else(|| invalid_proof("accountProof", "the proof is empty"))?;
+        if Keccak256::digest(&root.rlp)[..] != state_root[..] {
+            return Err(invalid_proof(
+                "accountProof",
+                &format!("the first node does not hash to the state root {:?}", state_root),
+            ));
+        }
+        let account_leaf = nodes.len() - 1;
+        for (idx, storage_proof) in proof.storage_proof.iter().enumerate() {
+            let name = format!("storageProof[{}]", idx);
+            link_nodes(&mut nodes, &name, &storage_proof.proof, Some(account_leaf))?;
+        }
+
+        Ok(MptProofCircuit {
+            config,
+            num_rows,
+            nodes,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates a circuit verifying the `eth_getProof` response stored as JSON at `path`.
+    pub fn from_json(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        path: &Path,
+        state_root: H256,
+    ) -> Result<Self, Keccak256Error> {
+        let file = File::open(path).map_err(|e| {
+            Keccak256Error::io(format!("failed to read proof from {}", path.display()), e)
+        })?;
+        let proof: EIP1186ProofResponse = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| Keccak256Error::InputParsing {
+                name: path.display().to_string(),
+                reason: e.to_string(),
+            })?;
+        Self::new(config, num_rows, &proof, state_root)
+    }
+
+    /// The state root of the proof
+    pub fn state_root(&self) -> H256 {
+        H256::from_slice(&Keccak256::digest(&self.nodes[0].rlp))
+    }
+
+    /// The public inputs of the circuit, see [`MptProofCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let root = self.state_root();
+        vec![
+            F::from_u128(u128::from_be_bytes(root[16..].try_into().unwrap())),
+            F::from_u128(u128::from_be_bytes(root[..16].try_into().unwrap())),
+        ]
+    }
+}
+
+fn invalid_proof(name: &str, reason: &str) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Appends the nodes of a proof, each linked to the node before it and the first one to `parent`.
+fn link_nodes(
+    nodes: &mut Vec<TrieNode>,
+    name: &str,
+    proof: &[Bytes],
+    mut parent: Option<usize>,
+) -> Result<(), Keccak256Error> {
+    for (idx, node) in proof.iter().enumerate() {
+        if node.len() < 32 {
+            return Err(invalid_proof(name, &format!("node {} is embedded in its parent", idx)));
+        }
+        let parent_link = match parent {
+            Some(parent_idx) => {
+                let mut reference = vec![HASH_REFERENCE_PREFIX];
+                reference.extend_from_slice(&Keccak256::digest(node));
+                let position = nodes[parent_idx]
+                    .rlp
+                    .windows(reference.len())
+                    .position(|window| window == reference.as_slice())
+                    .ok_or_else(|| {
+                        let reason = format!("node {} is not referenced by its parent", idx);
+                        invalid_proof(name, &reason)
+                    })?;
+                Some((parent_idx, position + 1))
+            }
+            None => None,
+        };
+        nodes.push(TrieNode {
+            rlp: node.to_vec(),
+            parent: parent_link,
+        });
+        parent = Some(nodes.len() - 1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use ethers_core::types::H256;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::MptProofCircuit;
+
+    const STATE_ROOT: &str = "73f34128a3953460529657847f4008c1711f8ac42fbd3028dfd2101e48fd0ebe";
+
+    fn fixture() -> &'static Path {
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/eth_get_proof.json"))
+    }
+
+    fn state_root() -> H256 {
+        H256::from_slice(&hex::decode(STATE_ROOT).unwrap())
+    }
+
+    #[test]
+    fn test_account_and_storage_proof() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let circuit =
+            MptProofCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, fixture(), state_root()).unwrap();
+        assert_eq!(circuit.state_root(), state_root());
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_wrong_state_root() {
+        let result = MptProofCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, fixture(), H256::zero());
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+
+        let circuit =
+            MptProofCircuit::<Fr>::from_json(CONFIG, NUM_ROWS, fixture(), state_root()).unwrap();
+        let mut instance = circuit.instance();
+        instance[0] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_tampered_node_is_rejected() {
+        let mut proof: ethers_core::types::EIP1186ProofResponse =
+            serde_json::from_reader(std::fs::File::open(fixture()).unwrap()).unwrap();
+        let mut leaf = proof.storage_proof[0].proof[1].to_vec();
+        *leaf.last_mut().unwrap() ^= 1;
+        proof.storage_proof[0].proof[1] = leaf.into();
+        let result = MptProofCircuit::<Fr>::new(CONFIG, NUM_ROWS, &proof, state_root());
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+}
@@ -0,0 +1,216 @@
+use std::ops::Range;
+
+use ethers_core::types::U256;
+
+use crate::Keccak256Error;
+
+/// An RLP item, by its position in the encoded bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RlpItem {
+    /// Offset of the first byte of the item, its prefix unless it is a single byte below `0x80`
+    pub offset: usize,
+    /// The bytes of a string, or the encoded items of a list
+    pub content: Range<usize>,
+    pub is_list: bool,
+}
+
+impl RlpItem {
+    /// Whether the item is encoded with a prefix, which is every item but a single byte below `0x80`
+    pub fn has_prefix(&self) -> bool {
+        self.offset != self.content.start
+    }
+
+    /// The content of the item in `data`
+    pub fn value<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.content.clone()]
+    }
+}
+
+fn invalid_rlp(reason: String) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: "rlp".to_string(),
+        reason,
+    }
+}
+
+/// Decodes the item starting at `offset` in `data`, rejecting non-canonical encodings
+pub fn decode(data: &[u8], offset: usize) -> Result<RlpItem, Keccak256Error> {
+    let prefix = *data
+        .get(offset)
+        .ok_or_else(|| invalid_rlp(format!("no item at offset {}", offset)))?;
+    let (is_list, header_len, content_len) = match prefix {
+        0x00..=0x7f => {
+            return Ok(RlpItem {
+                offset,
+                content: offset..offset + 1,
+                is_list: false,
+            })
+        }
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xb8..=0xbf | 0xf8..=0xff => {
+            let is_list = prefix >= 0xf8;
+            let len_of_len = (prefix - if is_list { 0xf7 } else { 0xb7 }) as usize;
+            let len_bytes = data
+                .get(offset + 1..offset + 1 + len_of_len)
+                .ok_or_else(|| invalid_rlp(format!("truncated length at offset {}", offset)))?;
+            if len_bytes[0] == 0 || len_of_len > std::mem::size_of::<usize>() {
+                return Err(invalid_rlp(format!(
+                    "non-canonical length at offset {}",
+                    offset
+                )));
+            }
+            let content_len = len_bytes
+                .iter()
+                .fold(0, |len, byte| (len << 8) | *byte as usize);
+            if content_len < 56 {
+                return Err(invalid_rlp(format!(
+                    "non-canonical length at offset {}",
+                    offset
+                )));
+            }
+            (is_list, 1 + len_of_len, content_len)
+        }
+    };
+
+    let start = offset + header_len;
+    let end = start
+        .checked_add(content_len)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| invalid_rlp(format!("truncated item at offset {}", offset)))?;
+    if !is_list && content_len == 1 && data[start] < 0x80 {
+        return Err(invalid_rlp(format!(
+            "non-canonical single byte at offset {}",
+            offset
+        )));
+    }
+    Ok(RlpItem {
+        offset,
+        content: start..end,
+        is_list,
+    })
+}
+
+/// Decodes `data`, which has to be exactly one list, and returns the items of the list
+pub fn decode_list(data: &[u8]) -> Result<Vec<RlpItem>, Keccak256Error> {
+    let list = decode(data, 0)?;
+    if !list.is_list || list.content.end != data.len() {
+        return Err(invalid_rlp("the data is not a single list".to_string()));
+    }
+    let mut items = vec![];
+    let mut offset = list.content.start;
+    while offset < list.content.end {
+        let item = decode(data, offset)?;
+        offset = item.content.end;
+        items.push(item);
+    }
+    if offset != list.content.end {
+        return Err(invalid_rlp("an item overflows its list".to_string()));
+    }
+    Ok(items)
+}
+
+/// The prefix of an item of `len` bytes, `offset` is `0x80` for strings and `0xc0` for lists
+fn encode_header(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = len.to_be_bytes();
+    let len_bytes = &len_bytes[len.leading_zeros() as usize / 8..];
+    std::iter::once(offset + 55 + len_bytes.len() as u8)
+        .chain(len_bytes.iter().copied())
+        .collect()
+}
+
+/// Encodes `bytes` as a string
+pub fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        return bytes.to_vec();
+    }
+    let mut encoded = encode_header(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// Encodes `value` as a string of its big-endian bytes without leading zeros
+pub fn encode_uint(value: U256) -> Vec<u8> {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    encode_bytes(&bytes[value.leading_zeros() as usize / 8..])
+}
+
+/// Encodes a list of already encoded `items`
+pub fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let len = items.iter().map(|item| item.len()).sum();
+    let mut encoded = encode_header(len, 0xc0);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::types::U256;
+
+    use super::{decode, decode_list, encode_bytes, encode_list, encode_uint, RlpItem};
+
+    #[test]
+    fn test_decode() {
+        // "dog", a single byte and the empty string
+        let dog = [0x83, b'd', b'o', b'g'];
+        assert_eq!(decode(&dog, 0).unwrap().value(&dog), b"dog");
+        let item = decode(&[0x0f], 0).unwrap();
+        assert!(!item.has_prefix());
+        assert_eq!(decode(&[0x80], 0).unwrap().content, 1..1);
+
+        // ["cat", "dog"]
+        let list = [0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g'];
+        let items = decode_list(&list).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                RlpItem {
+                    offset: 1,
+                    content: 2..5,
+                    is_list: false
+                },
+                RlpItem {
+                    offset: 5,
+                    content: 6..9,
+                    is_list: false
+                },
+            ]
+        );
+
+        // A long string
+        let mut long = vec![0xb8, 56];
+        long.extend([7; 56]);
+        assert_eq!(decode(&long, 0).unwrap().content, 2..58);
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_encodings() {
+        // A single byte below 0x80 with a prefix, a short length in the long form, truncated data
+        assert!(decode(&[0x81, 0x05], 0).is_err());
+        assert!(decode(&[0xb8, 0x05, 1, 2, 3, 4, 5], 0).is_err());
+        assert!(decode(&[0x83, b'd', b'o'], 0).is_err());
+        // Trailing bytes after the list
+        assert!(decode_list(&[0xc1, 0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode_bytes(b"dog"), [0x83, b'd', b'o', b'g']);
+        assert_eq!(encode_uint(U256::zero()), [0x80]);
+        assert_eq!(encode_uint(U256::from(0x0f)), [0x0f]);
+        assert_eq!(encode_uint(U256::from(0x0400)), [0x82, 0x04, 0x00]);
+
+        let list = encode_list(&[encode_bytes(b"cat"), encode_bytes(b"dog")]);
+        assert_eq!(list, [0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']);
+        // A long string decodes back to its bytes
+        let long = encode_bytes(&[7; 56]);
+        assert_eq!(long[..2], [0xb8, 56]);
+        assert_eq!(decode(&long, 0).unwrap().value(&long), [7; 56]);
+    }
+}
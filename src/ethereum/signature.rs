@@ -0,0 +1,427 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::H256;
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::Field;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Length of a function selector
+const SELECTOR_LENGTH: usize = 4;
+
+fn invalid_signature(signature: &str, reason: String) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: signature.to_string(),
+        reason,
+    }
+}
+
+/// Canonicalizes a Solidity function, event or error signature to the form that is hashed, like
+/// `function transfer(address to, uint amount) external returns (bool)` to
+/// `transfer(address,uint256)`.
+///
+/// The keyword, parameter names, `indexed`, data locations and everything after the parameters
+/// are dropped, `uint`, `int` and `byte` are expanded and tuples are kept as `(type,...)`. Only
+/// elementary types, tuples and arrays of them are supported, structs and enums have to be given
+/// as tuples and `uint8`.
+pub fn canonical_signature(signature: &str) -> Result<String, Keccak256Error> {
+    let mut signature_body = signature.trim();
+    for keyword in ["function", "event", "error"] {
+        if let Some(rest) = signature_body.strip_prefix(keyword) {
+            if rest.starts_with(char::is_whitespace) {
+                signature_body = rest.trim_start();
+            }
+        }
+    }
+    let open = signature_body
+        .find('(')
+        .ok_or_else(|| invalid_signature(signature, "has no parameter list".to_string()))?;
+    let name = signature_body[..open].trim();
+    let is_identifier = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if name.is_empty() || !is_identifier || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(invalid_signature(
+            signature,
+            format!("`{}` is not a name", name),
+        ));
+    }
+    let close = matching_paren(signature_body, open)
+        .ok_or_else(|| invalid_signature(signature, "has unbalanced parentheses".to_string()))?;
+    let params = canonical_params(&signature_body[open + 1..close])
+        .map_err(|reason| invalid_signature(signature, reason))?;
+    Ok(format!("{}({})", name, params))
+}
+
+/// Index of the parenthesis closing the one at `open`
+fn matching_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, c) in s.char_indices().skip_while(|(idx, _)| *idx < open) {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Canonicalizes a comma separated parameter list
+fn canonical_params(params: &str) -> Result<String, String> {
+    if params.trim().is_empty() {
+        return Ok(String::new());
+    }
+    let mut canonical = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in params.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                canonical.push(canonical_param(&params[start..idx])?);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    canonical.push(canonical_param(&params[start..])?);
+    Ok(canonical.join(","))
+}
+
+/// Canonicalizes a parameter: its type without name, `indexed` or data location
+fn canonical_param(param: &str) -> Result<String, String> {
+    let param = param.trim();
+    let (base, rest) = if param.starts_with('(') {
+        let close = matching_paren(param, 0).ok_or("unbalanced parentheses")?;
+        let base = format!("({})", canonical_params(&param[1..close])?);
+        (base, &param[close + 1..])
+    } else {
+        let end = param
+            .find(|c: char| c == '[' || c.is_whitespace())
+            .unwrap_or(param.len());
+        (canonical_elementary(&param[..end])?, &param[end..])
+    };
+
+    // Array dimensions directly after the type, like `[]` or `[3]`
+    let mut arrays = String::new();
+    let mut rest = rest.trim_start();
+    while let Some(dimension) = rest.strip_prefix('[') {
+        let close = dimension.find(']').ok_or("unbalanced brackets")?;
+        let size = dimension[..close].trim();
+        if !size.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("`{}` is not an array size", size));
+        }
+        arrays.push_str(&format!("[{}]", size));
+        rest = dimension[close + 1..].trim_start();
+    }
+    Ok(base + &arrays)
+}
+
+/// Canonicalizes an elementary type, expanding the aliases
+fn canonical_elementary(ty: &str) -> Result<String, String> {
+    let canonical = match ty {
+        "uint" => "uint256",
+        "int" => "int256",
+        "byte" => "bytes1",
+        _ => ty,
+    };
+    let suffix = |prefix: &str| {
+        let suffix = canonical.strip_prefix(prefix)?;
+        suffix.parse::<usize>().ok()
+    };
+    let is_valid = match canonical {
+        "address" | "bool" | "string" | "bytes" | "function" => true,
+        _ if canonical.starts_with("bytes") => {
+            matches!(suffix("bytes"), Some(size) if (1..=32).contains(&size))
+        }
+        _ if canonical.starts_with("uint") || canonical.starts_with("int") => {
+            let bits = suffix("uint").or_else(|| suffix("int"));
+            matches!(bits, Some(bits) if bits % 8 == 0 && (8..=256).contains(&bits))
+        }
+        _ => false,
+    };
+    match is_valid {
+        true => Ok(canonical.to_string()),
+        false => Err(format!("`{}` is not an elementary type", ty)),
+    }
+}
+
+/// What the digest of a signature is used as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SignatureHash {
+    /// The function selector, the first 4 bytes of the digest
+    #[default]
+    Selector,
+    /// The event topic, the whole digest
+    Topic,
+}
+
+/// Config of [`SignatureHashCircuit`]
+#[derive(Clone, Debug)]
+pub struct SignatureHashConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the function selector or event topic of a canonical signature.
+///
+/// The signature is hashed by the keccak circuit. The public instance is the selector, composed
+/// from the first 4 bytes of the digest, or the topic `[lo, hi]`, followed by the bytes of the
+/// signature, one byte per cell, if the signature is public.
+///
+/// The length of the signature is part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct SignatureHashCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    signature: String,
+    hash: SignatureHash,
+    public_signature: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for SignatureHashCircuit<F> {
+    type Config = SignatureHashConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            signature: "\0".repeat(self.signature.len()),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        SignatureHashConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[self.signature.as_bytes()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let public_cells = layouter.assign_region(
+            || "signature hash",
+            |mut region| {
+                let mut offset = 0;
+                let mut public_cells = match self.hash {
+                    SignatureHash::Selector => {
+                        let digest = config.bytes.assign_digest(
+                            &mut region,
+                            offset,
+                            &hash.hash_lo,
+                            &hash.hash_hi,
+                        )?;
+                        let selector = config.bytes.assign_from_bytes(
+                            &mut region,
+                            offset + 2,
+                            &digest[..SELECTOR_LENGTH],
+                        )?;
+                        offset += 3;
+                        vec![selector]
+                    }
+                    SignatureHash::Topic => vec![hash.hash_lo.clone(), hash.hash_hi.clone()],
+                };
+                if self.public_signature {
+                    let (bytes, _) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        offset,
+                        keccak_config,
+                        &assigned_rows,
+                        hash.first_keccak_f,
+                        0..self.signature.len(),
+                    )?;
+                    public_cells.extend(bytes);
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> SignatureHashCircuit<F> {
+    /// Creates a circuit hashing the canonical form of `signature` in `num_rows` rows, see
+    /// [`canonical_signature`]. The signature is private.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        signature: &str,
+        hash: SignatureHash,
+    ) -> Result<Self, Keccak256Error> {
+        Ok(SignatureHashCircuit {
+            config,
+            num_rows,
+            signature: canonical_signature(signature)?,
+            hash,
+            public_signature: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Makes the bytes of the canonical signature public.
+    pub fn with_public_signature(mut self) -> Self {
+        self.public_signature = true;
+        self
+    }
+
+    /// The canonical signature
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    /// The digest of the canonical signature, the event topic
+    pub fn topic(&self) -> H256 {
+        H256::from_slice(&Keccak256::digest(self.signature.as_bytes()))
+    }
+
+    /// The function selector
+    pub fn selector(&self) -> [u8; SELECTOR_LENGTH] {
+        self.topic()[..SELECTOR_LENGTH].try_into().unwrap()
+    }
+
+    /// The public inputs of the circuit, see [`SignatureHashCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let mut instance = match self.hash {
+            SignatureHash::Selector => {
+                vec![F::from(u32::from_be_bytes(self.selector()) as u64)]
+            }
+            SignatureHash::Topic => {
+                let topic = Word::<F>::from(self.topic());
+                vec![topic.lo(), topic.hi()]
+            }
+        };
+        if self.public_signature {
+            instance.extend(self.signature.bytes().map(|byte| F::from(byte as u64)));
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{canonical_signature, SignatureHash, SignatureHashCircuit};
+
+    #[test]
+    fn test_canonical_signature() {
+        for (signature, canonical) in [
+            (
+                "function transfer(address to, uint amount) external returns (bool)",
+                "transfer(address,uint256)",
+            ),
+            (
+                "event Transfer(address indexed from, address indexed to, uint256 value)",
+                "Transfer(address,address,uint256)",
+            ),
+            (
+                "swap((address,uint24 fee)[] calldata paths, bytes memory data, byte[2][] x)",
+                "swap((address,uint24)[],bytes,bytes1[2][])",
+            ),
+            ("error Unauthorized()", "Unauthorized()"),
+            ("withdraw(address payable to)", "withdraw(address)"),
+        ] {
+            assert_eq!(canonical_signature(signature).unwrap(), canonical);
+        }
+        for signature in [
+            "transfer(address,uint257)",
+            "transfer(address",
+            "(uint256)",
+            "f(MyStruct s)",
+        ] {
+            let result = canonical_signature(signature);
+            assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+        }
+    }
+
+    #[test]
+    fn test_function_selector() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let circuit = SignatureHashCircuit::<Fr>::new(
+            CONFIG,
+            NUM_ROWS,
+            "function transfer(address to, uint256 amount)",
+            SignatureHash::Selector,
+        )
+        .unwrap();
+        assert_eq!(circuit.selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(circuit.instance(), vec![Fr::from(0xa9059cbb)]);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let prover =
+            MockProver::<Fr>::run(CONFIG.k, &circuit, vec![vec![Fr::from(0x095ea7b3)]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_event_topic_with_public_signature() {
+        let circuit = SignatureHashCircuit::<Fr>::new(
+            CONFIG,
+            NUM_ROWS,
+            "event Transfer(address indexed from, address indexed to, uint256 value)",
+            SignatureHash::Topic,
+        )
+        .unwrap()
+        .with_public_signature();
+        assert_eq!(
+            hex::encode(circuit.topic()),
+            "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+        let instance = circuit.instance();
+        assert_eq!(instance.len(), 2 + circuit.signature().len());
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // The signature of another event
+        let mut wrong = instance;
+        wrong[2] = Fr::from(b't' as u64);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
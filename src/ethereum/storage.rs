@@ -0,0 +1,549 @@
+use std::marker::PhantomData;
+
+use ethers_core::types::{Address, H256, U256};
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::compare::{to_u128, WordCells};
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::expression::not;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Largest number of slots of an array element or offset of a struct member, so adding them to a
+/// slot carries at most once into the high half
+const MAX_SLOTS: u64 = 1 << 32;
+
+/// A step from the slot of a variable to the slot of a value inside it, following Solidity's
+/// storage layout
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StorageStep {
+    /// The value of `key` in a mapping, at `keccak256(key . slot)`. Keys of value types are their
+    /// 32 byte ABI encoding, `string` and `bytes` keys their raw bytes.
+    Mapping(Vec<u8>),
+    /// Element `index` of a dynamic array with elements of `element_slots` slots, at
+    /// `keccak256(slot) + index * element_slots`
+    DynamicArray { index: u64, element_slots: u64 },
+    /// Element `index` of a static array with elements of `element_slots` slots, at
+    /// `slot + index * element_slots`
+    StaticArray { index: u64, element_slots: u64 },
+    /// The struct member at `offset` slots from the start of the struct
+    Member(u64),
+}
+
+impl StorageStep {
+    /// The step into a mapping with an integer key
+    pub fn uint_key(key: U256) -> Self {
+        let mut encoded = vec![0; 32];
+        key.to_big_endian(&mut encoded);
+        StorageStep::Mapping(encoded)
+    }
+
+    /// The step into a mapping with an address key
+    pub fn address_key(key: Address) -> Self {
+        let mut encoded = vec![0; 12];
+        encoded.extend_from_slice(key.as_bytes());
+        StorageStep::Mapping(encoded)
+    }
+
+    /// The step with its private key or index zeroed, the shape of the circuit stays the same
+    fn without_witness(&self) -> Self {
+        match self {
+            StorageStep::Mapping(key) => StorageStep::Mapping(vec![0; key.len()]),
+            StorageStep::DynamicArray { element_slots, .. } => StorageStep::DynamicArray {
+                index: 0,
+                element_slots: *element_slots,
+            },
+            StorageStep::StaticArray { element_slots, .. } => StorageStep::StaticArray {
+                index: 0,
+                element_slots: *element_slots,
+            },
+            StorageStep::Member(offset) => StorageStep::Member(*offset),
+        }
+    }
+
+    /// The input hashed by the step from `slot`, if any
+    fn hash_input(&self, slot: H256) -> Option<Vec<u8>> {
+        match self {
+            StorageStep::Mapping(key) => Some([key.as_slice(), slot.as_bytes()].concat()),
+            StorageStep::DynamicArray { .. } => Some(slot.as_bytes().to_vec()),
+            _ => None,
+        }
+    }
+
+    /// The index, number of slots per index and offset added to the slot by the step, if any
+    fn addition(&self) -> Option<(u64, u64, u64)> {
+        match self {
+            StorageStep::Mapping(_) => None,
+            StorageStep::DynamicArray {
+                index,
+                element_slots,
+            }
+            | StorageStep::StaticArray {
+                index,
+                element_slots,
+            } => Some((*index, *element_slots, 0)),
+            StorageStep::Member(offset) => Some((0, 0, *offset)),
+        }
+    }
+}
+
+/// Computes the slot reached by following `path` from `slot` natively.
+pub fn storage_slot(slot: H256, path: &[StorageStep]) -> H256 {
+    path.iter().fold(slot, |slot, step| {
+        let slot = match step.hash_input(slot) {
+            Some(input) => H256::from_slice(&Keccak256::digest(input)),
+            None => slot,
+        };
+        match step.addition() {
+            Some((index, size, offset)) => {
+                let addend = U256::from(index as u128 * size as u128 + offset as u128);
+                let (sum, _) = U256::from_big_endian(slot.as_bytes()).overflowing_add(addend);
+                let mut bytes = [0; 32];
+                sum.to_big_endian(&mut bytes);
+                H256(bytes)
+            }
+            None => slot,
+        }
+    })
+}
+
+/// Config of the slot additions of array elements and struct members.
+///
+/// An enabled row adds `index * size + offset` to `slot`, with `size` and `offset` fixed, into
+/// `out` modulo `2^256`. The halves of `out` and the index are range checked by decompositions
+/// into bytes, the index to 8 bytes, so the addend stays below `2^128`.
+#[derive(Clone, Debug)]
+pub struct SlotConfig<F> {
+    q_enable: Column<Fixed>,
+    size: Column<Fixed>,
+    offset: Column<Fixed>,
+    slot: [Column<Advice>; 2],
+    index: Column<Advice>,
+    carry: Column<Advice>,
+    overflow: Column<Advice>,
+    out: [Column<Advice>; 2],
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> SlotConfig<F> {
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self {
+        let q_enable = meta.fixed_column();
+        let size = meta.fixed_column();
+        let offset = meta.fixed_column();
+        let slot = [(); 2].map(|_| meta.advice_column());
+        let index = meta.advice_column();
+        let carry = meta.advice_column();
+        let overflow = meta.advice_column();
+        let out = [(); 2].map(|_| meta.advice_column());
+        for column in [index].into_iter().chain(slot).chain(out) {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("slot addition", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let size = meta.query_fixed(size, Rotation::cur());
+            let offset = meta.query_fixed(offset, Rotation::cur());
+            let [slot_lo, slot_hi] = slot.map(|column| meta.query_advice(column, Rotation::cur()));
+            let index = meta.query_advice(index, Rotation::cur());
+            let carry = meta.query_advice(carry, Rotation::cur());
+            let overflow = meta.query_advice(overflow, Rotation::cur());
+            let [out_lo, out_hi] = out.map(|column| meta.query_advice(column, Rotation::cur()));
+            let two_pow_128 = F::from_u128(1 << 64) * F::from_u128(1 << 64);
+            [
+                carry.clone() * not::expr(carry.clone()),
+                overflow.clone() * not::expr(overflow.clone()),
+                slot_lo + index * size + offset - out_lo - carry.clone() * two_pow_128,
+                slot_hi + carry - out_hi - overflow * two_pow_128,
+            ]
+            .map(|constraint| q_enable.clone() * constraint)
+        });
+
+        SlotConfig {
+            q_enable,
+            size,
+            offset,
+            slot,
+            index,
+            carry,
+            overflow,
+            out,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Assigns the constant `slot` to the output of the row at `offset`, a start for the additions
+    fn assign_start<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        slot: H256,
+    ) -> Result<WordCells<'v, F>, Error> {
+        let word = Word::<F>::from(slot);
+        let lo = raw_assign_advice(region, self.out[0], offset, Value::known(word.lo()))?;
+        let hi = raw_assign_advice(region, self.out[1], offset, Value::known(word.hi()))?;
+        region.constrain_constant(lo.cell(), word.lo())?;
+        region.constrain_constant(hi.cell(), word.hi())?;
+        Ok(Word::new([lo, hi]))
+    }
+
+    /// Adds `index * size + member_offset` to `slot` at `offset`, the range checks take the three
+    /// rows of `bytes` from `bytes_offset` on. Returns the resulting slot.
+    fn assign_addition<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bytes: &BytesConfig<F>,
+        bytes_offset: usize,
+        slot: &WordCells<'v, F>,
+        (index, size, member_offset): (u64, u64, u64),
+    ) -> Result<WordCells<'v, F>, Error> {
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        raw_assign_fixed(region, self.size, offset, F::from(size))?;
+        raw_assign_fixed(region, self.offset, offset, F::from(member_offset))?;
+        let mut halves = vec![];
+        for (column, cell) in self.slot.into_iter().zip([slot.lo(), slot.hi()]) {
+            let value = cell.value().map(|value| value.evaluate());
+            let copied = raw_assign_advice(region, column, offset, value)?;
+            region.constrain_equal(cell.cell(), copied.cell())?;
+            halves.push(value.map(|value| to_u128(&value)));
+        }
+
+        let addend = index as u128 * size as u128 + member_offset as u128;
+        let out_lo = halves[0].map(|lo| lo.overflowing_add(addend));
+        let out_hi = halves[1]
+            .zip(out_lo)
+            .map(|(hi, (_, carry))| hi.overflowing_add(carry as u128));
+        let index = raw_assign_advice(region, self.index, offset, Value::known(F::from(index)))?;
+        raw_assign_advice(
+            region,
+            self.carry,
+            offset,
+            out_lo.map(|(_, carry)| F::from(carry)),
+        )?;
+        raw_assign_advice(
+            region,
+            self.overflow,
+            offset,
+            out_hi.map(|(_, over)| F::from(over)),
+        )?;
+        let out = Word::new([
+            raw_assign_advice(
+                region,
+                self.out[0],
+                offset,
+                out_lo.map(|(lo, _)| F::from_u128(lo)),
+            )?,
+            raw_assign_advice(
+                region,
+                self.out[1],
+                offset,
+                out_hi.map(|(hi, _)| F::from_u128(hi)),
+            )?,
+        ]);
+
+        // The index fits in 8 bytes, the halves of the result in 16
+        let index_bytes = bytes.assign_word(region, bytes_offset, &index)?;
+        for byte in index_bytes[8..].iter() {
+            region.constrain_constant(byte.cell(), F::ZERO)?;
+        }
+        bytes.assign_word(region, bytes_offset + 1, &out.lo())?;
+        bytes.assign_word(region, bytes_offset + 2, &out.hi())?;
+        Ok(out)
+    }
+}
+
+/// Config of [`StorageSlotCircuit`]
+#[derive(Clone, Debug)]
+pub struct StorageSlotConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    pub slot: SlotConfig<F>,
+}
+
+/// Circuit computing the storage slot of a value from the slot of its variable and a path of
+/// mapping keys, array indices and struct members.
+///
+/// Every mapping key and dynamic array hashes the current slot with the keccak circuit, the bytes
+/// of the slot are constrained to the input words of the hash and its digest becomes the next
+/// slot. Array indices and struct members are added to the slot in the circuit. The public
+/// instance is the final slot as a [`Word`] `[lo, hi]`.
+///
+/// The slot of the variable, the kinds of the steps, the key lengths, the element sizes and the
+/// member offsets are part of the circuit. The keys and indices are private.
+#[derive(Default, Clone, Debug)]
+pub struct StorageSlotCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    slot: H256,
+    path: Vec<StorageStep>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for StorageSlotCircuit<F> {
+    type Config = StorageSlotConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            path: self
+                .path
+                .iter()
+                .map(|step| step.without_witness())
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        let slot = SlotConfig::configure(meta);
+        StorageSlotConfig {
+            keccak,
+            bytes,
+            slot,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let mut inputs = vec![];
+        let mut slot = self.slot;
+        for (idx, step) in self.path.iter().enumerate() {
+            inputs.extend(step.hash_input(slot));
+            slot = storage_slot(slot, &self.path[idx..idx + 1]);
+        }
+        let inputs = inputs
+            .iter()
+            .map(|input| input.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        let slot = layouter.assign_region(
+            || "storage slot",
+            |mut region| {
+                let mut slot = config.slot.assign_start(&mut region, 0, self.slot)?;
+                let mut hashes = hashes.iter();
+                let mut offset = 1;
+                let mut bytes_offset = 0;
+                for step in self.path.iter() {
+                    let key_len = match step {
+                        StorageStep::Mapping(key) => Some(key.len()),
+                        StorageStep::DynamicArray { .. } => Some(0),
+                        _ => None,
+                    };
+                    // The slot is the input after the key, the digest is the next slot
+                    if let Some(key_len) = key_len {
+                        let hash = hashes.next().ok_or(Error::Synthesis)?;
+                        let slot_bytes = config.bytes.assign_digest(
+                            &mut region,
+                            bytes_offset,
+                            &slot.lo(),
+                            &slot.hi(),
+                        )?;
+                        let (input_bytes, next_offset) = config.bytes.assign_input_bytes(
+                            &mut region,
+                            bytes_offset + 2,
+                            keccak_config,
+                            &assigned_rows,
+                            hash.first_keccak_f,
+                            key_len..key_len + 32,
+                        )?;
+                        bytes_offset = next_offset;
+                        for (slot_byte, input_byte) in slot_bytes.iter().zip(input_bytes.iter()) {
+                            region.constrain_equal(slot_byte.cell(), input_byte.cell())?;
+                        }
+                        slot = Word::new([hash.hash_lo.clone(), hash.hash_hi.clone()]);
+                    }
+                    if let Some(addition) = step.addition() {
+                        slot = config.slot.assign_addition(
+                            &mut region,
+                            offset,
+                            &config.bytes,
+                            bytes_offset,
+                            &slot,
+                            addition,
+                        )?;
+                        offset += 1;
+                        bytes_offset += 3;
+                    }
+                }
+                Ok(slot)
+            },
+        )?;
+
+        layouter.constrain_instance(slot.lo().cell(), config.keccak.input, 0)?;
+        layouter.constrain_instance(slot.hi().cell(), config.keccak.input, 1)?;
+
+        Ok(())
+    }
+}
+
+impl<F: Field> StorageSlotCircuit<F> {
+    /// Creates a circuit following `path` from the variable at `slot` in `num_rows` rows.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the path is empty or an element size or member
+    /// offset is `2^32` or more.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        slot: H256,
+        path: Vec<StorageStep>,
+    ) -> Result<Self, Keccak256Error> {
+        if path.is_empty() {
+            return Err(Keccak256Error::InputParsing {
+                name: "path".to_string(),
+                reason: "the storage path is empty".to_string(),
+            });
+        }
+        for (idx, step) in path.iter().enumerate() {
+            if let Some((_, size, offset)) = step.addition() {
+                if size >= MAX_SLOTS || offset >= MAX_SLOTS {
+                    return Err(Keccak256Error::InputParsing {
+                        name: format!("path[{}]", idx),
+                        reason: format!("{} slots are too many", size.max(offset)),
+                    });
+                }
+            }
+        }
+
+        Ok(StorageSlotCircuit {
+            config,
+            num_rows,
+            slot,
+            path,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The storage slot at the end of the path
+    pub fn storage_slot(&self) -> H256 {
+        storage_slot(self.slot, &self.path)
+    }
+
+    /// The public inputs of the circuit, see [`StorageSlotCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let slot = Word::<F>::from(self.storage_slot());
+        vec![slot.lo(), slot.hi()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers_core::types::{Address, H256, U256};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{storage_slot, StorageSlotCircuit, StorageStep};
+
+    fn slot(value: u64) -> H256 {
+        H256::from_low_u64_be(value)
+    }
+
+    #[test]
+    fn test_native_storage_slot() {
+        // `mapping(address => uint256) balances` at slot 3
+        let holder = Address::from_low_u64_be(0xbeef);
+        let mut input = [0; 64];
+        input[12..32].copy_from_slice(holder.as_bytes());
+        input[63] = 3;
+        let expected = H256::from_slice(&Keccak256::digest(input));
+        assert_eq!(
+            storage_slot(slot(3), &[StorageStep::address_key(holder)]),
+            expected
+        );
+
+        // Element 5 of a dynamic array of two slot structs at slot 1, second member
+        let start = U256::from_big_endian(&Keccak256::digest(slot(1)));
+        let element = StorageStep::DynamicArray {
+            index: 5,
+            element_slots: 2,
+        };
+        let path = [element, StorageStep::Member(1)];
+        let expected = start + U256::from(11);
+        assert_eq!(
+            U256::from_big_endian(storage_slot(slot(1), &path).as_bytes()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_nested_storage_slot() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        // `mapping(string => mapping(uint256 => S[4])) data` at slot 7, member 1 of element 2
+        let path = vec![
+            StorageStep::Mapping(b"owner".to_vec()),
+            StorageStep::uint_key(U256::from(42)),
+            StorageStep::StaticArray {
+                index: 2,
+                element_slots: 3,
+            },
+            StorageStep::Member(1),
+        ];
+        let circuit = StorageSlotCircuit::<Fr>::new(CONFIG, NUM_ROWS, slot(7), path).unwrap();
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let mut instance = circuit.instance();
+        instance[0] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_slot_addition_carry_and_overflow() {
+        // The last slot wraps around to slot 0, which is then hashed with a key
+        let base = H256([0xff; 32]);
+        let path = vec![StorageStep::Member(1), StorageStep::uint_key(U256::one())];
+        let mut input = [0; 64];
+        input[31] = 1;
+        let expected = H256::from_slice(&Keccak256::digest(input));
+        assert_eq!(storage_slot(base, &path), expected);
+
+        let circuit = StorageSlotCircuit::<Fr>::new(CONFIG, NUM_ROWS, base, path).unwrap();
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        // A carry into the high half only
+        let mut base = [0xff; 32];
+        base[..16].copy_from_slice(&[0; 16]);
+        let path = vec![StorageStep::StaticArray {
+            index: u64::MAX,
+            element_slots: 3,
+        }];
+        let circuit = StorageSlotCircuit::<Fr>::new(CONFIG, NUM_ROWS, H256(base), path).unwrap();
+        assert_eq!(circuit.storage_slot()[15], 1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let too_large = StorageStep::Member(1 << 32);
+        let result = StorageSlotCircuit::<Fr>::new(CONFIG, NUM_ROWS, slot(0), vec![too_large]);
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::Path;
+
+use ethers_core::types::{Address, Bytes, H256, U256, U64};
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::ethereum::rlp::{decode, decode_list, encode_bytes, encode_list, encode_uint, RlpItem};
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::eth_types::{Field, ToScalar};
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// Length of a function selector at the start of the call data
+const SELECTOR_LENGTH: usize = 4;
+
+/// An entry of the access list of an EIP-2930 or EIP-1559 transaction
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+/// A signed transaction, as returned by `eth_getTransactionByHash`.
+///
+/// `type` selects the envelope: 0 for legacy transactions, 1 for EIP-2930 and 2 for EIP-1559.
+/// Fields the type does not use are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transaction {
+    #[serde(rename = "type", default)]
+    pub tx_type: U64,
+    #[serde(default)]
+    pub chain_id: Option<U256>,
+    pub nonce: U256,
+    #[serde(default)]
+    pub gas_price: Option<U256>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<U256>,
+    pub gas: U256,
+    /// The recipient, `None` for contract creations
+    pub to: Option<Address>,
+    pub value: U256,
+    pub input: Bytes,
+    #[serde(default)]
+    pub access_list: Vec<AccessListItem>,
+    pub v: U64,
+    pub r: U256,
+    pub s: U256,
+}
+
+/// A field of a transaction that [`TransactionCircuit`] can make public
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxField {
+    Nonce,
+    /// The recipient, as the scalar of [`ToScalar`] for [`Address`]
+    To,
+    /// The value `[lo, hi]`
+    Value,
+    /// The first 4 bytes of the call data
+    Selector,
+}
+
+fn invalid_transaction(reason: String) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: "transaction".to_string(),
+        reason,
+    }
+}
+
+impl Transaction {
+    /// Reads a transaction stored as JSON at `path`.
+    pub fn from_json(path: &Path) -> Result<Self, Keccak256Error> {
+        let file = File::open(path).map_err(|e| {
+            Keccak256Error::io(
+                format!("failed to read transaction from {}", path.display()),
+                e,
+            )
+        })?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| Keccak256Error::InputParsing {
+            name: path.display().to_string(),
+            reason: e.to_string(),
+        })
+    }
+
+    /// The signed encoding: `rlp(fields)` for legacy transactions, `type || rlp(fields)` for
+    /// typed ones
+    pub fn encode(&self) -> Result<Vec<u8>, Keccak256Error> {
+        let required = |value: Option<U256>, name: &str| {
+            value.map(encode_uint).ok_or_else(|| {
+                invalid_transaction(format!(
+                    "a type {} transaction needs {}",
+                    self.tx_type, name
+                ))
+            })
+        };
+        let to = encode_bytes(self.to.as_ref().map_or(&[][..], |to| to.as_bytes()));
+        let tail = [to, encode_uint(self.value), encode_bytes(&self.input)];
+        let access_list = encode_list(
+            &self
+                .access_list
+                .iter()
+                .map(|item| {
+                    let keys = item
+                        .storage_keys
+                        .iter()
+                        .map(|key| encode_bytes(key.as_bytes()));
+                    encode_list(&[
+                        encode_bytes(item.address.as_bytes()),
+                        encode_list(&keys.collect::<Vec<_>>()),
+                    ])
+                })
+                .collect::<Vec<_>>(),
+        );
+        let signature = [
+            encode_uint(self.v.as_u64().into()),
+            encode_uint(self.r),
+            encode_uint(self.s),
+        ];
+
+        let mut fields = vec![];
+        match self.tx_type.as_u64() {
+            0 => {
+                fields.push(encode_uint(self.nonce));
+                fields.push(required(self.gas_price, "gasPrice")?);
+                fields.push(encode_uint(self.gas));
+                fields.extend(tail);
+            }
+            1 => {
+                fields.push(required(self.chain_id, "chainId")?);
+                fields.push(encode_uint(self.nonce));
+                fields.push(required(self.gas_price, "gasPrice")?);
+                fields.push(encode_uint(self.gas));
+                fields.extend(tail);
+                fields.push(access_list);
+            }
+            2 => {
+                fields.push(required(self.chain_id, "chainId")?);
+                fields.push(encode_uint(self.nonce));
+                fields.push(required(
+                    self.max_priority_fee_per_gas,
+                    "maxPriorityFeePerGas",
+                )?);
+                fields.push(required(self.max_fee_per_gas, "maxFeePerGas")?);
+                fields.push(encode_uint(self.gas));
+                fields.extend(tail);
+                fields.push(access_list);
+            }
+            tx_type => {
+                return Err(invalid_transaction(format!(
+                    "type {} is not supported",
+                    tx_type
+                )))
+            }
+        }
+        fields.extend(signature);
+
+        let mut encoded = match self.tx_type.as_u64() {
+            0 => vec![],
+            tx_type => vec![tx_type as u8],
+        };
+        encoded.extend(encode_list(&fields));
+        Ok(encoded)
+    }
+
+    /// The transaction hash, the digest of [`Transaction::encode`]
+    pub fn hash(&self) -> Result<H256, Keccak256Error> {
+        Ok(H256::from_slice(&Keccak256::digest(self.encode()?)))
+    }
+
+    /// Index of `field` in the RLP list of the transaction, the selector is in the call data
+    fn field_index(&self, field: TxField) -> usize {
+        // Typed transactions start with the chain id, EIP-1559 ones have two fee fields
+        let shift = match self.tx_type.as_u64() {
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        };
+        match field {
+            TxField::Nonce => shift.min(1),
+            TxField::To => 3 + shift,
+            TxField::Value => 4 + shift,
+            TxField::Selector => 5 + shift,
+        }
+    }
+}
+
+/// Config of [`TransactionCircuit`]
+#[derive(Clone, Debug)]
+pub struct TransactionConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+}
+
+/// Circuit proving the hash of a transaction while making chosen fields public.
+///
+/// The encoded transaction is hashed by the keccak circuit. Its type byte, the prefix of its RLP
+/// list and the prefixes of all fields are constrained to constants, so the public fields are read
+/// from their positions in the list. The public instance is the transaction hash `[lo, hi]`
+/// followed by the [`TxField`]s in the order they were chosen, all other fields stay private.
+///
+/// The type and the lengths of the encoded fields are part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct TransactionCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    transaction: Transaction,
+    encoded: Vec<u8>,
+    /// The type byte, the prefix of the RLP list and the prefixes of its fields, by their
+    /// positions in `encoded`
+    prefixes: Vec<(usize, u8)>,
+    /// The fields of the RLP list, at their positions in `encoded`
+    fields: Vec<RlpItem>,
+    public_fields: Vec<TxField>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for TransactionCircuit<F> {
+    type Config = TransactionConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        // The type shapes the circuit
+        let transaction = Transaction {
+            tx_type: self.transaction.tx_type,
+            ..Default::default()
+        };
+        Self {
+            transaction,
+            encoded: vec![0; self.encoded.len()],
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Wide enough to compose a recipient
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, Address::len_bytes());
+        TransactionConfig { keccak, bytes }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[self.encoded.as_slice()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let public_cells = layouter.assign_region(
+            || "transaction fields",
+            |mut region| {
+                // The prefixes and the content of the public fields, in order
+                let byte_indices = self
+                    .prefixes
+                    .iter()
+                    .map(|(byte_idx, _)| *byte_idx)
+                    .chain(
+                        self.public_fields
+                            .iter()
+                            .flat_map(|field| self.public_range(*field)),
+                    )
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                let (cells, mut offset) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    0,
+                    keccak_config,
+                    &assigned_rows,
+                    hash.first_keccak_f,
+                    byte_indices.iter().copied(),
+                )?;
+                let cell = |byte_idx: usize| {
+                    let position = byte_indices.binary_search(&byte_idx);
+                    position
+                        .map(|position| cells[position].clone())
+                        .or(Err(Error::Synthesis))
+                };
+                for &(byte_idx, prefix) in self.prefixes.iter() {
+                    region.constrain_constant(cell(byte_idx)?.cell(), F::from(prefix as u64))?;
+                }
+
+                let mut public_cells = vec![hash.hash_lo.clone(), hash.hash_hi.clone()];
+                for field in self.public_fields.iter() {
+                    let range = self.public_range(*field);
+                    let bytes = range.map(cell).collect::<Result<Vec<_>, _>>()?;
+                    // The value is split into `[lo, hi]` at its 16 low bytes
+                    let parts = match field {
+                        TxField::Value => {
+                            let split = bytes.len().saturating_sub(16);
+                            vec![&bytes[split..], &bytes[..split]]
+                        }
+                        _ => vec![bytes.as_slice()],
+                    };
+                    for part in parts {
+                        public_cells.push(config.bytes.assign_from_bytes(
+                            &mut region,
+                            offset,
+                            part,
+                        )?);
+                        offset += 1;
+                    }
+                }
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> TransactionCircuit<F> {
+    /// Creates a circuit hashing `transaction` in `num_rows` rows that makes `public_fields`
+    /// public.
+    ///
+    /// Fails if the transaction cannot be encoded, or a public field is missing: the recipient of
+    /// a contract creation or the selector of call data shorter than 4 bytes.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        transaction: Transaction,
+        public_fields: Vec<TxField>,
+    ) -> Result<Self, Keccak256Error> {
+        let encoded = transaction.encode()?;
+        let list_offset = (transaction.tx_type.as_u64() != 0) as usize;
+        let list = decode(&encoded, list_offset)?;
+        let fields = decode_list(&encoded[list_offset..])?
+            .into_iter()
+            .map(|field| RlpItem {
+                offset: field.offset + list_offset,
+                content: field.content.start + list_offset..field.content.end + list_offset,
+                is_list: field.is_list,
+            })
+            .collect::<Vec<_>>();
+        let prefixes = (0..list.content.start)
+            .chain(
+                fields
+                    .iter()
+                    .flat_map(|field| field.offset..field.content.start),
+            )
+            .map(|byte_idx| (byte_idx, encoded[byte_idx]))
+            .collect();
+        let circuit = TransactionCircuit {
+            config,
+            num_rows,
+            transaction,
+            encoded,
+            prefixes,
+            fields,
+            public_fields,
+            _marker: PhantomData,
+        };
+        for field in circuit.public_fields.iter() {
+            let content = &circuit.fields[circuit.transaction.field_index(*field)].content;
+            let missing = match field {
+                TxField::To => content.is_empty(),
+                TxField::Selector => content.len() < SELECTOR_LENGTH,
+                _ => false,
+            };
+            if missing {
+                return Err(invalid_transaction(format!(
+                    "the transaction has no {:?}",
+                    field
+                )));
+            }
+        }
+        Ok(circuit)
+    }
+
+    /// Creates a circuit for the transaction stored as JSON at `path`, see
+    /// [`TransactionCircuit::new`].
+    pub fn from_json(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        path: &Path,
+        public_fields: Vec<TxField>,
+    ) -> Result<Self, Keccak256Error> {
+        Self::new(
+            config,
+            num_rows,
+            Transaction::from_json(path)?,
+            public_fields,
+        )
+    }
+
+    /// Byte range of the public value of `field` in the encoded transaction
+    fn public_range(&self, field: TxField) -> Range<usize> {
+        let content = self.fields[self.transaction.field_index(field)]
+            .content
+            .clone();
+        match field {
+            TxField::Selector => content.start..content.start + SELECTOR_LENGTH,
+            _ => content,
+        }
+    }
+
+    /// The transaction hash
+    pub fn hash(&self) -> H256 {
+        H256::from_slice(&Keccak256::digest(&self.encoded))
+    }
+
+    /// The public inputs of the circuit, see [`TransactionCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let hash = Word::<F>::from(self.hash());
+        let mut instance = vec![hash.lo(), hash.hi()];
+        for field in self.public_fields.iter() {
+            let transaction = &self.transaction;
+            match field {
+                TxField::Nonce => instance.push(
+                    transaction
+                        .nonce
+                        .to_scalar()
+                        .expect("the nonce is smaller than the field"),
+                ),
+                TxField::To => instance.push(
+                    transaction
+                        .to
+                        .unwrap_or_default()
+                        .to_scalar()
+                        .expect("an address is smaller than the field"),
+                ),
+                TxField::Value => {
+                    let value = Word::<F>::from(transaction.value);
+                    instance.extend([value.lo(), value.hi()]);
+                }
+                TxField::Selector => {
+                    let selector = &transaction.input[..SELECTOR_LENGTH];
+                    instance.push(F::from(
+                        u32::from_be_bytes(selector.try_into().unwrap()) as u64
+                    ));
+                }
+            }
+        }
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use ethers_core::types::{Address, H256, U256, U64};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{Transaction, TransactionCircuit, TxField};
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/{}.json", name))
+    }
+
+    /// The hash stored next to the transaction in a fixture
+    fn fixture_hash(name: &str) -> H256 {
+        let file = std::fs::File::open(fixture(name)).unwrap();
+        let json: serde_json::Value = serde_json::from_reader(file).unwrap();
+        serde_json::from_value(json["hash"].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_eip155_example() {
+        // The signed transaction of the EIP-155 example
+        let transaction = Transaction {
+            nonce: 9.into(),
+            gas_price: Some(U256::from(20) * U256::exp10(9)),
+            gas: 21000.into(),
+            to: Some(Address::repeat_byte(0x35)),
+            value: U256::exp10(18),
+            v: U64::from(37),
+            r: U256::from_str_radix(
+                "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276",
+                16,
+            )
+            .unwrap(),
+            s: U256::from_str_radix(
+                "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+                16,
+            )
+            .unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            hex::encode(transaction.encode().unwrap()),
+            "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7640000\
+             8025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f\
+             761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+    }
+
+    #[test]
+    fn test_transaction_types() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let public_fields = vec![TxField::Nonce, TxField::To, TxField::Value];
+        for name in ["tx_legacy", "tx_eip2930", "tx_eip1559"] {
+            let circuit = TransactionCircuit::<Fr>::from_json(
+                CONFIG,
+                NUM_ROWS,
+                &fixture(name),
+                public_fields.clone(),
+            )
+            .unwrap();
+            assert_eq!(circuit.hash(), fixture_hash(name));
+
+            let instance = circuit.instance();
+            assert_eq!(instance.len(), 6);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+            prover.assert_satisfied();
+        }
+    }
+
+    #[test]
+    fn test_public_selector() {
+        let circuit = TransactionCircuit::<Fr>::from_json(
+            CONFIG,
+            NUM_ROWS,
+            &fixture("tx_eip1559"),
+            vec![TxField::Selector, TxField::Value],
+        )
+        .unwrap();
+        let instance = circuit.instance();
+        // swapExactTokensForTokens(uint256,uint256,address[],address,uint256)
+        assert_eq!(instance[2], Fr::from(0x38ed1739));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A different selector or value
+        for idx in [2, 3] {
+            let mut wrong = instance.clone();
+            wrong[idx] += Fr::from(1);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+    }
+
+    #[test]
+    fn test_missing_public_field() {
+        // The EIP-2930 fixture has no call data
+        let result = TransactionCircuit::<Fr>::from_json(
+            CONFIG,
+            NUM_ROWS,
+            &fixture("tx_eip2930"),
+            vec![TxField::Selector],
+        );
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Region, Value};
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Fixed, TableColumn};
+use halo2_proofs::poly::Rotation;
+
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::expression::from_bytes;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::param::NUM_BYTES_PER_WORD;
+use crate::vanilla::{KeccakAssignedRow, KeccakCircuitConfig};
+
+/// Decomposes values of up to `num_bytes` bytes, like the keccak `word_value`s or the halves of a
+/// digest, into range checked little-endian bytes.
+///
+/// Every enabled row holds a value and its bytes, least significant first. The bytes are looked
+/// up in a byte table, so the decomposition is unique as long as `num_bytes` bytes fit in the
+/// field.
+#[derive(Clone, Debug)]
+pub struct BytesConfig<F> {
+    q_enable: Column<Fixed>,
+    word: Column<Advice>,
+    bytes: Vec<Column<Advice>>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> BytesConfig<F> {
+    /// Configures the decomposition into `num_bytes` bytes, `byte_table` has to hold exactly the
+    /// values `0..256`.
+    pub fn configure(
+        meta: &mut ConstraintSystem<F>,
+        byte_table: TableColumn,
+        num_bytes: usize,
+    ) -> Self {
+        let q_enable = meta.fixed_column();
+        let word = meta.advice_column();
+        let bytes = (0..num_bytes).map(|_| meta.advice_column()).collect::<Vec<_>>();
+        meta.enable_equality(word);
+        for &column in bytes.iter() {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("word bytes", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let word = meta.query_advice(word, Rotation::cur());
+            let bytes = bytes
+                .iter()
+                .map(|column| meta.query_advice(*column, Rotation::cur()))
+                .collect::<Vec<_>>();
+            vec![q_enable * (from_bytes::expr(&bytes) - word)]
+        });
+        for &column in bytes.iter() {
+            meta.lookup("byte range", |meta| {
+                let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+                let byte = meta.query_advice(column, Rotation::cur());
+                vec![(q_enable * byte, byte_table)]
+            });
+        }
+
+        BytesConfig {
+            q_enable,
+            word,
+            bytes,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Decomposes the value in `word_cell` at `offset`, returns the cells of its bytes,
+    /// least significant first. The value is copied, so the bytes are bound to the original cell.
+    pub fn assign_word<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        word_cell: &Halo2AssignedCell<'v, F>,
+    ) -> Result<Vec<Halo2AssignedCell<'v, F>>, Error> {
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        let word_value = word_cell.value().map(|word| word.evaluate());
+        let word = raw_assign_advice(region, self.word, offset, word_value)?;
+        region.constrain_equal(word_cell.cell(), word.cell())?;
+
+        let bytes: Value<Vec<u8>> = word_value.map(|word| word.to_bytes_le());
+        self.bytes
+            .iter()
+            .enumerate()
+            .map(|(idx, column)| {
+                let byte = bytes.as_ref().map(|bytes| F::from(bytes[idx] as u64));
+                raw_assign_advice(region, *column, offset, byte)
+            })
+            .collect()
+    }
+
+    /// Composes the big-endian bytes in `byte_cells`, at most `num_bytes` of them, into a value at
+    /// `offset` and returns its cell. The bytes are copied, the remaining high bytes are constrained
+    /// to the constant 0, so the circuit has to enable a constant column.
+    pub fn assign_from_bytes<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        byte_cells: &[Halo2AssignedCell<'v, F>],
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        if byte_cells.len() > self.bytes.len() {
+            log::error!(
+                "{} bytes do not fit in a decomposition into {} bytes",
+                byte_cells.len(),
+                self.bytes.len()
+            );
+            return Err(Error::Synthesis);
+        }
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        let mut word_value = Value::known(F::ZERO);
+        let mut multiplier = F::ONE;
+        for (idx, column) in self.bytes.iter().enumerate() {
+            match byte_cells.len().checked_sub(idx + 1) {
+                Some(byte_idx) => {
+                    let byte_cell = &byte_cells[byte_idx];
+                    let byte_value = byte_cell.value().map(|byte| byte.evaluate());
+                    let byte = raw_assign_advice(region, *column, offset, byte_value)?;
+                    region.constrain_equal(byte_cell.cell(), byte.cell())?;
+                    word_value = word_value + byte_value.map(|byte| byte * multiplier);
+                }
+                None => {
+                    let byte = raw_assign_advice(region, *column, offset, Value::known(F::ZERO))?;
+                    region.constrain_constant(byte.cell(), F::ZERO)?;
+                }
+            }
+            multiplier *= F::from(256);
+        }
+        raw_assign_advice(region, self.word, offset, word_value)
+    }
+
+    /// Decomposes the digest `[hash_lo, hash_hi]` at `offset` and `offset + 1`, returns the cells
+    /// of its 32 bytes in the big-endian order of the digest. Needs a decomposition into at least
+    /// 16 bytes.
+    pub fn assign_digest<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        hash_lo: &Halo2AssignedCell<'v, F>,
+        hash_hi: &Halo2AssignedCell<'v, F>,
+    ) -> Result<Vec<Halo2AssignedCell<'v, F>>, Error> {
+        let hi = self.assign_word(region, offset, hash_hi)?;
+        let lo = self.assign_word(region, offset + 1, hash_lo)?;
+        // A half of the digest has 16 bytes, the bytes above are zero
+        let half = |bytes: Vec<_>| bytes.into_iter().take(16).rev();
+        Ok(half(hi).chain(half(lo)).collect())
+    }
+
+    /// Decomposes the input words holding the bytes `byte_indices` of the hash starting at the
+    /// keccak_f with index `first_keccak_f`, from `offset` on. Every word is decomposed once.
+    ///
+    /// Returns the byte cells in the order of `byte_indices` and the next free offset.
+    pub fn assign_input_bytes<'v>(
+        &self,
+        region: &mut Region<F>,
+        mut offset: usize,
+        keccak_config: &KeccakCircuitConfig<F>,
+        assigned_rows: &[KeccakAssignedRow<'v, F>],
+        first_keccak_f: usize,
+        byte_indices: impl IntoIterator<Item = usize>,
+    ) -> Result<(Vec<Halo2AssignedCell<'v, F>>, usize), Error> {
+        let mut words = BTreeMap::new();
+        let mut cells = vec![];
+        for byte_idx in byte_indices {
+            let word_idx = byte_idx / NUM_BYTES_PER_WORD;
+            if !words.contains_key(&word_idx) {
+                let word_row = keccak_config
+                    .input_word_row(assigned_rows, first_keccak_f, word_idx)
+                    .ok_or(Error::Synthesis)?;
+                words.insert(word_idx, self.assign_word(region, offset, &word_row.word_value)?);
+                offset += 1;
+            }
+            cells.push(words[&word_idx][byte_idx % NUM_BYTES_PER_WORD].clone());
+        }
+        Ok((cells, offset))
+    }
+}
@@ -0,0 +1,148 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::Region;
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Fixed};
+use halo2_proofs::poly::Rotation;
+
+use crate::gadgets::bytes::BytesConfig;
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::expression::not;
+use crate::util::word::Word;
+use crate::util::Halo2AssignedCell;
+
+/// A 256 bit value as its halves `[lo, hi]` of 128 bits, like a digest
+pub type WordCells<'v, F> = Word<Halo2AssignedCell<'v, F>>;
+
+/// Constrains `a <= b`, or `a < b` on strict rows, for 256 bit values given as halves `[lo, hi]` of
+/// 128 bits.
+///
+/// Every enabled row holds `a`, `b` and the difference `b - a - strict` as halves, with the borrow
+/// from the low half. Both halves of the difference are decomposed into 16 bytes, which only
+/// succeeds if the difference does not underflow.
+#[derive(Clone, Debug)]
+pub struct LessEqualConfig<F> {
+    q_enable: Column<Fixed>,
+    strict: Column<Fixed>,
+    a: [Column<Advice>; 2],
+    b: [Column<Advice>; 2],
+    borrow: Column<Advice>,
+    diff: [Column<Advice>; 2],
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> LessEqualConfig<F> {
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> Self {
+        let q_enable = meta.fixed_column();
+        let strict = meta.fixed_column();
+        let a = [(); 2].map(|_| meta.advice_column());
+        let b = [(); 2].map(|_| meta.advice_column());
+        let borrow = meta.advice_column();
+        let diff = [(); 2].map(|_| meta.advice_column());
+        for column in a.into_iter().chain(b).chain(diff) {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("less equal", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let strict = meta.query_fixed(strict, Rotation::cur());
+            let [a_lo, a_hi] = a.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [b_lo, b_hi] = b.map(|column| meta.query_advice(column, Rotation::cur()));
+            let [diff_lo, diff_hi] = diff.map(|column| meta.query_advice(column, Rotation::cur()));
+            let borrow = meta.query_advice(borrow, Rotation::cur());
+            let two_pow_128 = F::from_u128(1 << 64) * F::from_u128(1 << 64);
+            vec![
+                q_enable.clone() * borrow.clone() * not::expr(borrow.clone()),
+                q_enable.clone() * (b_lo - a_lo - strict + borrow.clone() * two_pow_128 - diff_lo),
+                q_enable * (b_hi - a_hi - borrow - diff_hi),
+            ]
+        });
+
+        LessEqualConfig {
+            q_enable,
+            strict,
+            a,
+            b,
+            borrow,
+            diff,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Constrains `a <= b` at `offset`, the halves of the difference are range checked by `bytes`,
+    /// a decomposition into 16 bytes, at `bytes_offset` and `bytes_offset + 1`.
+    ///
+    /// If `a > b` the witness does not satisfy the range check.
+    pub fn assign<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bytes: &BytesConfig<F>,
+        bytes_offset: usize,
+        a: &WordCells<'v, F>,
+        b: &WordCells<'v, F>,
+    ) -> Result<(), Error> {
+        self.assign_row(region, offset, bytes, bytes_offset, a, b, false)
+    }
+
+    /// Constrains `a < b` at `offset`, like [`LessEqualConfig::assign`].
+    ///
+    /// If `a >= b` the witness does not satisfy the range check.
+    pub fn assign_less<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bytes: &BytesConfig<F>,
+        bytes_offset: usize,
+        a: &WordCells<'v, F>,
+        b: &WordCells<'v, F>,
+    ) -> Result<(), Error> {
+        self.assign_row(region, offset, bytes, bytes_offset, a, b, true)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn assign_row<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        bytes: &BytesConfig<F>,
+        bytes_offset: usize,
+        a: &WordCells<'v, F>,
+        b: &WordCells<'v, F>,
+        strict: bool,
+    ) -> Result<(), Error> {
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        raw_assign_fixed(region, self.strict, offset, F::from(strict))?;
+        let mut copy = |column, cell: &Halo2AssignedCell<'v, F>| {
+            let value = cell.value().map(|value| value.evaluate());
+            let copied = raw_assign_advice(region, column, offset, value)?;
+            region.constrain_equal(cell.cell(), copied.cell())?;
+            Ok::<_, Error>(value.map(|value| to_u128(&value)))
+        };
+        let [a_lo, a_hi] = [copy(self.a[0], &a.lo())?, copy(self.a[1], &a.hi())?];
+        let [b_lo, b_hi] = [copy(self.b[0], &b.lo())?, copy(self.b[1], &b.hi())?];
+
+        let borrow = a_lo
+            .zip(b_lo)
+            .map(|(a_lo, b_lo)| b_lo < a_lo || (strict && b_lo == a_lo));
+        let diff_lo = a_lo
+            .zip(b_lo)
+            .map(|(a_lo, b_lo)| F::from_u128(b_lo.wrapping_sub(a_lo).wrapping_sub(strict as u128)));
+        // On underflow the difference is left negative, the decomposition then fails
+        let diff_hi = a_hi.zip(b_hi).zip(borrow).map(|((a_hi, b_hi), borrow)| {
+            F::from_u128(b_hi) - F::from_u128(a_hi) - F::from(borrow)
+        });
+        raw_assign_advice(region, self.borrow, offset, borrow.map(F::from))?;
+        let diff_lo = raw_assign_advice(region, self.diff[0], offset, diff_lo)?;
+        let diff_hi = raw_assign_advice(region, self.diff[1], offset, diff_hi)?;
+
+        bytes.assign_word(region, bytes_offset, &diff_lo)?;
+        bytes.assign_word(region, bytes_offset + 1, &diff_hi)?;
+        Ok(())
+    }
+}
+
+/// The low 128 bits of `value`
+pub fn to_u128<F: Field>(value: &F) -> u128 {
+    u128::from_le_bytes(value.to_bytes_le()[..16].try_into().unwrap())
+}
@@ -0,0 +1,124 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Region, Value};
+use halo2_proofs::plonk::{
+    Advice, Column, ConstraintSystem, Error, Expression, Fixed, TableColumn,
+};
+use halo2_proofs::poly::Rotation;
+
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::Halo2AssignedCell;
+
+/// ASCII code of the digit `0`
+const ASCII_ZERO: u64 = b'0' as u64;
+
+/// Parses ASCII decimal digits, like the length in an EIP-191 message prefix, and ties the value
+/// to a total length.
+///
+/// Every enabled row holds a digit byte and the value of the digits so far,
+/// `acc = multiplier * acc_prev + (byte - '0')`, with a multiplier of 0 on the first digit and 10
+/// on the others. Both `byte - '0'` and `byte - '0' + 246` are looked up in a byte table, so every
+/// byte is a digit. The row of the last digit also constrains `total = acc + offset`.
+#[derive(Clone, Debug)]
+pub struct DecimalConfig<F> {
+    q_enable: Column<Fixed>,
+    multiplier: Column<Fixed>,
+    q_total: Column<Fixed>,
+    offset: Column<Fixed>,
+    byte: Column<Advice>,
+    acc: Column<Advice>,
+    total: Column<Advice>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> DecimalConfig<F> {
+    /// Configures the gadget, `byte_table` has to hold exactly the values `0..256`.
+    pub fn configure(meta: &mut ConstraintSystem<F>, byte_table: TableColumn) -> Self {
+        let q_enable = meta.fixed_column();
+        let multiplier = meta.fixed_column();
+        let q_total = meta.fixed_column();
+        let offset = meta.fixed_column();
+        let byte = meta.advice_column();
+        let acc = meta.advice_column();
+        let total = meta.advice_column();
+        for column in [byte, acc, total] {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("decimal digits", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let multiplier = meta.query_fixed(multiplier, Rotation::cur());
+            let q_total = meta.query_fixed(q_total, Rotation::cur());
+            let offset = meta.query_fixed(offset, Rotation::cur());
+            let byte = meta.query_advice(byte, Rotation::cur());
+            let acc = meta.query_advice(acc, Rotation::cur());
+            let acc_prev = meta.query_advice(acc, Rotation::prev());
+            let total = meta.query_advice(total, Rotation::cur());
+            let digit = byte - Expression::Constant(F::from(ASCII_ZERO));
+            vec![
+                q_enable * (acc.clone() - multiplier * acc_prev - digit),
+                q_total * (total - acc - offset),
+            ]
+        });
+        for shift in [0, 246] {
+            meta.lookup("decimal digit range", |meta| {
+                let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+                let byte = meta.query_advice(byte, Rotation::cur());
+                let digit = byte - Expression::Constant(F::from(ASCII_ZERO) - F::from(shift));
+                vec![(q_enable * digit, byte_table)]
+            });
+        }
+
+        DecimalConfig {
+            q_enable,
+            multiplier,
+            q_total,
+            offset,
+            byte,
+            acc,
+            total,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Parses the digits in `digit_cells`, most significant first, in the rows from `offset` on
+    /// and constrains `total` to their value plus `total_offset`. Returns the cell of the value.
+    pub fn assign<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        digit_cells: &[Halo2AssignedCell<'v, F>],
+        total: &Halo2AssignedCell<'v, F>,
+        total_offset: u64,
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        if digit_cells.is_empty() {
+            log::error!("A decimal number needs at least one digit");
+            return Err(Error::Synthesis);
+        }
+        let mut acc_value = Value::known(F::ZERO);
+        let mut acc = None;
+        for (idx, digit_cell) in digit_cells.iter().enumerate() {
+            let row = offset + idx;
+            raw_assign_fixed(region, self.q_enable, row, F::ONE)?;
+            let multiplier = if idx == 0 { F::ZERO } else { F::from(10) };
+            raw_assign_fixed(region, self.multiplier, row, multiplier)?;
+            let byte_value = digit_cell.value().map(|byte| byte.evaluate());
+            let byte = raw_assign_advice(region, self.byte, row, byte_value)?;
+            region.constrain_equal(digit_cell.cell(), byte.cell())?;
+
+            acc_value = acc_value
+                .zip(byte_value)
+                .map(|(acc, byte)| acc * multiplier + byte - F::from(ASCII_ZERO));
+            acc = Some(raw_assign_advice(region, self.acc, row, acc_value)?);
+        }
+
+        let last = offset + digit_cells.len() - 1;
+        raw_assign_fixed(region, self.q_total, last, F::ONE)?;
+        raw_assign_fixed(region, self.offset, last, F::from(total_offset))?;
+        let total_value = total.value().map(|total| total.evaluate());
+        let total_copy = raw_assign_advice(region, self.total, last, total_value)?;
+        region.constrain_equal(total.cell(), total_copy.cell())?;
+        acc.ok_or(Error::Synthesis)
+    }
+}
@@ -0,0 +1,111 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Region, Value};
+use halo2_proofs::plonk::{Advice, Column, ConstraintSystem, Error, Expression, Fixed};
+use halo2_proofs::poly::Rotation;
+
+use crate::gadgets::compare::WordCells;
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::Halo2AssignedCell;
+
+/// Reduces digests, given as halves `[lo, hi]` of 128 bits, to a field element.
+///
+/// Every enabled row holds four limbs of 128 bits, least significant first, and their value
+/// `limb_0 + limb_1 * 2^128 + q_wide * (limb_2 * 2^256 + limb_3 * 2^384)` evaluated in the field,
+/// so the value is the digest modulo the field order, or the 512 bit `high || low` of two digests
+/// on wide rows. The limbs are not range checked, they have to be digest cells of the keccak
+/// circuit.
+#[derive(Clone, Debug)]
+pub struct FieldReductionConfig<F> {
+    q_enable: Column<Fixed>,
+    q_wide: Column<Fixed>,
+    limbs: [Column<Advice>; 4],
+    value: Column<Advice>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> FieldReductionConfig<F> {
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> Self {
+        let q_enable = meta.fixed_column();
+        let q_wide = meta.fixed_column();
+        let limbs = [(); 4].map(|_| meta.advice_column());
+        let value = meta.advice_column();
+        for column in limbs.into_iter().chain([value]) {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("field reduction", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let q_wide = meta.query_fixed(q_wide, Rotation::cur());
+            let [limb_0, limb_1, limb_2, limb_3] =
+                limbs.map(|column| meta.query_advice(column, Rotation::cur()));
+            let value = meta.query_advice(value, Rotation::cur());
+            let [w_1, w_2, w_3] = limb_weights::<F>().map(Expression::Constant);
+            vec![
+                q_enable * (limb_0 + limb_1 * w_1 + q_wide * (limb_2 * w_2 + limb_3 * w_3) - value),
+            ]
+        });
+
+        FieldReductionConfig {
+            q_enable,
+            q_wide,
+            limbs,
+            value,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reduces `digest` modulo the field order at `offset`, returns the cell of the value.
+    pub fn assign_digest<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        digest: &WordCells<'v, F>,
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        self.assign_row(region, offset, &[digest.lo(), digest.hi()])
+    }
+
+    /// Reduces the 512 bit `high || low` of two digests modulo the field order at `offset`,
+    /// returns the cell of the value.
+    pub fn assign_wide<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        high: &WordCells<'v, F>,
+        low: &WordCells<'v, F>,
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        raw_assign_fixed(region, self.q_wide, offset, F::ONE)?;
+        self.assign_row(region, offset, &[low.lo(), low.hi(), high.lo(), high.hi()])
+    }
+
+    fn assign_row<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        limbs: &[Halo2AssignedCell<'v, F>],
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        let weights = [F::ONE].into_iter().chain(limb_weights::<F>());
+        let mut value = Value::known(F::ZERO);
+        for ((column, limb), weight) in self.limbs.iter().zip(limbs).zip(weights) {
+            let limb_value = limb.value().map(|limb| limb.evaluate());
+            let copied = raw_assign_advice(region, *column, offset, limb_value)?;
+            region.constrain_equal(limb.cell(), copied.cell())?;
+            value = value
+                .zip(limb_value)
+                .map(|(value, limb)| value + limb * weight);
+        }
+        raw_assign_advice(region, self.value, offset, value)
+    }
+}
+
+/// The weights `2^128`, `2^256` and `2^384` of the limbs after the first one, modulo the field order
+fn limb_weights<F: Field>() -> [F; 3] {
+    let two_pow_128 = F::from_u128(1 << 64) * F::from_u128(1 << 64);
+    [
+        two_pow_128,
+        two_pow_128 * two_pow_128,
+        two_pow_128 * two_pow_128 * two_pow_128,
+    ]
+}
@@ -0,0 +1,148 @@
+use halo2_proofs::circuit::{Layouter, Region};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::util::eth_types::Field;
+use crate::util::{Halo2AssignedCell, SKIP_FIRST_PASS};
+use crate::vanilla::keccak_packed_multi::{get_keccak_capacity, get_num_keccak_f};
+use crate::vanilla::witness::multi_keccak_bytes;
+use crate::vanilla::{KeccakAssignedRow, KeccakCircuitConfig, KeccakConfigParams};
+use crate::KeccakCircuit;
+
+/// The cells of an input hashed by [`assign_hashes`] or pinned by [`pin_hashes`]
+#[derive(Clone, Debug)]
+pub struct HashedInput<'v, F: Field> {
+    /// Index of the first keccak_f of the input in the region
+    pub first_keccak_f: usize,
+    pub hash_lo: Halo2AssignedCell<'v, F>,
+    pub hash_hi: Halo2AssignedCell<'v, F>,
+}
+
+/// Configures the keccak circuit for a circuit built on its cells, with the input column open to
+/// copy constraints and a constant column for the input lengths and `is_final` pinned by
+/// [`assign_hashes`] and the constants of the circuit.
+pub fn configure_keccak<F: Field>(
+    meta: &mut ConstraintSystem<F>,
+    params: KeccakConfigParams,
+) -> CircuitConfig<F> {
+    let keccak = KeccakCircuit::<F>::configure_with_params(meta, params.into());
+    let constants = meta.fixed_column();
+    meta.enable_constant(constants);
+    keccak
+}
+
+/// Configures the keccak circuit like [`configure_keccak`], followed by the decomposition of
+/// values of up to `num_bytes` bytes into bytes of the keccak byte table. Decomposing the digest
+/// halves takes 16 bytes.
+pub fn configure_keccak_bytes<F: Field>(
+    meta: &mut ConstraintSystem<F>,
+    params: KeccakConfigParams,
+    num_bytes: usize,
+) -> (CircuitConfig<F>, BytesConfig<F>) {
+    let keccak = configure_keccak(meta, params);
+    let bytes = BytesConfig::configure(meta, keccak.keccak_config.byte_table(), num_bytes);
+    (keccak, bytes)
+}
+
+/// Assigns the keccak region hashing `inputs` in a circuit of `num_rows` rows.
+///
+/// Every input is pinned to its keccak_f's by [`pin_hashes`]. The circuit has to enable a constant
+/// column. Returns the assigned rows, for looking up input words with
+/// [`KeccakCircuitConfig::input_word_row`], and the digest cells of every input.
+pub fn assign_hashes<'v, F: Field>(
+    layouter: &mut impl Layouter<F>,
+    config: &KeccakCircuitConfig<F>,
+    num_rows: usize,
+    inputs: &[&[u8]],
+) -> Result<(Vec<KeccakAssignedRow<'v, F>>, Vec<HashedInput<'v, F>>), Error> {
+    let params = config.parameters;
+    let mut first_pass = SKIP_FIRST_PASS;
+    layouter.assign_region(
+        || "keccak circuit",
+        |mut region| {
+            if first_pass {
+                first_pass = false;
+                return Ok((vec![], vec![]));
+            }
+            let witness = multi_keccak_bytes(
+                inputs,
+                Some(get_keccak_capacity(num_rows, params.rows_per_round)),
+                params,
+            )?;
+            let assigned_rows = config.assign(&mut region, witness)?;
+            let lengths = inputs.iter().map(|input| input.len()).collect::<Vec<_>>();
+            let hashed_inputs = pin_hashes(&mut region, config, &assigned_rows, &lengths)?;
+            Ok((assigned_rows, hashed_inputs))
+        },
+    )
+}
+
+/// Pins the hashed inputs of `lengths` bytes to their keccak_f's in `assigned_rows`.
+///
+/// The length of every input and the `is_final` of each of its keccak_f's are constrained to
+/// constants, so the digest cells belong to exactly that input. Returns the digest cells of every
+/// input.
+pub fn pin_hashes<'v, F: Field>(
+    region: &mut Region<F>,
+    config: &KeccakCircuitConfig<F>,
+    assigned_rows: &[KeccakAssignedRow<'v, F>],
+    lengths: &[usize],
+) -> Result<Vec<HashedInput<'v, F>>, Error> {
+    let mut first_keccak_f = 0;
+    let mut hashed_inputs = Vec::with_capacity(lengths.len());
+    for &len in lengths {
+        let first_word = config
+            .input_word_row(assigned_rows, first_keccak_f, 0)
+            .ok_or(Error::Synthesis)?;
+        region.constrain_constant(first_word.bytes_left.cell(), F::from(len as u64))?;
+        let num_keccak_f = get_num_keccak_f(len);
+        for idx in 0..num_keccak_f {
+            let row = config
+                .keccak_f_output_row(assigned_rows, first_keccak_f + idx)
+                .ok_or(Error::Synthesis)?;
+            let is_final = F::from(idx == num_keccak_f - 1);
+            region.constrain_constant(row.is_final.cell(), is_final)?;
+        }
+        let output = config
+            .keccak_f_output_row(assigned_rows, first_keccak_f + num_keccak_f - 1)
+            .ok_or(Error::Synthesis)?;
+        hashed_inputs.push(HashedInput {
+            first_keccak_f,
+            hash_lo: output.hash_lo.clone(),
+            hash_hi: output.hash_hi.clone(),
+        });
+        first_keccak_f += num_keccak_f;
+    }
+    Ok(hashed_inputs)
+}
+
+/// Constrains the digest of `child` to the 32 input bytes of `parent` from `byte_offset` on.
+///
+/// The digest and the input words holding those bytes are decomposed by `bytes`, a decomposition
+/// into 16 bytes, from `offset` on. Returns the next free offset.
+#[allow(clippy::too_many_arguments)]
+pub fn link_digest<'v, F: Field>(
+    region: &mut Region<F>,
+    bytes: &BytesConfig<F>,
+    offset: usize,
+    config: &KeccakCircuitConfig<F>,
+    assigned_rows: &[KeccakAssignedRow<'v, F>],
+    child: &HashedInput<'v, F>,
+    parent: &HashedInput<'v, F>,
+    byte_offset: usize,
+) -> Result<usize, Error> {
+    let digest = bytes.assign_digest(region, offset, &child.hash_lo, &child.hash_hi)?;
+    let (input, next_offset) = bytes.assign_input_bytes(
+        region,
+        offset + 2,
+        config,
+        assigned_rows,
+        parent.first_keccak_f,
+        byte_offset..byte_offset + 32,
+    )?;
+    for (digest_byte, input_byte) in digest.iter().zip(input.iter()) {
+        region.constrain_equal(digest_byte.cell(), input_byte.cell())?;
+    }
+    Ok(next_offset)
+}
@@ -0,0 +1,7 @@
+//! Gadgets that work on the cells of the keccak circuit.
+
+pub mod bytes;
+pub mod compare;
+pub mod decimal;
+pub mod field;
+pub mod keccak;
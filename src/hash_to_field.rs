@@ -0,0 +1,213 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner};
+use halo2_proofs::plonk::{Circuit, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::field::FieldReductionConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak};
+use crate::util::eth_types::Field;
+use crate::util::prime_field::ScalarField;
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+
+/// Config of [`KeccakToFieldCircuit`]
+#[derive(Clone, Debug)]
+pub struct ToFieldConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub reduction: FieldReductionConfig<F>,
+}
+
+/// Circuit proving a field element derived from keccak digests.
+///
+/// With one input the element is `keccak256(input)` modulo the field order, as
+/// [`ScalarField::from_digest`] computes it. For a ~254 bit field this is biased towards small
+/// elements. With two inputs the element is the 512 bit `keccak256(first) || keccak256(second)`
+/// modulo the field order, as [`ScalarField::from_wide_digest`] computes it, which is statistically
+/// close to uniform. The digest cells are composed into the element by [`FieldReductionConfig`].
+///
+/// The public instance is the element, the inputs stay private. The number of inputs and their
+/// lengths are part of the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct KeccakToFieldCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    inputs: Vec<Vec<u8>>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakToFieldCircuit<F> {
+    type Config = ToFieldConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        Self {
+            inputs: self
+                .inputs
+                .iter()
+                .map(|input| vec![0; input.len()])
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        let keccak = configure_keccak(meta, params);
+        let reduction = FieldReductionConfig::configure(meta);
+        ToFieldConfig { keccak, reduction }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| input.as_slice())
+            .collect::<Vec<_>>();
+        let (_, hashes) = assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+        let digests = hashes
+            .iter()
+            .map(|hash| Word::new([hash.hash_lo.clone(), hash.hash_hi.clone()]))
+            .collect::<Vec<_>>();
+
+        let value = layouter.assign_region(
+            || "field reduction",
+            |mut region| match digests.as_slice() {
+                [digest] => config.reduction.assign_digest(&mut region, 0, digest),
+                [high, low] => config.reduction.assign_wide(&mut region, 0, high, low),
+                _ => {
+                    log::error!("Expected one or two inputs, got {}", digests.len());
+                    Err(Error::Synthesis)
+                }
+            },
+        )?;
+        layouter.constrain_instance(value.cell(), config.keccak.input, 0)?;
+
+        Ok(())
+    }
+}
+
+impl<F: Field> KeccakToFieldCircuit<F> {
+    /// Creates a circuit proving `keccak256(input)` modulo the field order in `num_rows` rows.
+    pub fn new(config: KeccakConfigParams, num_rows: usize, input: Vec<u8>) -> Self {
+        KeccakToFieldCircuit {
+            config,
+            num_rows,
+            inputs: vec![input],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates a circuit proving `keccak256(first) || keccak256(second)` modulo the field order in
+    /// `num_rows` rows.
+    pub fn wide(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        first: Vec<u8>,
+        second: Vec<u8>,
+    ) -> Self {
+        KeccakToFieldCircuit {
+            config,
+            num_rows,
+            inputs: vec![first, second],
+            _marker: PhantomData,
+        }
+    }
+
+    /// The field element, see [`KeccakToFieldCircuit`]
+    pub fn value(&self) -> F {
+        let digests = self
+            .inputs
+            .iter()
+            .map(|input| Keccak256::digest(input).into())
+            .collect::<Vec<[u8; 32]>>();
+        match digests.as_slice() {
+            [high, low] => F::from_wide_digest(high, low),
+            _ => F::from_digest(&digests[0]),
+        }
+    }
+
+    /// The public inputs of the circuit, see [`KeccakToFieldCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        vec![self.value()]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use halo2_proofs::halo2curves::ff::PrimeField;
+    use num_bigint::BigUint;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::util::prime_field::ScalarField;
+
+    use super::KeccakToFieldCircuit;
+
+    /// `value` as an integer modulo the order of [`Fr`]
+    fn reduce(value: &[u8]) -> Fr {
+        let modulus = BigUint::parse_bytes(Fr::MODULUS[2..].as_bytes(), 16).unwrap();
+        let reduced = BigUint::from_bytes_be(value) % modulus;
+        Fr::from_bytes_le(&reduced.to_bytes_le())
+    }
+
+    #[test]
+    fn test_native_reduction() {
+        // The all ones digest is above the modulus
+        for digest in [[0xff; 32], Keccak256::digest(b"abc").into()] {
+            assert_eq!(Fr::from_digest(&digest), reduce(&digest));
+            assert_eq!(
+                Fr::from_wide_digest(&digest, &[0x11; 32]),
+                reduce(&[digest, [0x11; 32]].concat())
+            );
+        }
+    }
+
+    #[test]
+    fn test_digest_to_field() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let circuit = KeccakToFieldCircuit::<Fr>::new(CONFIG, NUM_ROWS, b"hello".to_vec());
+        assert_eq!(circuit.value(), reduce(&Keccak256::digest(b"hello")));
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![vec![Fr::from(1)]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_wide_digest_to_field() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let (first, second) = (b"hello\x00".to_vec(), vec![b'x'; 300]);
+        let circuit =
+            KeccakToFieldCircuit::<Fr>::wide(CONFIG, NUM_ROWS, first.clone(), second.clone());
+        let mut wide = Keccak256::digest(&first).to_vec();
+        wide.extend_from_slice(&Keccak256::digest(&second));
+        assert_eq!(circuit.value(), reduce(&wide));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        // The inputs in the other order
+        let swapped = KeccakToFieldCircuit::<Fr>::wide(CONFIG, NUM_ROWS, second, first);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![swapped.instance()]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
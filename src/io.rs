@@ -1,4 +1,3 @@
-use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Cursor, Write};
 use std::path::Path;
@@ -9,68 +8,94 @@ use halo2_proofs::poly::commitment::Params;
 use halo2_proofs::poly::kzg::commitment::ParamsKZG;
 use halo2_proofs::SerdeFormat::RawBytes;
 
-fn with_writer<E>(path: &Path, f: impl FnOnce(&mut BufWriter<File>) -> Result<(), E>)
-where
-    E: fmt::Debug,
-{
-    let file = File::create(path).expect("Unable to create file");
+use crate::Keccak256Error;
+
+fn with_writer(
+    path: &Path,
+    what: &str,
+    f: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+) -> Result<(), Keccak256Error> {
+    let context = || format!("failed to write {} to {}", what, path.display());
+    let file = File::create(path).map_err(|e| Keccak256Error::io(context(), e))?;
     let mut writer = BufWriter::new(file);
-    f(&mut writer).expect("Unable to write to file");
-    writer.flush().expect("Unable to flush file");
+    f(&mut writer).map_err(|e| Keccak256Error::io(context(), e))?;
+    writer.flush().map_err(|e| Keccak256Error::io(context(), e))
 }
 
-fn with_reader<T, E>(path: &Path, f: impl FnOnce(&mut BufReader<File>) -> Result<T, E>) -> T
-where
-    E: fmt::Debug,
-{
-    let file = File::open(path).expect("Unable to open file");
+fn with_reader<T>(
+    path: &Path,
+    what: &str,
+    f: impl FnOnce(&mut BufReader<File>) -> std::io::Result<T>,
+) -> Result<T, Keccak256Error> {
+    let context = || format!("failed to read {} from {}", what, path.display());
+    let file = File::open(path).map_err(|e| Keccak256Error::io(context(), e))?;
     let mut reader = BufReader::new(file);
-    f(&mut reader).expect("Unable to read from file")
+    f(&mut reader).map_err(|e| Keccak256Error::io(context(), e))
 }
 
 /// Write SRS to file.
-pub fn write_srs(srs: &ParamsKZG<Bn256>, path: &Path) {
-    with_writer(path, |writer| srs.write(writer));
+pub fn write_srs(srs: &ParamsKZG<Bn256>, path: &Path) -> Result<(), Keccak256Error> {
+    with_writer(path, "SRS", |writer| srs.write(writer))
 }
 
 /// Write proving key and verification key to file.
-pub fn write_keys(pk: &ProvingKey<G1Affine>, pk_path: &Path, vk_path: &Path) {
-    with_writer(pk_path, |writer| pk.write(writer, RawBytes));
-    with_writer(vk_path, |writer| pk.get_vk().write(writer, RawBytes));
+pub fn write_keys(
+    pk: &ProvingKey<G1Affine>,
+    pk_path: &Path,
+    vk_path: &Path,
+) -> Result<(), Keccak256Error> {
+    with_writer(pk_path, "proving key", |writer| pk.write(writer, RawBytes))?;
+    with_writer(vk_path, "verification key", |writer| {
+        pk.get_vk().write(writer, RawBytes)
+    })
 }
 
 /// Read SRS from file.
-pub fn read_srs_path(path: &Path) -> ParamsKZG<Bn256> {
-    with_reader(path, |reader| ParamsKZG::read(reader))
+pub fn read_srs_path(path: &Path) -> Result<ParamsKZG<Bn256>, Keccak256Error> {
+    with_reader(path, "SRS", |reader| ParamsKZG::read(reader))
 }
 
-/// Read SRS from
-pub fn read_srs_bytes(data: &[u8]) -> ParamsKZG<Bn256> {
-    ParamsKZG::read::<_>(&mut &data[..]).unwrap()
+/// Read SRS from raw data.
+pub fn read_srs_bytes(data: &[u8]) -> Result<ParamsKZG<Bn256>, Keccak256Error> {
+    ParamsKZG::read::<_>(&mut &data[..]).map_err(|e| Keccak256Error::io("failed to read SRS", e))
 }
 
 /// Read a proving key from the file.
-pub fn read_pk<C: Circuit<Fr>>(path: &Path, params: C::Params) -> ProvingKey<G1Affine> {
-    with_reader(path, |reader| {
-        ProvingKey::read::<_, C>(reader, RawBytes, params)
+pub fn read_pk<C: Circuit<Fr>>(
+    path: &Path,
+    params: impl Into<C::Params>,
+) -> Result<ProvingKey<G1Affine>, Keccak256Error> {
+    with_reader(path, "proving key", |reader| {
+        ProvingKey::read::<_, C>(reader, RawBytes, params.into())
     })
 }
 
 /// Read a proving key from raw data.
-pub fn read_pk_bytes<C: Circuit<Fr>>(bytes: &[u8], params: C::Params) -> ProvingKey<G1Affine> {
+pub fn read_pk_bytes<C: Circuit<Fr>>(
+    bytes: &[u8],
+    params: impl Into<C::Params>,
+) -> Result<ProvingKey<G1Affine>, Keccak256Error> {
     let mut cursor = Cursor::new(bytes);
-    ProvingKey::read::<_, C>(&mut cursor, RawBytes, params).unwrap()
+    ProvingKey::read::<_, C>(&mut cursor, RawBytes, params.into())
+        .map_err(|e| Keccak256Error::io("failed to read proving key", e))
 }
 
 /// Read a verification key from the file.
-pub fn read_vk<C: Circuit<Fr>>(path: &Path, params: C::Params) -> VerifyingKey<G1Affine> {
-    with_reader(path, |reader| {
-        VerifyingKey::read::<_, C>(reader, RawBytes, params)
+pub fn read_vk<C: Circuit<Fr>>(
+    path: &Path,
+    params: impl Into<C::Params>,
+) -> Result<VerifyingKey<G1Affine>, Keccak256Error> {
+    with_reader(path, "verification key", |reader| {
+        VerifyingKey::read::<_, C>(reader, RawBytes, params.into())
     })
 }
 
 /// Read a verification key from raw data.
-pub fn read_vk_bytes<C: Circuit<Fr>>(bytes: &[u8], params: C::Params) -> VerifyingKey<G1Affine> {
+pub fn read_vk_bytes<C: Circuit<Fr>>(
+    bytes: &[u8],
+    params: impl Into<C::Params>,
+) -> Result<VerifyingKey<G1Affine>, Keccak256Error> {
     let mut cursor = Cursor::new(bytes);
-    VerifyingKey::read::<_, C>(&mut cursor, RawBytes, params).unwrap()
+    VerifyingKey::read::<_, C>(&mut cursor, RawBytes, params.into())
+        .map_err(|e| Keccak256Error::io("failed to read verification key", e))
 }
@@ -7,53 +7,73 @@
 
 use crate::circuit::{generate_halo2_proof, verify_halo2_proof};
 use crate::serialisation::{deserialize_circuit_inputs, InputsSerialisationWrapper};
-pub use circuit::KeccakCircuit;
+pub use chain::KeccakChainCircuit;
+pub use circuit::{KeccakCircuit, KeccakCircuitParams};
+pub use continuation::{state_commitment, KeccakChunkCircuit, KeccakState};
+pub use disclosure::KeccakDisclosureCircuit;
+pub use hash_to_field::KeccakToFieldCircuit;
+pub use merkle::{MerkleTreeCircuit, MerkleVariant};
+pub use pow::{KeccakPowCircuit, PowTarget};
+pub use util::word::Word;
 use halo2_proofs::halo2curves::bn256::{Bn256, Fr, G1Affine};
 use halo2_proofs::plonk::{ProvingKey, VerifyingKey};
 use halo2_proofs::poly::kzg::commitment::ParamsKZG;
+use rand::{thread_rng, RngCore};
 use std::collections::HashMap;
-use std::error::Error;
-use std::fmt::Display;
 use std::path::Path;
-use thiserror::Error;
+pub use error::Keccak256Error;
 pub use vanilla::KeccakConfigParams;
 
 mod util;
 /// Module for Keccak circuits in vanilla halo2.
 mod vanilla;
 
+mod chain;
 mod circuit;
+mod continuation;
+mod disclosure;
+mod error;
+pub mod ethereum;
+mod gadgets;
+mod hash_to_field;
 pub mod io;
+mod merkle;
+mod pow;
 
 mod serialisation;
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod test_utils;
 
 pub const DEFAULT_CONFIG: KeccakConfigParams = KeccakConfigParams {
     k: 14,
     rows_per_round: 28,
 };
 
-#[derive(Debug, Error)]
-pub struct Keccak256Error(String);
-
-impl Display for Keccak256Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+#[cfg(not(target_arch = "wasm32"))]
+pub fn prove(
+    srs_key_path: &str,
+    proving_key_path: &str,
+    input: HashMap<String, Vec<String>>,
+) -> Result<(Vec<u8>, Vec<u8>), Keccak256Error> {
+    prove_with_rng(srs_key_path, proving_key_path, input, thread_rng())
 }
 
+/// Same as [`prove`], but draws the proof's blinding factors from `rng`.
+/// Passing a seeded RNG makes the generated proof reproducible byte for byte.
 #[cfg(not(target_arch = "wasm32"))]
-pub fn prove(
+pub fn prove_with_rng(
     srs_key_path: &str,
     proving_key_path: &str,
     input: HashMap<String, Vec<String>>,
-) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
-    let srs = io::read_srs_path(Path::new(&srs_key_path));
+    rng: impl RngCore,
+) -> Result<(Vec<u8>, Vec<u8>), Keccak256Error> {
+    let srs = io::read_srs_path(Path::new(&srs_key_path))?;
     let proving_key =
-        io::read_pk::<KeccakCircuit<Fr>>(Path::new(&proving_key_path), DEFAULT_CONFIG);
+        io::read_pk::<KeccakCircuit<Fr>>(Path::new(&proving_key_path), DEFAULT_CONFIG)?;
 
-    prove_with_params(srs, proving_key, input)
+    prove_with_params(srs, proving_key, input, rng)
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -61,27 +81,38 @@ pub fn prove(
     srs_key: &[u8],
     proving_key: &[u8],
     input: HashMap<String, Vec<String>>,
-) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
-    let srs = io::read_srs_bytes(srs_key);
-    let proving_key = io::read_pk_bytes::<KeccakCircuit<Fr>>(proving_key, DEFAULT_CONFIG);
+) -> Result<(Vec<u8>, Vec<u8>), Keccak256Error> {
+    prove_with_rng(srs_key, proving_key, input, thread_rng())
+}
+
+/// Same as [`prove`], but draws the proof's blinding factors from `rng`.
+/// Passing a seeded RNG makes the generated proof reproducible byte for byte.
+#[cfg(target_arch = "wasm32")]
+pub fn prove_with_rng(
+    srs_key: &[u8],
+    proving_key: &[u8],
+    input: HashMap<String, Vec<String>>,
+    rng: impl RngCore,
+) -> Result<(Vec<u8>, Vec<u8>), Keccak256Error> {
+    let srs = io::read_srs_bytes(srs_key)?;
+    let proving_key = io::read_pk_bytes::<KeccakCircuit<Fr>>(proving_key, DEFAULT_CONFIG)?;
 
-    prove_with_params(srs, proving_key, input)
+    prove_with_params(srs, proving_key, input, rng)
 }
 
 fn prove_with_params(
     srs: ParamsKZG<Bn256>,
     proving_key: ProvingKey<G1Affine>,
     input: HashMap<String, Vec<String>>,
-) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
-    let circuit_inputs = deserialize_circuit_inputs(input)
-        .map_err(|e| Keccak256Error(format!("Failed to deserialize circuit inputs: {}", e)))?;
+    rng: impl RngCore,
+) -> Result<(Vec<u8>, Vec<u8>), Keccak256Error> {
+    let circuit_inputs = deserialize_circuit_inputs(input)?;
 
     let (inputs, proof) =
-        generate_halo2_proof(circuit_inputs, &srs, &proving_key, Some(DEFAULT_CONFIG))
-            .map_err(|e| Keccak256Error(format!("Failed to generate the proof: {}", e)))?;
+        generate_halo2_proof(circuit_inputs, &srs, &proving_key, Some(DEFAULT_CONFIG), rng)?;
 
     let serialized_inputs = bincode::serialize(&InputsSerialisationWrapper(inputs))
-        .map_err(|e| Keccak256Error(format!("Serialisation of Inputs failed: {}", e)))?;
+        .map_err(|e| Keccak256Error::Serialisation(e.to_string()))?;
 
     Ok((proof, serialized_inputs))
 }
@@ -91,40 +122,48 @@ fn verify_with_params(
     verifying_key: VerifyingKey<G1Affine>,
     proof: Vec<u8>,
     public_inputs: Vec<u8>,
-) -> Result<bool, Box<dyn Error>> {
+) -> Result<bool, Keccak256Error> {
     let deserialized_inputs = bincode::deserialize::<InputsSerialisationWrapper>(&public_inputs)
-        .map_err(|e| Keccak256Error(e.to_string()))?
+        .map_err(|e| Keccak256Error::MalformedPublicInputs(e.to_string()))?
         .0;
 
-    let is_valid = verify_halo2_proof(proof, &deserialized_inputs, &srs, &verifying_key)
-        .map_err(|_| Keccak256Error("Verification failed".to_string()))?;
-
-    Ok(is_valid)
+    // A well-formed proof that does not verify is a regular `false`, anything else is an error.
+    match verify_halo2_proof(proof, &deserialized_inputs, &srs, &verifying_key) {
+        Ok(()) => Ok(true),
+        Err(Keccak256Error::InvalidProof(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
 }
 
+/// Verifies `proof` against the serialised `public_inputs`.
+/// Returns `Ok(false)` when the proof is well-formed but rejected. Undecodable or non-canonical public
+/// inputs, truncated and undecodable proofs are returned as errors instead.
 #[cfg(not(target_arch = "wasm32"))]
 pub fn verify(
     srs_key_path: &str,
     verifying_key_path: &str,
     proof: Vec<u8>,
     public_inputs: Vec<u8>,
-) -> Result<bool, Box<dyn Error>> {
-    let srs = io::read_srs_path(Path::new(&srs_key_path));
+) -> Result<bool, Keccak256Error> {
+    let srs = io::read_srs_path(Path::new(&srs_key_path))?;
     let verifying_key =
-        io::read_vk::<KeccakCircuit<Fr>>(Path::new(&verifying_key_path), DEFAULT_CONFIG);
+        io::read_vk::<KeccakCircuit<Fr>>(Path::new(&verifying_key_path), DEFAULT_CONFIG)?;
 
     verify_with_params(srs, verifying_key, proof, public_inputs)
 }
 
+/// Verifies `proof` against the serialised `public_inputs`.
+/// Returns `Ok(false)` when the proof is well-formed but rejected. Undecodable or non-canonical public
+/// inputs, truncated and undecodable proofs are returned as errors instead.
 #[cfg(target_arch = "wasm32")]
 pub fn verify(
     srs_key: &[u8],
     verifying_key: &[u8],
     proof: Vec<u8>,
     public_inputs: Vec<u8>,
-) -> Result<bool, Box<dyn Error>> {
-    let srs = io::read_srs_bytes(srs_key);
-    let verifying_key = io::read_vk_bytes::<KeccakCircuit<Fr>>(verifying_key, DEFAULT_CONFIG);
+) -> Result<bool, Keccak256Error> {
+    let srs = io::read_srs_bytes(srs_key)?;
+    let verifying_key = io::read_vk_bytes::<KeccakCircuit<Fr>>(verifying_key, DEFAULT_CONFIG)?;
 
     verify_with_params(srs, verifying_key, proof, public_inputs)
 }
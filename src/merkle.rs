@@ -0,0 +1,659 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, Region, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error, Fixed};
+use halo2_proofs::poly::Rotation;
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::compare::{LessEqualConfig, WordCells};
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::assign_value::{raw_assign_advice, raw_assign_fixed};
+use crate::util::eth_types::Field;
+use crate::util::expression::not;
+use crate::util::word::Word;
+use crate::util::Halo2AssignedCell;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// How the two children of an internal node are ordered before hashing
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MerkleVariant {
+    /// `keccak256(min(a, b) || max(a, b))`, the pairs of OpenZeppelin's `MerkleProof`
+    #[default]
+    Sorted,
+    /// `keccak256(left || right)` by the position of the children in the tree
+    Ordered,
+}
+
+/// A child of an internal node
+#[derive(Clone, Debug)]
+enum Child {
+    /// A leaf, or a sibling of the proven path, that is not hashed in the circuit
+    Value([u8; 32]),
+    /// The internal node with this index
+    Node(usize),
+}
+
+/// An internal node hashing its children `a` and `c`, swapped if `swap` is set
+#[derive(Clone, Debug)]
+struct PairNode {
+    a: Child,
+    c: Child,
+    swap: bool,
+    /// Weight of `swap` in the leaf index, 0 if the node is not on an indexed path
+    weight: u64,
+}
+
+/// Config of the conditional swap of the children of every internal node.
+///
+/// An enabled row holds the children `a` and `c` as halves `[lo, hi]`, the `bit` swapping them and
+/// the resulting `left` and `right` halves of the hashed input. `index` accumulates `bit * weight`
+/// over the rows, so on a path it ends with the leaf index.
+#[derive(Clone, Debug)]
+pub struct PathConfig<F> {
+    q_enable: Column<Fixed>,
+    weight: Column<Fixed>,
+    bit: Column<Advice>,
+    a: [Column<Advice>; 2],
+    c: [Column<Advice>; 2],
+    left: [Column<Advice>; 2],
+    right: [Column<Advice>; 2],
+    index: Column<Advice>,
+    _marker: PhantomData<F>,
+}
+
+/// The cells of a row of [`PathConfig`]
+struct PathRow<'v, F: Field> {
+    bit: Halo2AssignedCell<'v, F>,
+    a: WordCells<'v, F>,
+    c: WordCells<'v, F>,
+    left: WordCells<'v, F>,
+    right: WordCells<'v, F>,
+    index: Halo2AssignedCell<'v, F>,
+}
+
+impl<F: Field> PathConfig<F> {
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self {
+        let q_enable = meta.fixed_column();
+        let weight = meta.fixed_column();
+        let bit = meta.advice_column();
+        let [a, c, left, right] = [(); 4].map(|_| [(); 2].map(|_| meta.advice_column()));
+        let index = meta.advice_column();
+        for column in [bit, index]
+            .into_iter()
+            .chain(a)
+            .chain(c)
+            .chain(left)
+            .chain(right)
+        {
+            meta.enable_equality(column);
+        }
+
+        meta.create_gate("merkle path", |meta| {
+            let q_enable = meta.query_fixed(q_enable, Rotation::cur());
+            let weight = meta.query_fixed(weight, Rotation::cur());
+            let bit = meta.query_advice(bit, Rotation::cur());
+            let index = meta.query_advice(index, Rotation::cur());
+            let index_prev = meta.query_advice(index, Rotation::prev());
+            let mut constraints = vec![
+                bit.clone() * not::expr(bit.clone()),
+                index - index_prev - bit.clone() * weight,
+            ];
+            for limb in 0..2 {
+                let a = meta.query_advice(a[limb], Rotation::cur());
+                let c = meta.query_advice(c[limb], Rotation::cur());
+                let left = meta.query_advice(left[limb], Rotation::cur());
+                let right = meta.query_advice(right[limb], Rotation::cur());
+                constraints.push(left - a.clone() - bit.clone() * (c.clone() - a.clone()));
+                constraints.push(right - c.clone() - bit.clone() * (a - c));
+            }
+            constraints
+                .into_iter()
+                .map(|constraint| q_enable.clone() * constraint)
+                .collect::<Vec<_>>()
+        });
+
+        PathConfig {
+            q_enable,
+            weight,
+            bit,
+            a,
+            c,
+            left,
+            right,
+            index,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Assigns the starting index 0 at `offset`, the rows follow it
+    fn assign_start<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+    ) -> Result<Halo2AssignedCell<'v, F>, Error> {
+        let index = raw_assign_advice(region, self.index, offset, Value::known(F::ZERO))?;
+        region.constrain_constant(index.cell(), F::ZERO)?;
+        Ok(index)
+    }
+
+    fn assign_row<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        node: &PairNode,
+        [a, c]: [Value<[u8; 32]>; 2],
+        index: Value<u64>,
+    ) -> Result<PathRow<'v, F>, Error> {
+        raw_assign_fixed(region, self.q_enable, offset, F::ONE)?;
+        raw_assign_fixed(region, self.weight, offset, F::from(node.weight))?;
+        let (left, right) = match node.swap {
+            false => (a, c),
+            true => (c, a),
+        };
+        Ok(PathRow {
+            bit: raw_assign_advice(region, self.bit, offset, Value::known(F::from(node.swap)))?,
+            a: assign_word_halves(region, self.a, offset, a)?,
+            c: assign_word_halves(region, self.c, offset, c)?,
+            left: assign_word_halves(region, self.left, offset, left)?,
+            right: assign_word_halves(region, self.right, offset, right)?,
+            index: raw_assign_advice(region, self.index, offset, index.map(F::from))?,
+        })
+    }
+}
+
+/// Config of [`MerkleTreeCircuit`]
+#[derive(Clone, Debug)]
+pub struct MerkleConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    pub path: PathConfig<F>,
+    pub less_equal: LessEqualConfig<F>,
+}
+
+/// Circuit proving the root of a binary keccak256 Merkle tree, from all of its leaves or from a leaf
+/// and the siblings on its path.
+///
+/// Every internal node hashes the 64 bytes of its two child digests in the keccak circuit. The
+/// digest of a child is copy-constrained, byte by byte, into the input words of its parent. Leaves,
+/// and the siblings of a proof, are 32 byte values that are not hashed again. In the
+/// [`MerkleVariant::Sorted`] variant the smaller child, compared as a big endian integer, is hashed
+/// first, in the [`MerkleVariant::Ordered`] variant the left child.
+///
+/// The public instance is the root `[lo, hi]`, followed by the leaf index for a proof in the
+/// ordered variant if [`MerkleTreeCircuit::with_public_index`] is set. The sorted variant does not
+/// determine the index of the leaf.
+#[derive(Default, Clone, Debug)]
+pub struct MerkleTreeCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    variant: MerkleVariant,
+    /// The internal nodes, every node after its children and the root last
+    nodes: Vec<PairNode>,
+    /// The digest of every internal node
+    digests: Vec<[u8; 32]>,
+    public_index: bool,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for MerkleTreeCircuit<F> {
+    type Config = MerkleConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        let child = |child: &Child| match child {
+            Child::Value(_) => Child::Value([0; 32]),
+            Child::Node(idx) => Child::Node(*idx),
+        };
+        let mut circuit = Self::empty(self.config, self.num_rows, self.variant);
+        for node in self.nodes.iter() {
+            circuit.push_node(child(&node.a), child(&node.c), node.weight, false);
+        }
+        Self {
+            public_index: self.public_index,
+            ..circuit
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Decomposes the input words, the child digests and the differences of sorted children
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        let path = PathConfig::configure(meta);
+        let less_equal = LessEqualConfig::configure(meta);
+        MerkleConfig {
+            keccak,
+            bytes,
+            path,
+            less_equal,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        if self.public_index && self.variant == MerkleVariant::Sorted {
+            log::error!("The leaf index of a sorted Merkle proof is not determined by its path");
+            return Err(Error::Synthesis);
+        }
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let inputs = self
+            .nodes
+            .iter()
+            .map(|node| self.node_input(node))
+            .collect::<Vec<_>>();
+        let inputs = inputs
+            .iter()
+            .map(|input| input.as_slice())
+            .collect::<Vec<_>>();
+        let (assigned_rows, hashed_nodes) =
+            assign_hashes(&mut layouter, keccak_config, self.num_rows, &inputs)?;
+
+        let index = layouter.assign_region(
+            || "merkle nodes",
+            |mut region| {
+                let mut index = config.path.assign_start(&mut region, 0)?;
+                let mut index_value = Value::known(0);
+                let mut bytes_offset = 0;
+                for (idx, (node, hashed_node)) in
+                    self.nodes.iter().zip(hashed_nodes.iter()).enumerate()
+                {
+                    let children = [&node.a, &node.c].map(|child| match child {
+                        Child::Value(value) => Value::known(*value),
+                        Child::Node(child_idx) => Value::known(self.digests[*child_idx]),
+                    });
+                    index_value = index_value.map(|index| index + node.weight * node.swap as u64);
+                    let row = config.path.assign_row(
+                        &mut region,
+                        idx + 1,
+                        node,
+                        children,
+                        index_value,
+                    )?;
+                    index = row.index;
+
+                    // Children hashed in the circuit are their digest cells
+                    for (child, cells) in [(&node.a, &row.a), (&node.c, &row.c)] {
+                        if let Child::Node(child_idx) = child {
+                            let hashed_child = &hashed_nodes[*child_idx];
+                            region
+                                .constrain_equal(cells.lo().cell(), hashed_child.hash_lo.cell())?;
+                            region
+                                .constrain_equal(cells.hi().cell(), hashed_child.hash_hi.cell())?;
+                        }
+                    }
+                    match self.variant {
+                        MerkleVariant::Sorted => {
+                            config.less_equal.assign(
+                                &mut region,
+                                idx,
+                                &config.bytes,
+                                bytes_offset,
+                                &row.left,
+                                &row.right,
+                            )?;
+                            bytes_offset += 2;
+                        }
+                        // Children of a whole tree are hashed by their position
+                        MerkleVariant::Ordered if node.weight == 0 => {
+                            region.constrain_constant(row.bit.cell(), F::ZERO)?;
+                        }
+                        MerkleVariant::Ordered => {}
+                    }
+
+                    // The node input is `left || right`
+                    let mut halves = vec![];
+                    for half in [&row.left, &row.right] {
+                        halves.extend(config.bytes.assign_digest(
+                            &mut region,
+                            bytes_offset,
+                            &half.lo(),
+                            &half.hi(),
+                        )?);
+                        bytes_offset += 2;
+                    }
+                    let (input, next_offset) = config.bytes.assign_input_bytes(
+                        &mut region,
+                        bytes_offset,
+                        keccak_config,
+                        &assigned_rows,
+                        hashed_node.first_keccak_f,
+                        0..64,
+                    )?;
+                    bytes_offset = next_offset;
+                    for (input_byte, half_byte) in input.iter().zip(halves.iter()) {
+                        region.constrain_equal(input_byte.cell(), half_byte.cell())?;
+                    }
+                }
+                Ok(index)
+            },
+        )?;
+
+        let root = hashed_nodes.last().ok_or(Error::Synthesis)?;
+        layouter.constrain_instance(root.hash_lo.cell(), config.keccak.input, 0)?;
+        layouter.constrain_instance(root.hash_hi.cell(), config.keccak.input, 1)?;
+        if self.public_index {
+            layouter.constrain_instance(index.cell(), config.keccak.input, 2)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> MerkleTreeCircuit<F> {
+    /// Creates a circuit computing the root of the tree with `leaves` in `num_rows` rows.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the number of leaves is not a power of two of at
+    /// least 2.
+    pub fn from_leaves(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        variant: MerkleVariant,
+        leaves: Vec<[u8; 32]>,
+    ) -> Result<Self, Keccak256Error> {
+        if leaves.len() < 2 || !leaves.len().is_power_of_two() {
+            return Err(invalid_tree(
+                "leaves",
+                &format!("{} leaves do not form a complete binary tree", leaves.len()),
+            ));
+        }
+        let mut circuit = Self::empty(config, num_rows, variant);
+        let mut level = leaves.into_iter().map(Child::Value).collect::<Vec<_>>();
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    Child::Node(circuit.push_node(pair[0].clone(), pair[1].clone(), 0, false))
+                })
+                .collect();
+        }
+        Ok(circuit)
+    }
+
+    /// Creates a circuit computing the root from `leaf` and the `siblings` on its path, from the
+    /// leaves up, in `num_rows` rows.
+    ///
+    /// In the ordered variant bit `i` of `index` is set if the node at height `i` is a right child,
+    /// the sorted variant ignores `index`. Returns [`Keccak256Error::InputParsing`] if there are no
+    /// siblings or `index` is out of the tree.
+    pub fn from_proof(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        variant: MerkleVariant,
+        leaf: [u8; 32],
+        index: u64,
+        siblings: Vec<[u8; 32]>,
+    ) -> Result<Self, Keccak256Error> {
+        if siblings.is_empty() || siblings.len() > 64 {
+            return Err(invalid_tree(
+                "siblings",
+                &format!("a path of {} siblings is not supported", siblings.len()),
+            ));
+        }
+        if variant == MerkleVariant::Ordered && siblings.len() < 64 && index >> siblings.len() != 0
+        {
+            return Err(invalid_tree(
+                "index",
+                &format!(
+                    "leaf {} is out of a tree of height {}",
+                    index,
+                    siblings.len()
+                ),
+            ));
+        }
+        let mut circuit = Self::empty(config, num_rows, variant);
+        let mut current = Child::Value(leaf);
+        for (height, sibling) in siblings.into_iter().enumerate() {
+            let node = match variant {
+                MerkleVariant::Sorted => circuit.push_node(current, Child::Value(sibling), 0, false),
+                MerkleVariant::Ordered => {
+                    let swap = (index >> height) & 1 == 1;
+                    circuit.push_node(current, Child::Value(sibling), 1 << height, swap)
+                }
+            };
+            current = Child::Node(node);
+        }
+        Ok(circuit)
+    }
+
+    /// Makes the leaf index of an ordered proof a public input, see [`MerkleTreeCircuit`]
+    pub fn with_public_index(self) -> Self {
+        Self {
+            public_index: true,
+            ..self
+        }
+    }
+
+    /// The root of the tree
+    pub fn root(&self) -> [u8; 32] {
+        *self.digests.last().unwrap()
+    }
+
+    /// The public inputs of the circuit, see [`MerkleTreeCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let root = self.root();
+        let mut instance = vec![
+            F::from_u128(u128::from_be_bytes(root[16..].try_into().unwrap())),
+            F::from_u128(u128::from_be_bytes(root[..16].try_into().unwrap())),
+        ];
+        if self.public_index {
+            let index = self
+                .nodes
+                .iter()
+                .map(|node| node.weight * node.swap as u64)
+                .sum::<u64>();
+            instance.push(F::from(index));
+        }
+        instance
+    }
+
+    fn empty(config: KeccakConfigParams, num_rows: usize, variant: MerkleVariant) -> Self {
+        MerkleTreeCircuit {
+            config,
+            num_rows,
+            variant,
+            nodes: vec![],
+            digests: vec![],
+            public_index: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Appends the node hashing `a` and `c` and returns its index. The children are swapped in the
+    /// sorted variant if `a` is the larger one, in the ordered variant if `swap` is set.
+    fn push_node(&mut self, a: Child, c: Child, weight: u64, swap: bool) -> usize {
+        let swap = match self.variant {
+            MerkleVariant::Sorted => self.child_value(&a) > self.child_value(&c),
+            MerkleVariant::Ordered => swap,
+        };
+        let node = PairNode { a, c, swap, weight };
+        let digest = Keccak256::digest(self.node_input(&node));
+        self.digests.push(digest.into());
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// The 64 byte input of `node`, whose children are before it
+    fn node_input(&self, node: &PairNode) -> Vec<u8> {
+        let (left, right) = match node.swap {
+            false => (self.child_value(&node.a), self.child_value(&node.c)),
+            true => (self.child_value(&node.c), self.child_value(&node.a)),
+        };
+        [left, right].concat()
+    }
+
+    /// The value of a leaf or sibling, or the digest of a node
+    fn child_value(&self, child: &Child) -> [u8; 32] {
+        match child {
+            Child::Value(value) => *value,
+            Child::Node(idx) => self.digests[*idx],
+        }
+    }
+}
+
+/// Assigns the 256 bit `value` as halves `[lo, hi]` to two advice cells at `offset`
+fn assign_word_halves<'v, F: Field>(
+    region: &mut Region<F>,
+    columns: [Column<Advice>; 2],
+    offset: usize,
+    value: Value<[u8; 32]>,
+) -> Result<WordCells<'v, F>, Error> {
+    let lo = value.map(|value| F::from_u128(u128::from_be_bytes(value[16..].try_into().unwrap())));
+    let hi = value.map(|value| F::from_u128(u128::from_be_bytes(value[..16].try_into().unwrap())));
+    Ok(Word::new([
+        raw_assign_advice(region, columns[0], offset, lo)?,
+        raw_assign_advice(region, columns[1], offset, hi)?,
+    ]))
+}
+
+fn invalid_tree(name: &str, reason: &str) -> Keccak256Error {
+    Keccak256Error::InputParsing {
+        name: name.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use sha3::{Digest, Keccak256};
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::Keccak256Error;
+
+    use super::{MerkleTreeCircuit, MerkleVariant};
+
+    fn leaves(num_leaves: usize) -> Vec<[u8; 32]> {
+        (0..num_leaves as u8)
+            .map(|i| Keccak256::digest([i])[..].try_into().unwrap())
+            .collect()
+    }
+
+    fn hash_pair(variant: MerkleVariant, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let (left, right) = match variant {
+            MerkleVariant::Sorted if left > right => (right, left),
+            _ => (left, right),
+        };
+        Keccak256::digest([left, right].concat())[..]
+            .try_into()
+            .unwrap()
+    }
+
+    fn root_and_proof(
+        variant: MerkleVariant,
+        leaves: &[[u8; 32]],
+        index: usize,
+    ) -> ([u8; 32], Vec<[u8; 32]>) {
+        let mut level = leaves.to_vec();
+        let mut siblings = vec![];
+        let mut index = index;
+        while level.len() > 1 {
+            siblings.push(level[index ^ 1]);
+            level = level
+                .chunks(2)
+                .map(|pair| hash_pair(variant, pair[0], pair[1]))
+                .collect();
+            index /= 2;
+        }
+        (level[0], siblings)
+    }
+
+    #[test]
+    fn test_tree_roots() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let leaves = leaves(4);
+        for variant in [MerkleVariant::Sorted, MerkleVariant::Ordered] {
+            let circuit =
+                MerkleTreeCircuit::<Fr>::from_leaves(CONFIG, NUM_ROWS, variant, leaves.clone())
+                    .unwrap();
+            assert_eq!(circuit.root(), root_and_proof(variant, &leaves, 0).0);
+
+            let prover =
+                MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+            prover.assert_satisfied();
+
+            let mut instance = circuit.instance();
+            instance[1] += Fr::from(1);
+            let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+
+        let result = MerkleTreeCircuit::<Fr>::from_leaves(
+            CONFIG,
+            NUM_ROWS,
+            MerkleVariant::Sorted,
+            leaves[..3].to_vec(),
+        );
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_sorted_proof() {
+        let leaves = leaves(8);
+        let (root, siblings) = root_and_proof(MerkleVariant::Sorted, &leaves, 6);
+        let circuit = MerkleTreeCircuit::<Fr>::from_proof(
+            CONFIG,
+            NUM_ROWS,
+            MerkleVariant::Sorted,
+            leaves[6],
+            0,
+            siblings,
+        )
+        .unwrap();
+        assert_eq!(circuit.root(), root);
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+    }
+
+    #[test]
+    fn test_ordered_proof_with_public_index() {
+        let leaves = leaves(8);
+        let (root, siblings) = root_and_proof(MerkleVariant::Ordered, &leaves, 5);
+        let circuit = MerkleTreeCircuit::<Fr>::from_proof(
+            CONFIG,
+            NUM_ROWS,
+            MerkleVariant::Ordered,
+            leaves[5],
+            5,
+            siblings.clone(),
+        )
+        .unwrap()
+        .with_public_index();
+        assert_eq!(circuit.root(), root);
+        assert_eq!(circuit.instance()[2], Fr::from(5));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+        prover.assert_satisfied();
+
+        // The path of another leaf does not lead to the root
+        let mut instance = circuit.instance();
+        instance[2] = Fr::from(4);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance]).unwrap();
+        assert!(prover.verify().is_err());
+
+        let result = MerkleTreeCircuit::<Fr>::from_proof(
+            CONFIG,
+            NUM_ROWS,
+            MerkleVariant::Ordered,
+            leaves[5],
+            8,
+            siblings,
+        );
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+}
@@ -0,0 +1,308 @@
+use std::marker::PhantomData;
+
+use halo2_proofs::circuit::{Layouter, SimpleFloorPlanner, Value};
+use halo2_proofs::plonk::{Advice, Circuit, Column, ConstraintSystem, Error};
+use sha3::{Digest, Keccak256};
+
+use crate::circuit::CircuitConfig;
+use crate::gadgets::bytes::BytesConfig;
+use crate::gadgets::compare::LessEqualConfig;
+use crate::gadgets::keccak::{assign_hashes, configure_keccak_bytes};
+use crate::util::assign_value::raw_assign_advice;
+use crate::util::eth_types::{Field, H256, U256};
+use crate::util::word::Word;
+use crate::vanilla::KeccakConfigParams;
+use crate::Keccak256Error;
+
+/// The work a digest has to meet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowTarget {
+    /// The digest, as a big endian integer, is below the target
+    Below(U256),
+    /// The digest starts with at least this many zero bits, from 1 to 256
+    LeadingZeros(u32),
+}
+
+impl Default for PowTarget {
+    fn default() -> Self {
+        PowTarget::LeadingZeros(1)
+    }
+}
+
+impl PowTarget {
+    /// The exclusive upper bound of the digest, `2^(256 - n)` for `n` leading zero bits
+    pub fn bound(&self) -> U256 {
+        match self {
+            PowTarget::Below(target) => *target,
+            PowTarget::LeadingZeros(bits) => U256::one() << (256 - *bits as usize),
+        }
+    }
+
+    /// Whether `digest` meets the target
+    pub fn is_met_by(&self, digest: H256) -> bool {
+        U256::from_big_endian(digest.as_bytes()) < self.bound()
+    }
+}
+
+/// Computes the digest `keccak256(nonce || data)` natively.
+pub fn pow_digest(nonce: &[u8], data: &[u8]) -> H256 {
+    let mut hasher = Keccak256::new();
+    hasher.update(nonce);
+    hasher.update(data);
+    H256::from_slice(&hasher.finalize())
+}
+
+/// Config of [`KeccakPowCircuit`]
+#[derive(Clone, Debug)]
+pub struct PowConfig<F> {
+    pub keccak: CircuitConfig<F>,
+    pub bytes: BytesConfig<F>,
+    pub less_equal: LessEqualConfig<F>,
+    /// The halves `[lo, hi]` of the bound
+    pub bound: [Column<Advice>; 2],
+}
+
+/// Circuit proving that `keccak256(nonce || data)` meets a proof-of-work target without revealing
+/// the nonce.
+///
+/// The digest `[hash_lo, hash_hi]` is constrained below the bound of the target by
+/// [`LessEqualConfig::assign_less`]. The public instance is the bound `[lo, hi]`, followed by the
+/// bytes of the data, one byte per cell. For [`PowTarget::LeadingZeros`] the bound is also
+/// constrained to the constant `2^(256 - n)`.
+///
+/// The lengths of the nonce and the data and the number of leading zero bits are part of the
+/// circuit. If the digest does not meet the target the witness does not satisfy the circuit.
+#[derive(Default, Clone, Debug)]
+pub struct KeccakPowCircuit<F: Field> {
+    config: KeccakConfigParams,
+    num_rows: usize,
+    nonce: Vec<u8>,
+    data: Vec<u8>,
+    target: PowTarget,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> Circuit<F> for KeccakPowCircuit<F> {
+    type Config = PowConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+    type Params = KeccakConfigParams;
+
+    fn without_witnesses(&self) -> Self {
+        let target = match self.target {
+            PowTarget::Below(_) => PowTarget::Below(U256::zero()),
+            PowTarget::LeadingZeros(bits) => PowTarget::LeadingZeros(bits),
+        };
+        Self {
+            nonce: vec![0; self.nonce.len()],
+            data: vec![0; self.data.len()],
+            target,
+            ..self.clone()
+        }
+    }
+
+    fn params(&self) -> Self::Params {
+        self.config
+    }
+
+    fn configure_with_params(meta: &mut ConstraintSystem<F>, params: Self::Params) -> Self::Config {
+        // Decomposes the input words and the difference to the bound
+        let (keccak, bytes) = configure_keccak_bytes(meta, params, 16);
+        let less_equal = LessEqualConfig::configure(meta);
+        let bound = [(); 2].map(|_| meta.advice_column());
+        for column in bound {
+            meta.enable_equality(column);
+        }
+        PowConfig {
+            keccak,
+            bytes,
+            less_equal,
+            bound,
+        }
+    }
+
+    fn configure(_: &mut ConstraintSystem<F>) -> Self::Config {
+        unreachable!()
+    }
+
+    fn synthesize(
+        &self,
+        config: Self::Config,
+        mut layouter: impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let keccak_config = &config.keccak.keccak_config;
+        keccak_config.load_aux_tables(&mut layouter, keccak_config.parameters.k)?;
+        let input = [self.nonce.as_slice(), self.data.as_slice()].concat();
+        let (assigned_rows, hashes) = assign_hashes(
+            &mut layouter,
+            keccak_config,
+            self.num_rows,
+            &[input.as_slice()],
+        )?;
+        let hash = hashes.first().ok_or(Error::Synthesis)?;
+
+        let public_cells = layouter.assign_region(
+            || "proof of work",
+            |mut region| {
+                let bound = Word::<F>::from(self.target.bound());
+                let bound = Word::new([
+                    raw_assign_advice(&mut region, config.bound[0], 0, Value::known(bound.lo()))?,
+                    raw_assign_advice(&mut region, config.bound[1], 0, Value::known(bound.hi()))?,
+                ]);
+                if let PowTarget::LeadingZeros(_) = self.target {
+                    let constant = Word::<F>::from(self.target.bound());
+                    region.constrain_constant(bound.lo().cell(), constant.lo())?;
+                    region.constrain_constant(bound.hi().cell(), constant.hi())?;
+                }
+                let digest = Word::new([hash.hash_lo.clone(), hash.hash_hi.clone()]);
+                config
+                    .less_equal
+                    .assign_less(&mut region, 0, &config.bytes, 0, &digest, &bound)?;
+
+                let (data, _) = config.bytes.assign_input_bytes(
+                    &mut region,
+                    2,
+                    keccak_config,
+                    &assigned_rows,
+                    hash.first_keccak_f,
+                    self.nonce.len()..input.len(),
+                )?;
+                let mut public_cells = vec![bound.lo(), bound.hi()];
+                public_cells.extend(data);
+                Ok(public_cells)
+            },
+        )?;
+
+        for (idx, cell) in public_cells.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.keccak.input, idx)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Field> KeccakPowCircuit<F> {
+    /// Creates a circuit proving that `keccak256(nonce || data)` meets `target` in `num_rows` rows.
+    ///
+    /// Returns [`Keccak256Error::InputParsing`] if the number of leading zero bits is not in
+    /// `1..=256`.
+    pub fn new(
+        config: KeccakConfigParams,
+        num_rows: usize,
+        nonce: Vec<u8>,
+        data: Vec<u8>,
+        target: PowTarget,
+    ) -> Result<Self, Keccak256Error> {
+        if let PowTarget::LeadingZeros(bits) = target {
+            if !(1..=256).contains(&bits) {
+                return Err(Keccak256Error::InputParsing {
+                    name: "target".to_string(),
+                    reason: format!("{} leading zero bits are not in 1..=256", bits),
+                });
+            }
+        }
+        Ok(KeccakPowCircuit {
+            config,
+            num_rows,
+            nonce,
+            data,
+            target,
+            _marker: PhantomData,
+        })
+    }
+
+    /// The digest `keccak256(nonce || data)`
+    pub fn digest(&self) -> H256 {
+        pow_digest(&self.nonce, &self.data)
+    }
+
+    /// Whether the digest meets the target, otherwise the circuit is not satisfied
+    pub fn is_valid(&self) -> bool {
+        self.target.is_met_by(self.digest())
+    }
+
+    /// The public inputs of the circuit, see [`KeccakPowCircuit`]
+    pub fn instance(&self) -> Vec<F> {
+        let bound = Word::<F>::from(self.target.bound());
+        let mut instance = vec![bound.lo(), bound.hi()];
+        instance.extend(self.data.iter().map(|byte| F::from(*byte as u64)));
+        instance
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    use crate::test_utils::{CONFIG, NUM_ROWS};
+    use crate::util::eth_types::U256;
+    use crate::Keccak256Error;
+
+    use super::{pow_digest, KeccakPowCircuit, PowTarget};
+
+    const DATA: &[u8] = b"register alice@example.com";
+
+    /// The first 8 byte nonce whose digest meets `target`
+    fn find_nonce(target: PowTarget) -> Vec<u8> {
+        (0u64..)
+            .map(|nonce| nonce.to_be_bytes().to_vec())
+            .find(|nonce| target.is_met_by(pow_digest(nonce, DATA)))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_leading_zeros() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let target = PowTarget::LeadingZeros(8);
+        let nonce = find_nonce(target);
+        assert_eq!(pow_digest(&nonce, DATA)[0], 0);
+
+        let circuit =
+            KeccakPowCircuit::<Fr>::new(CONFIG, NUM_ROWS, nonce, DATA.to_vec(), target).unwrap();
+        assert!(circuit.is_valid());
+        let instance = circuit.instance();
+        assert_eq!(instance[1], Fr::from_u128(1 << 120));
+
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+        prover.assert_satisfied();
+
+        // A weaker public bound is not the constant of the target
+        let mut wrong = instance;
+        wrong[1] = Fr::from_u128(1 << 121);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![wrong]).unwrap();
+        assert!(prover.verify().is_err());
+
+        let result = KeccakPowCircuit::<Fr>::new(
+            CONFIG,
+            NUM_ROWS,
+            vec![],
+            DATA.to_vec(),
+            PowTarget::LeadingZeros(0),
+        );
+        assert!(matches!(result, Err(Keccak256Error::InputParsing { .. })));
+    }
+
+    #[test]
+    fn test_below_target() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let nonce = b"nonce".to_vec();
+        let digest = U256::from_big_endian(pow_digest(&nonce, DATA).as_bytes());
+
+        // The digest is below `digest + 1`, but not below itself
+        for (target, valid) in [(digest + 1, true), (digest, false)] {
+            let circuit = KeccakPowCircuit::<Fr>::new(
+                CONFIG,
+                NUM_ROWS,
+                nonce.clone(),
+                DATA.to_vec(),
+                PowTarget::Below(target),
+            )
+            .unwrap();
+            assert_eq!(circuit.is_valid(), valid);
+
+            let prover =
+                MockProver::<Fr>::run(CONFIG.k, &circuit, vec![circuit.instance()]).unwrap();
+            assert_eq!(prover.verify().is_ok(), valid);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use std::fmt;
 use std::str::FromStr;
 use halo2_proofs::halo2curves::bn256::Fr;
 use halo2_proofs::halo2curves::ff::PrimeField;
-use serde::de::{SeqAccess, Visitor};
+use serde::de::{Error, SeqAccess, Visitor};
 use serde::ser::SerializeSeq;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use crate::Keccak256Error;
@@ -21,8 +21,9 @@ pub fn deserialize_circuit_inputs(
                 .iter()
                 .map(|s| {
                     // TODO - support big integers full range, not just u128
-                    let int = u128::from_str(s).map_err(|e| {
-                        Keccak256Error(format!("Failed to parse input as u128: {}", e))
+                    let int = u128::from_str(s).map_err(|e| Keccak256Error::InputParsing {
+                        name: k.clone(),
+                        reason: format!("`{}` is not a u128: {}", s, e),
                     });
 
                     int.map(|i| Fr::from_u128(i))
@@ -57,7 +58,7 @@ impl<'de> Deserialize<'de> for InputsSerialisationWrapper {
             type Value = InputsSerialisationWrapper;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a sequence of byte arrays of length 32")
+                formatter.write_str("a sequence of canonical field elements encoded as 32 byte arrays")
             }
 
             fn visit_seq<A>(self, mut seq: A) -> Result<InputsSerialisationWrapper, A::Error>
@@ -66,7 +67,13 @@ impl<'de> Deserialize<'de> for InputsSerialisationWrapper {
             {
                 let mut vec = Vec::new();
                 while let Some(bytes) = seq.next_element::<[u8; 32]>()? {
-                    vec.push(Fr::from_bytes(&bytes).unwrap());
+                    let fp = Option::<Fr>::from(Fr::from_bytes(&bytes)).ok_or_else(|| {
+                        A::Error::custom(format!(
+                            "public input {} is not a canonical field element",
+                            vec.len()
+                        ))
+                    })?;
+                    vec.push(fp);
                 }
                 Ok(InputsSerialisationWrapper(vec))
             }
@@ -99,6 +106,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_non_canonical_field_element_is_rejected() {
+        // 2^256 - 1 is larger than the field modulus
+        let serialized = serde_json::to_string(&vec![[0xffu8; 32]]).unwrap();
+        let result = serde_json::from_str::<InputsSerialisationWrapper>(&serialized);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_circuit_inputs_deserialization() {
         let mut serialized = HashMap::new();
@@ -109,4 +124,12 @@ mod tests {
         assert_eq!(deserialized.get("out").unwrap()[0], Fr::from(1));
         assert_eq!(deserialized.get("out").unwrap()[1], Fr::from(2));
     }
+
+    #[test]
+    fn test_circuit_inputs_deserialization_reports_bad_value() {
+        let mut serialized = HashMap::new();
+        serialized.insert("in".to_string(), vec!["1".to_string(), "0x02".to_string()]);
+        let err = deserialize_circuit_inputs(serialized).unwrap_err();
+        assert!(matches!(err, Keccak256Error::InputParsing { ref name, .. } if name == "in"));
+    }
 }
@@ -0,0 +1,13 @@
+//! Parameters shared by the tests of the circuits.
+
+use crate::circuit::NUM_UNUSABLE_ROWS;
+use crate::KeccakConfigParams;
+
+/// Small config the circuits are tested with
+pub(crate) const CONFIG: KeccakConfigParams = KeccakConfigParams {
+    k: 12,
+    rows_per_round: 5,
+};
+
+/// Usable rows of a circuit with [`CONFIG`]
+pub(crate) const NUM_ROWS: usize = (1 << CONFIG.k) - NUM_UNUSABLE_ROWS;
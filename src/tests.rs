@@ -19,10 +19,16 @@ use halo2_proofs::{
 use hex::FromHex;
 use rand_core::OsRng;
 use sha3::{Digest, Keccak256};
+use std::cell::Cell;
+use std::io::Read;
+use std::rc::Rc;
 use test_case::test_case;
 
-use crate::circuit::KeccakCircuit;
+use crate::circuit::{KeccakCircuit, NUM_UNUSABLE_ROWS};
+use crate::test_utils::{CONFIG, NUM_ROWS};
 use crate::util::eth_types::Field;
+use crate::vanilla::keccak_packed_multi::get_num_keccak_f;
+use crate::vanilla::param::{NUM_ROUNDS, NUM_WORDS_TO_ABSORB, RATE};
 use crate::vanilla::*;
 
 fn verify_mock<F: Field + Ord + FromUniformBytes<64>>(
@@ -31,7 +37,8 @@ fn verify_mock<F: Field + Ord + FromUniformBytes<64>>(
     _success: bool,
 ) {
     let k = config.k;
-    let circuit = KeccakCircuit::new(config, Some(2usize.pow(k) - 109), inputs, true, false);
+    let num_rows = 2usize.pow(k) - NUM_UNUSABLE_ROWS;
+    let circuit = KeccakCircuit::new(config, Some(num_rows), inputs, true, false);
 
     let prover = MockProver::<F>::run(k, &circuit, vec![vec![]]).unwrap();
     prover.assert_satisfied();
@@ -65,6 +72,166 @@ fn packed_multi_keccak_simple(k: u32, rows_per_round: usize) {
     }
 }
 
+#[test]
+fn multi_keccak_over_capacity_returns_error() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    // A single keccak_f fits in this circuit, the input below needs two.
+    let num_rows = (1 + NUM_WORDS_TO_ABSORB + (NUM_ROUNDS + 1)) * CONFIG.rows_per_round;
+    let inputs = vec![(0u8..200).collect::<Vec<_>>()];
+    let circuit = KeccakCircuit::new(CONFIG, Some(num_rows), inputs, true, false);
+
+    let result = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![vec![]]);
+    assert!(matches!(
+        result,
+        Err(halo2_proofs::plonk::Error::BoundsFailure)
+    ));
+}
+
+/// Reader that records how many bytes have been read from it.
+struct CountingReader<'a> {
+    bytes: &'a [u8],
+    read: Rc<Cell<usize>>,
+}
+
+impl Read for CountingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.bytes.read(buf)?;
+        self.read.set(self.read.get() + n);
+        Ok(n)
+    }
+}
+
+#[test]
+fn multi_keccak_reads_input_block_by_block() {
+    let input = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+    let read = Rc::new(Cell::new(0));
+    let reader = CountingReader {
+        bytes: &input,
+        read: read.clone(),
+    };
+
+    let mut rows =
+        witness::multi_keccak::<Fr, _>(vec![(reader, input.len())], None, CONFIG).unwrap();
+    let rows_per_keccak_f = (NUM_ROUNDS + 1) * CONFIG.rows_per_round;
+
+    // The dummy rows don't need any input
+    for _ in 0..CONFIG.rows_per_round {
+        rows.next().unwrap().unwrap();
+    }
+    assert_eq!(read.get(), 0);
+    // The rows of a keccak_f only need its own block
+    rows.next().unwrap().unwrap();
+    assert_eq!(read.get(), RATE);
+    for _ in 1..rows_per_keccak_f {
+        rows.next().unwrap().unwrap();
+    }
+    assert_eq!(read.get(), RATE);
+    rows.next().unwrap().unwrap();
+    assert_eq!(read.get(), 2 * RATE);
+
+    let num_rows = rows.count() + CONFIG.rows_per_round + rows_per_keccak_f + 1;
+    assert_eq!(
+        num_rows,
+        (1 + get_num_keccak_f(input.len()) * (NUM_ROUNDS + 1)) * CONFIG.rows_per_round
+    );
+}
+
+#[test]
+fn multi_keccak_bytes_matches_readers() {
+    let inputs = vec![
+        vec![],
+        (0u8..135).collect::<Vec<_>>(),
+        (0u8..200).collect::<Vec<_>>(),
+    ];
+    let slices = inputs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let readers = slices.iter().map(|input| (*input, input.len())).collect();
+
+    let parallel = witness::multi_keccak_bytes::<Fr>(&slices, Some(6), CONFIG).unwrap();
+    let lazy = witness::multi_keccak::<Fr, _>(readers, Some(6), CONFIG).unwrap();
+    let mut num_rows = 0;
+    for (parallel, lazy) in parallel.zip(lazy) {
+        let (parallel, lazy) = (parallel.unwrap(), lazy.unwrap());
+        assert_eq!(parallel.cell_values, lazy.cell_values);
+        assert_eq!(
+            (parallel.is_final, parallel.bytes_left, parallel.word_value),
+            (lazy.is_final, lazy.bytes_left, lazy.word_value)
+        );
+        num_rows += 1;
+    }
+    assert_eq!(num_rows, (1 + 6 * (NUM_ROUNDS + 1)) * CONFIG.rows_per_round);
+}
+
+#[test]
+fn multi_keccak_rejects_input_of_wrong_length() {
+    let input = (0u8..200).collect::<Vec<_>>();
+
+    for len in [199, 201] {
+        let rows =
+            witness::multi_keccak::<Fr, _>(vec![(input.as_slice(), len)], None, CONFIG).unwrap();
+        let errors = rows.filter_map(|row| row.err()).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1, "declared length {}", len);
+    }
+}
+
+#[test]
+fn packed_multi_keccak_public_states() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    // keccak_f 0 and 1 hash the first input, keccak_f 2 the second one and keccak_f 3 is padding
+    let inputs = vec![
+        (0u8..200).collect::<Vec<_>>(),
+        (0u8..10).collect::<Vec<_>>(),
+    ];
+
+    // The witness generator carries the same state on after the first block
+    let states = witness::keccak_states::<Fr>(&inputs[0]);
+    let mut stream = witness::KeccakRowStream::<Fr, _>::new(inputs[0].as_slice(), 200, CONFIG);
+    for row in stream
+        .by_ref()
+        .take((NUM_ROUNDS + 1) * CONFIG.rows_per_round)
+    {
+        row.unwrap();
+    }
+    assert_eq!(stream.state(), states[0]);
+    // The digest is squeezed from the last state
+    let digest = Keccak256::digest(&inputs[0]);
+    for (i, lane) in digest.chunks(8).enumerate() {
+        let lane = u64::from_le_bytes(lane.try_into().unwrap());
+        assert_eq!(states[1][i][0], util::pack_u64::<Fr>(lane));
+    }
+
+    let circuit = KeccakCircuit::new(CONFIG, Some(NUM_ROWS), inputs, true, false)
+        .with_public_states(vec![0, 2, 3]);
+    let instance = circuit.instance();
+    assert_eq!(instance.len(), 3 * 25);
+    let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+    prover.assert_satisfied();
+
+    let mut tampered = instance;
+    tampered[30] += Fr::from(1);
+    let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![tampered]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn packed_multi_keccak_public_states_before_input_words() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    // A single keccak_f, its 100 bytes take 13 words
+    let inputs = vec![(0u8..100).collect::<Vec<_>>()];
+    let circuit =
+        KeccakCircuit::new(CONFIG, Some(NUM_ROWS), inputs, true, true).with_public_states(vec![0]);
+    let instance = circuit.instance();
+    assert_eq!(instance.len(), 25 + 13);
+    let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![instance.clone()]).unwrap();
+    prover.assert_satisfied();
+
+    for idx in [3, 25 + 3] {
+        let mut tampered = instance.clone();
+        tampered[idx] += Fr::from(1);
+        let prover = MockProver::<Fr>::run(CONFIG.k, &circuit, vec![tampered]).unwrap();
+        assert!(prover.verify().is_err(), "tampered instance cell {}", idx);
+    }
+}
+
 #[ignore]
 #[test_case(14, 25 ; "k: 14, rows_per_round: 25")]
 #[test_case(18, 9 ; "k: 18, rows_per_round: 9")]
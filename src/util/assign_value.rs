@@ -1,6 +1,6 @@
 use halo2_proofs::arithmetic::Field;
 use halo2_proofs::circuit::{Cell, Region, Value};
-use halo2_proofs::plonk::{Advice, Assigned, Column, Fixed};
+use halo2_proofs::plonk::{Advice, Assigned, Column, Error, Fixed};
 
 use crate::util::Halo2AssignedCell;
 
@@ -11,16 +11,14 @@ pub fn raw_assign_advice<'v, F: Field>(
     column: Column<Advice>,
     offset: usize,
     value: Value<impl Into<Assigned<F>>>,
-) -> Halo2AssignedCell<'v, F> {
+) -> Result<Halo2AssignedCell<'v, F>, Error> {
     let value = value.map(|a| Into::<Assigned<F>>::into(a));
-    region
-        .assign_advice(
-            || format!("assign advice {column:?} offset {offset}"),
-            column,
-            offset,
-            || value,
-        )
-        .unwrap()
+    region.assign_advice(
+        || format!("assign advice {column:?} offset {offset}"),
+        column,
+        offset,
+        || value,
+    )
 }
 
 #[inline(always)]
@@ -29,7 +27,7 @@ pub fn raw_assign_fixed<F: Field>(
     column: Column<Fixed>,
     offset: usize,
     value: F,
-) -> Cell {
+) -> Result<Cell, Error> {
     region
         .assign_fixed(
             || format!("assign fixed {column:?} offset {offset}"),
@@ -37,6 +35,5 @@ pub fn raw_assign_fixed<F: Field>(
             offset,
             || Value::known(value),
         )
-        .unwrap()
-        .cell()
+        .map(|cell| cell.cell())
 }
\ No newline at end of file
@@ -48,6 +48,27 @@ pub trait ScalarField: PrimeField + FromUniformBytes<64> + From<bool> + Hash + O
         }
         lower_64
     }
+
+    /// Reduces a big-endian 256 bit digest modulo the field order.
+    ///
+    /// For a ~254 bit field the result is biased towards small values, see [`ScalarField::from_wide_digest`].
+    fn from_digest(digest: &[u8; 32]) -> Self {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(digest);
+        bytes[..32].reverse();
+        Self::from_uniform_bytes(&bytes)
+    }
+
+    /// Reduces the big-endian 512 bit value `high || low` of two digests modulo the field order.
+    ///
+    /// The result is statistically close to uniform if the digests are.
+    fn from_wide_digest(high: &[u8; 32], low: &[u8; 32]) -> Self {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(high);
+        bytes[32..].copy_from_slice(low);
+        bytes.reverse();
+        Self::from_uniform_bytes(&bytes)
+    }
 }
 // See below for implementations
 
@@ -58,6 +58,7 @@ pub struct KeccakRow<F: PrimeField> {
     pub(crate) q_round_last: bool,
     pub(crate) q_input: bool,
     pub(crate) q_input_last: bool,
+    pub(crate) q_continue: bool,
     pub(crate) round_cst: F,
     pub(crate) is_final: bool,
     pub(crate) cell_values: Vec<F>,
@@ -77,6 +78,7 @@ impl<F: PrimeField> KeccakRow<F> {
                 q_round_last: false,
                 q_input: false,
                 q_input_last: false,
+                q_continue: false,
                 round_cst: F::ZERO,
                 is_final: false,
                 cell_values: Vec::new(),
@@ -55,6 +55,9 @@ pub struct KeccakCircuitConfig<F> {
     q_input: Column<Fixed>,
     // Bool. True on 1st row of all last input round.
     q_input_last: Column<Fixed>,
+    // Bool. True on 1st row when the first hash continues from the state in the dummy round
+    // instead of starting from the all-zero state.
+    q_continue: Column<Fixed>,
 
     pub keccak_table: KeccakTable,
 
@@ -85,6 +88,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
         let q_round_last = meta.fixed_column();
         let q_input = meta.fixed_column();
         let q_input_last = meta.fixed_column();
+        let q_continue = meta.fixed_column();
         let round_cst = meta.fixed_column();
         let keccak_table = KeccakTable::construct(meta);
 
@@ -462,7 +466,11 @@ impl<F: Field> KeccakCircuitConfig<F> {
         // Absorb
         meta.create_gate("absorb", |meta| {
             let mut cb = BaseConstraintBuilder::new(MAX_DEGREE);
-            let continue_hash = not::expr(start_new_hash(meta, Rotation::cur()));
+            // On the first row `q_continue` cancels `q_first`, so the first hash absorbs into the
+            // state of the dummy round.
+            let continue_hash = not::expr(
+                start_new_hash(meta, Rotation::cur()) - meta.query_fixed(q_continue, Rotation::cur()),
+            );
             let absorb_positions = get_absorb_positions();
             let mut a_slice = 0;
             for j in 0..5 {
@@ -787,6 +795,7 @@ impl<F: Field> KeccakCircuitConfig<F> {
             q_round_last,
             q_input,
             q_input_last,
+            q_continue,
             keccak_table,
             cell_manager,
             round_cst,
@@ -801,6 +810,10 @@ impl<F: Field> KeccakCircuitConfig<F> {
     }
 }
 
+/// The cells of a round are queried as the state, followed by `absorb_from`, `absorb_data` and
+/// `absorb_result`, filling the `num_rows_per_round` rows of a column before the next one.
+const ABSORB_DATA_CELL: usize = KECCAK_WIDTH + 1;
+
 #[derive(Clone)]
 pub struct KeccakAssignedRow<'v, F: Field> {
     pub is_final: KeccakAssignedValue<'v, F>,
@@ -808,20 +821,49 @@ pub struct KeccakAssignedRow<'v, F: Field> {
     pub hash_hi: KeccakAssignedValue<'v, F>,
     pub bytes_left: KeccakAssignedValue<'v, F>,
     pub word_value: KeccakAssignedValue<'v, F>,
+    /// The state cells located on this row, see [`KeccakCircuitConfig::state_cells`].
+    pub state: Vec<KeccakAssignedValue<'v, F>>,
+    /// The cell of the packed absorbed word if it is on this row, see
+    /// [`KeccakCircuitConfig::absorbed_word_cell`].
+    pub absorbed_word: Option<KeccakAssignedValue<'v, F>>,
     pub _marker: PhantomData<&'v ()>,
 }
 
 impl<F: Field> KeccakCircuitConfig<F> {
     /// Returns vector of `is_final`, `length`, `hash.lo`, `hash.hi` for assigned rows
+    ///
+    /// The witness rows are consumed one at a time, so they can be generated lazily by
+    /// [`witness::multi_keccak`]. A failure to read the witness is reported as [`Error::Synthesis`].
     pub fn assign<'v>(
         &self,
         region: &mut Region<F>,
-        witness: &[KeccakRow<F>],
-    ) -> Vec<KeccakAssignedRow<'v, F>> {
+        witness: impl IntoIterator<Item = std::io::Result<KeccakRow<F>>>,
+    ) -> Result<Vec<KeccakAssignedRow<'v, F>>, Error> {
+        self.assign_from(region, 0, witness)
+    }
+
+    /// Assigns the witness rows like [`Self::assign`], from `offset` on.
+    ///
+    /// The first row starts a new run of hashes like the first row of the circuit, so that several
+    /// runs can share a region. A run followed by another one has to end with `NUM_WORDS_TO_ABSORB`
+    /// rounds without selectors, e.g. [`witness::continuation_end_rows`], as the absorb gate of its
+    /// last keccak_f looks that far ahead. The returned rows are indexed from the start of the run.
+    pub fn assign_from<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        witness: impl IntoIterator<Item = std::io::Result<KeccakRow<F>>>,
+    ) -> Result<Vec<KeccakAssignedRow<'v, F>>, Error> {
         witness
-            .iter()
+            .into_iter()
             .enumerate()
-            .map(|(offset, keccak_row)| self.set_row(region, offset, keccak_row))
+            .map(|(idx, keccak_row)| {
+                let keccak_row = keccak_row.map_err(|e| {
+                    log::error!("Failed to read the keccak witness: {}", e);
+                    Error::Synthesis
+                })?;
+                self.assign_row(region, offset + idx, &keccak_row, idx == 0)
+            })
             .collect()
     }
 
@@ -831,18 +873,29 @@ impl<F: Field> KeccakCircuitConfig<F> {
         region: &mut Region<F>,
         offset: usize,
         row: &KeccakRow<F>,
-    ) -> KeccakAssignedRow<'v, F> {
+    ) -> Result<KeccakAssignedRow<'v, F>, Error> {
+        self.assign_row(region, offset, row, offset == 0)
+    }
+
+    fn assign_row<'v>(
+        &self,
+        region: &mut Region<F>,
+        offset: usize,
+        row: &KeccakRow<F>,
+        is_first: bool,
+    ) -> Result<KeccakAssignedRow<'v, F>, Error> {
         // Fixed selectors
         for (_, column, value) in &[
             ("q_enable", self.q_enable, F::from(row.q_enable)),
-            ("q_first", self.q_first, F::from(offset == 0)),
+            ("q_first", self.q_first, F::from(is_first)),
             ("q_round", self.q_round, F::from(row.q_round)),
             ("q_round_last", self.q_round_last, F::from(row.q_round_last)),
             ("q_absorb", self.q_absorb, F::from(row.q_absorb)),
             ("q_input", self.q_input, F::from(row.q_input)),
             ("q_input_last", self.q_input_last, F::from(row.q_input_last)),
+            ("q_continue", self.q_continue, F::from(row.q_continue)),
         ] {
-            raw_assign_fixed(region, *column, offset, *value);
+            raw_assign_fixed(region, *column, offset, *value)?;
         }
 
         // Keccak data
@@ -856,23 +909,142 @@ impl<F: Field> KeccakCircuitConfig<F> {
         .map(|(_name, column, value)| raw_assign_advice(region, column, offset, value));
 
         // Cell values
-        row.cell_values.iter().zip(self.cell_manager.columns()).for_each(|(bit, column)| {
-            raw_assign_advice(region, column.advice, offset, Value::known(*bit));
-        });
+        // The first KECCAK_WIDTH cells of a round hold the state, `num_rows_per_round` per column.
+        let num_rows_per_round = self.parameters.rows_per_round;
+        let mut state = Vec::new();
+        let mut absorbed_word = None;
+        for (idx, (bit, column)) in
+            row.cell_values.iter().zip(self.cell_manager.columns()).enumerate()
+        {
+            let cell = raw_assign_advice(region, column.advice, offset, Value::known(*bit))?;
+            let cell_idx = offset % num_rows_per_round + idx * num_rows_per_round;
+            if cell_idx == ABSORB_DATA_CELL {
+                absorbed_word = Some(cell.clone());
+            }
+            if cell_idx < KECCAK_WIDTH {
+                state.push(cell);
+            }
+        }
 
         // Round constant
-        raw_assign_fixed(region, self.round_cst, offset, row.round_cst);
-
-        KeccakAssignedRow {
-            is_final,
-            hash_lo,
-            hash_hi,
-            bytes_left,
-            word_value,
+        raw_assign_fixed(region, self.round_cst, offset, row.round_cst)?;
+
+        Ok(KeccakAssignedRow {
+            is_final: is_final?,
+            hash_lo: hash_lo?,
+            hash_hi: hash_hi?,
+            bytes_left: bytes_left?,
+            word_value: word_value?,
+            state,
+            absorbed_word,
             _marker: PhantomData,
+        })
+    }
+
+    /// Returns the 25 state cells `s[i][j]` of the round starting at `round_offset`, ordered by
+    /// `i * 5 + j`. In the last round of a keccak_f they hold the state after the permutation.
+    ///
+    /// Returns `None` if the state of that round was not assigned, like in the dummy round of a
+    /// hash that does not continue from a given state.
+    pub fn state_cells<'v>(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<'v, F>],
+        round_offset: usize,
+    ) -> Option<Vec<KeccakAssignedValue<'v, F>>> {
+        let num_rows_per_round = self.parameters.rows_per_round;
+        (0..KECCAK_WIDTH)
+            .map(|idx| {
+                assigned_rows
+                    .get(round_offset + idx % num_rows_per_round)?
+                    .state
+                    .get(idx / num_rows_per_round)
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Returns the 25 state cells holding the sponge state after the keccak_f with index
+    /// `keccak_f_idx`, counting the keccak_f's of all hashes in the region from the first one.
+    ///
+    /// These are the cells of the last round of that keccak_f, see [`Self::state_cells`]. Returns
+    /// `None` if the region does not have that many keccak_f's.
+    pub fn state_cells_after_keccak_f<'v>(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<'v, F>],
+        keccak_f_idx: usize,
+    ) -> Option<Vec<KeccakAssignedValue<'v, F>>> {
+        let round = 1 + keccak_f_idx * (NUM_ROUNDS + 1) + NUM_ROUNDS;
+        self.state_cells(assigned_rows, round * self.parameters.rows_per_round)
+    }
+
+    /// Returns the row of the last round of the keccak_f with index `keccak_f_idx`, counting the
+    /// keccak_f's of all hashes in the region from the first one. Its `is_final` is set if the
+    /// keccak_f finishes a hash, `hash_lo` and `hash_hi` then hold the digest.
+    pub fn keccak_f_output_row<'a, 'v>(
+        &self,
+        assigned_rows: &'a [KeccakAssignedRow<'v, F>],
+        keccak_f_idx: usize,
+    ) -> Option<&'a KeccakAssignedRow<'v, F>> {
+        let round = 1 + keccak_f_idx * (NUM_ROUNDS + 1) + NUM_ROUNDS;
+        assigned_rows.get(round * self.parameters.rows_per_round)
+    }
+
+    /// Returns the row absorbing the input word with index `word_idx` of the hash that starts at
+    /// the keccak_f with index `first_keccak_f`. Its `word_value` holds the little-endian word of
+    /// `NUM_BYTES_PER_WORD` input bytes, with the bytes after the end of the input set to zero,
+    /// and its `bytes_left` the number of input bytes from the start of the word on.
+    pub fn input_word_row<'a, 'v>(
+        &self,
+        assigned_rows: &'a [KeccakAssignedRow<'v, F>],
+        first_keccak_f: usize,
+        word_idx: usize,
+    ) -> Option<&'a KeccakAssignedRow<'v, F>> {
+        let keccak_f_idx = first_keccak_f + word_idx / NUM_WORDS_TO_ABSORB;
+        let round = 1 + keccak_f_idx * (NUM_ROUNDS + 1) + word_idx % NUM_WORDS_TO_ABSORB;
+        assigned_rows.get(round * self.parameters.rows_per_round)
+    }
+
+    /// Returns the cell holding the input word with index `word_idx` of the hash that starts at the
+    /// keccak_f with index `first_keccak_f`, packed like the state cells. The word is absorbed into
+    /// the lane of its position in the rate, a word without padding is the packed little-endian
+    /// word of its `NUM_BYTES_PER_WORD` input bytes.
+    ///
+    /// The cells can be copied after [`Self::enable_absorbed_word_equality`].
+    pub fn absorbed_word_cell<'v>(
+        &self,
+        assigned_rows: &[KeccakAssignedRow<'v, F>],
+        first_keccak_f: usize,
+        word_idx: usize,
+    ) -> Option<KeccakAssignedValue<'v, F>> {
+        let keccak_f_idx = first_keccak_f + word_idx / NUM_WORDS_TO_ABSORB;
+        let round = 1 + keccak_f_idx * (NUM_ROUNDS + 1) + word_idx % NUM_WORDS_TO_ABSORB;
+        let num_rows_per_round = self.parameters.rows_per_round;
+        assigned_rows
+            .get(round * num_rows_per_round + ABSORB_DATA_CELL % num_rows_per_round)?
+            .absorbed_word
+            .clone()
+    }
+
+    /// Enables equality on the column of the absorbed words, see [`Self::absorbed_word_cell`].
+    pub fn enable_absorbed_word_equality(&self, meta: &mut ConstraintSystem<F>) {
+        let column = ABSORB_DATA_CELL / self.parameters.rows_per_round;
+        meta.enable_equality(self.cell_manager.columns()[column].advice);
+    }
+
+    /// Enables equality on the columns of the state, see [`Self::state_cells`], e.g. to copy the
+    /// state out to continue a hash in another circuit.
+    pub fn enable_state_equality(&self, meta: &mut ConstraintSystem<F>) {
+        let num_columns = (KECCAK_WIDTH - 1) / self.parameters.rows_per_round + 1;
+        for column in &self.cell_manager.columns()[..num_columns] {
+            meta.enable_equality(column.advice);
         }
     }
 
+    /// The lookup table column holding all byte values, for range checking bytes.
+    pub fn byte_table(&self) -> TableColumn {
+        self.pack_table[0]
+    }
+
     pub fn load_aux_tables(&self, layouter: &mut impl Layouter<F>, k: u32) -> Result<(), Error> {
         load_normalize_table(layouter, "normalize_6", &self.normalize_6, 6u64, k)?;
         load_normalize_table(layouter, "normalize_4", &self.normalize_4, 4u64, k)?;
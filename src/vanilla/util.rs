@@ -89,6 +89,53 @@ pub fn get_rotate_count(count: usize, part_size: usize) -> usize {
     (count + part_size - 1) / part_size
 }
 
+/// Native keccak_f permutation on the lanes `s[i][j]`, following the same steps as the circuit.
+pub fn keccak_f1600(s: &mut [[u64; 5]; 5]) {
+    for round_cst in ROUND_CST.iter().take(NUM_ROUNDS) {
+        // Theta
+        let c = s.map(|s| s.iter().fold(0, |acc, s| acc ^ s));
+        for i in 0..5 {
+            let t = c[(i + 4) % 5] ^ c[(i + 1) % 5].rotate_left(1);
+            for j in 0..5 {
+                s[i][j] ^= t;
+            }
+        }
+        // Rho/Pi
+        let mut os = [[0u64; 5]; 5];
+        for i in 0..5 {
+            for j in 0..5 {
+                os[j][(2 * i + 3 * j) % 5] = s[i][j].rotate_left(RHO_MATRIX[i][j] as u32);
+            }
+        }
+        // Chi
+        for i in 0..5 {
+            for j in 0..5 {
+                s[i][j] = os[i][j] ^ (!os[(i + 1) % 5][j] & os[(i + 2) % 5][j]);
+            }
+        }
+        // Iota
+        s[0][0] ^= round_cst;
+    }
+}
+
+/// Natively xors a block of at most `RATE` little-endian bytes into the lanes `s[i][j]`
+pub fn absorb_block(s: &mut [[u64; 5]; 5], block: &[u8]) {
+    for (&(i, j), word) in get_absorb_positions().iter().zip(block.chunks(NUM_BYTES_PER_WORD)) {
+        let mut word_bytes = [0u8; NUM_BYTES_PER_WORD];
+        word_bytes[..word.len()].copy_from_slice(word);
+        s[i][j] ^= u64::from_le_bytes(word_bytes);
+    }
+}
+
+/// Applies the keccak padding `0x01 .. 0x80` to `input`, the result is a whole number of blocks
+pub fn pad_keccak_input(input: &[u8]) -> Vec<u8> {
+    let mut padded = input.to_vec();
+    padded.push(1);
+    padded.resize((input.len() / RATE + 1) * RATE, 0);
+    *padded.last_mut().unwrap() |= 0x80;
+    padded
+}
+
 /// Encodes the data using rlc
 pub mod compose_rlc {
     use halo2_proofs::plonk::Expression;
@@ -1,75 +1,323 @@
 // This file is moved out from mod.rs.
 use super::*;
-
-/// Witness generation for multiple keccak hashes of little-endian `bytes`.
-pub fn multi_keccak<F: Field>(
-    bytes: &[Vec<u8>],
+use std::io::{self, Read};
+
+/// Witness generation for multiple keccak hashes of little-endian input.
+///
+/// Every input is a reader together with its length in bytes: the length is needed upfront because
+/// `bytes_left` is assigned from the first row of a hash on. The rows are generated lazily, one
+/// keccak_f at a time, so only a single input block and its rows are held in memory.
+///
+/// Returns [`Error::BoundsFailure`] if the inputs do not fit in `capacity` keccak_f's.
+pub fn multi_keccak<F: Field, R: Read>(
+    inputs: Vec<(R, usize)>,
     capacity: Option<usize>,
     parameters: KeccakConfigParams,
-) -> (Vec<KeccakRow<F>>, Vec<[F; NUM_WORDS_TO_SQUEEZE]>) {
-    let num_rows_per_round = parameters.rows_per_round;
-    let mut rows =
-        Vec::with_capacity((1 + capacity.unwrap_or(0) * (NUM_ROUNDS + 1)) * num_rows_per_round);
+) -> Result<impl Iterator<Item = io::Result<KeccakRow<F>>>, Error> {
+    let num_padding = num_padding_keccak_f(inputs.iter().map(|(_, len)| *len), capacity)?;
     // Dummy first row so that the initial data is absorbed
     // The initial data doesn't really matter, `is_final` just needs to be disabled.
-    rows.append(&mut KeccakRow::dummy_rows(num_rows_per_round));
+    let dummy_rows = KeccakRow::dummy_rows(parameters.rows_per_round).into_iter().map(Ok);
     // Actual keccaks
-    let artifacts = bytes
+    let hashes = inputs
+        .into_iter()
+        .flat_map(move |(reader, len)| KeccakRowStream::new(reader, len, parameters));
+    let padding = (0..num_padding)
+        .flat_map(move |_| KeccakRowStream::new(io::empty(), 0, parameters));
+    Ok(dummy_rows.chain(hashes).chain(padding))
+}
+
+/// Witness generation for multiple keccak hashes of little-endian `inputs` held in memory.
+///
+/// The rows of the inputs are generated in parallel and kept in memory, only the padding hashes
+/// are generated lazily. Use [`multi_keccak`] with readers for inputs too large to hold their rows.
+///
+/// Returns [`Error::BoundsFailure`] if the inputs do not fit in `capacity` keccak_f's.
+pub fn multi_keccak_bytes<F: Field>(
+    inputs: &[&[u8]],
+    capacity: Option<usize>,
+    parameters: KeccakConfigParams,
+) -> Result<impl Iterator<Item = io::Result<KeccakRow<F>>>, Error> {
+    let num_padding = num_padding_keccak_f(inputs.iter().map(|input| input.len()), capacity)?;
+    let dummy_rows = KeccakRow::dummy_rows(parameters.rows_per_round).into_iter().map(Ok);
+    // Actual keccaks
+    let hashes = inputs
         .par_iter()
-        .map(|bytes| {
-            let num_keccak_f = get_num_keccak_f(bytes.len());
-            let mut squeeze_digests = Vec::with_capacity(num_keccak_f);
-            let mut rows = Vec::with_capacity(num_keccak_f * (NUM_ROUNDS + 1) * num_rows_per_round);
-            keccak(&mut rows, &mut squeeze_digests, bytes, parameters);
-            (rows, squeeze_digests)
-        })
+        .map(|input| KeccakRowStream::new(*input, input.len(), parameters).collect::<Vec<_>>())
         .collect::<Vec<_>>();
+    let padding = (0..num_padding)
+        .flat_map(move |_| KeccakRowStream::new(io::empty(), 0, parameters));
+    Ok(dummy_rows.chain(hashes.into_iter().flatten()).chain(padding))
+}
 
-    let mut squeeze_digests = Vec::with_capacity(capacity.unwrap_or(0));
-    for (rows_part, squeezes) in artifacts {
-        rows.extend(rows_part);
-        squeeze_digests.extend(squeezes);
+/// The number of empty hashes padding inputs of `lengths` bytes to `capacity` keccak_f's.
+///
+/// Returns [`Error::BoundsFailure`] if the inputs do not fit in `capacity` keccak_f's.
+fn num_padding_keccak_f(
+    lengths: impl Iterator<Item = usize>,
+    capacity: Option<usize>,
+) -> Result<usize, Error> {
+    let num_keccak_f = lengths.map(get_num_keccak_f).sum::<usize>();
+    // Pad with no data hashes to the expected capacity
+    match capacity {
+        // Check that we are not over capacity
+        Some(capacity) if num_keccak_f > capacity => Err(Error::BoundsFailure),
+        Some(capacity) => Ok(capacity - num_keccak_f),
+        None => Ok(0),
+    }
+}
+
+/// Witness generation for one chunk of a hash that is split over several circuits.
+///
+/// The chunk continues the sponge from `start_state` (the packed lanes `s[i][j]`) and absorbs the
+/// `len` bytes read from `reader`. A chunk that does not finish the hash has to fill all `capacity`
+/// keccak_f's with whole blocks, the rows after them carry its end state on. The last chunk
+/// finishes the hash and is padded with empty hashes like in [`multi_keccak`], followed by the
+/// same rows for the all-zero state, so another run of hashes can follow in the region.
+///
+/// Returns [`Error::BoundsFailure`] if the chunk does not fit, or does not fill, the circuit.
+pub fn keccak_chunk<F: Field, R: Read>(
+    reader: R,
+    len: usize,
+    start_state: [[F; 5]; 5],
+    is_last: bool,
+    capacity: usize,
+    parameters: KeccakConfigParams,
+) -> Result<impl Iterator<Item = io::Result<KeccakRow<F>>>, Error> {
+    let num_keccak_f = if is_last { get_num_keccak_f(len) } else { len / RATE };
+    if num_keccak_f > capacity || (!is_last && (len % RATE != 0 || num_keccak_f != capacity)) {
+        return Err(Error::BoundsFailure);
     }
+    let start_rows = continuation_start_rows(&start_state, parameters).into_iter().map(Ok);
+    let hash = KeccakRowStream::with_state(reader, len, start_state, is_last, parameters);
+    let padding = (num_keccak_f..capacity)
+        .flat_map(move |_| KeccakRowStream::new(io::empty(), 0, parameters));
+    let end_rows = if is_last {
+        continuation_end_rows(&[[F::ZERO; 5]; 5], parameters)
+    } else {
+        vec![]
+    };
+    Ok(start_rows.chain(hash).chain(padding).chain(end_rows.into_iter().map(Ok)))
+}
 
-    if let Some(capacity) = capacity {
-        // Pad with no data hashes to the expected capacity
-        while rows.len() < (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round {
-            keccak(&mut rows, &mut squeeze_digests, &[], parameters);
+/// The sponge state `s` after every keccak_f of the hash of `input`, in the packed form of the
+/// state cells. The last one is the state the digest is squeezed from.
+///
+/// Computed natively, the states match the cells returned by
+/// [`KeccakCircuitConfig::state_cells_after_keccak_f`].
+pub fn keccak_states<F: Field>(input: &[u8]) -> Vec<[[F; 5]; 5]> {
+    let mut s = [[0u64; 5]; 5];
+    pad_keccak_input(input)
+        .chunks(RATE)
+        .map(|block| {
+            absorb_block(&mut s, block);
+            keccak_f1600(&mut s);
+            s.map(|s| s.map(pack_u64))
+        })
+        .collect()
+}
+
+/// Lazy witness generation for the keccak hash of the `len` bytes read from `reader`.
+///
+/// The input is read one block of `RATE` bytes at a time and the rows of the corresponding keccak_f
+/// are yielded before the next block is read. A reader that ends early or has bytes beyond `len`
+/// yields an [`io::Error`] and ends the stream.
+pub struct KeccakRowStream<F: Field, R: Read> {
+    reader: R,
+    bytes_left: usize,
+    s: [[F; 5]; 5],
+    is_last: bool,
+    rows: std::vec::IntoIter<KeccakRow<F>>,
+    done: bool,
+    parameters: KeccakConfigParams,
+}
+
+impl<F: Field, R: Read> KeccakRowStream<F, R> {
+    pub fn new(reader: R, len: usize, parameters: KeccakConfigParams) -> Self {
+        Self::with_state(reader, len, [[F::ZERO; 5]; 5], true, parameters)
+    }
+
+    /// Continues a hash from the sponge state `s`.
+    ///
+    /// If `is_last` is false the hash goes on after this input: `len` has to be a multiple of `RATE`,
+    /// no padding is absorbed and the stream ends with [`continuation_end_rows`].
+    pub fn with_state(
+        reader: R,
+        len: usize,
+        s: [[F; 5]; 5],
+        is_last: bool,
+        parameters: KeccakConfigParams,
+    ) -> Self {
+        Self {
+            reader,
+            bytes_left: len,
+            s,
+            is_last,
+            rows: Vec::new().into_iter(),
+            done: false,
+            parameters,
         }
-        // Check that we are not over capacity
-        if rows.len() > (1 + capacity * (NUM_ROUNDS + 1)) * num_rows_per_round {
-            panic!("{:?}", Error::BoundsFailure);
+    }
+
+    /// The sponge state after the keccak_f's generated so far, in packed form.
+    pub fn state(&self) -> [[F; 5]; 5] {
+        self.s
+    }
+
+    /// Reads the next block and generates the rows of its keccak_f.
+    fn next_block(&mut self) -> io::Result<Vec<KeccakRow<F>>> {
+        let is_final_block = self.bytes_left < RATE;
+        if is_final_block && !self.is_last {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a keccak input that does not finish the hash needs whole blocks",
+            ));
+        }
+        let mut block = vec![0u8; std::cmp::min(self.bytes_left, RATE)];
+        self.reader.read_exact(&mut block)?;
+        self.done = is_final_block || (!self.is_last && self.bytes_left == block.len());
+        if self.done && self.reader.read(&mut [0u8])? != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "keccak input is longer than its declared length",
+            ));
+        }
+
+        let mut rows = Vec::with_capacity((NUM_ROUNDS + 1) * self.parameters.rows_per_round);
+        keccak_f(&mut rows, &mut self.s, &block, self.bytes_left, self.parameters);
+        self.bytes_left -= block.len();
+        if self.done && !self.is_last {
+            rows.extend(continuation_end_rows(&self.s, self.parameters));
+        }
+        Ok(rows)
+    }
+}
+
+impl<F: Field, R: Read> Iterator for KeccakRowStream<F, R> {
+    type Item = io::Result<KeccakRow<F>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(row) = self.rows.next() {
+            return Some(Ok(row));
+        }
+        if self.done {
+            return None;
+        }
+        match self.next_block() {
+            Ok(rows) => {
+                self.rows = rows.into_iter();
+                self.rows.next().map(Ok)
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
         }
     }
-    (rows, squeeze_digests)
 }
-/// Witness generation for keccak hash of little-endian `bytes`.
-fn keccak<F: Field>(
+
+/// Rows of the dummy round when the first hash continues from the state `s`.
+/// The state cells of the round are assigned and `q_continue` is enabled on its first row.
+pub fn continuation_start_rows<F: Field>(
+    s: &[[F; 5]; 5],
+    parameters: KeccakConfigParams,
+) -> Vec<KeccakRow<F>> {
+    let num_rows_per_round = parameters.rows_per_round;
+    let mut cell_manager = CellManager::new(num_rows_per_round);
+    let mut region = KeccakRegion::new();
+    for s in s {
+        for s in s {
+            let cell = cell_manager.query_cell_value();
+            cell.assign(&mut region, 0, *s);
+        }
+    }
+    let mut rows = KeccakRow::dummy_rows(num_rows_per_round);
+    for (row_idx, row) in rows.iter_mut().enumerate() {
+        row.q_continue = row_idx == 0;
+        row.cell_values = region.rows.get(row_idx).cloned().unwrap_or_default();
+    }
+    rows
+}
+
+/// Rows following the last keccak_f of a hash that is continued in another circuit.
+///
+/// The absorb gate of that keccak_f looks `NUM_WORDS_TO_ABSORB` rounds ahead for the next block,
+/// these rounds carry the end state `s` on with nothing absorbed into it. No selectors are enabled.
+pub fn continuation_end_rows<F: Field>(
+    s: &[[F; 5]; 5],
+    parameters: KeccakConfigParams,
+) -> Vec<KeccakRow<F>> {
+    let num_rows_per_round = parameters.rows_per_round;
+    let absorb_positions = get_absorb_positions();
+    let mut rows = Vec::with_capacity(NUM_WORDS_TO_ABSORB * num_rows_per_round);
+    for &(i, j) in absorb_positions.iter() {
+        let mut cell_manager = CellManager::new(num_rows_per_round);
+        let mut region = KeccakRegion::new();
+        // State data
+        for s in s {
+            for s in s {
+                let cell = cell_manager.query_cell_value();
+                cell.assign(&mut region, 0, *s);
+            }
+        }
+        // Absorb data: absorbing zero keeps the word as it is
+        for value in [s[i][j], F::ZERO, s[i][j]] {
+            let cell = cell_manager.query_cell_value();
+            cell.assign(&mut region, 0, value);
+        }
+        for row_idx in 0..num_rows_per_round {
+            rows.push(KeccakRow {
+                q_enable: false,
+                q_round: false,
+                q_absorb: false,
+                q_round_last: false,
+                q_input: false,
+                q_input_last: false,
+                q_continue: false,
+                round_cst: F::ZERO,
+                is_final: false,
+                cell_values: region.rows.get(row_idx).cloned().unwrap_or_default(),
+                hash: Word::default().into_value(),
+                bytes_left: F::ZERO,
+                word_value: F::ZERO,
+            });
+        }
+    }
+    rows
+}
+
+/// Witness generation for a single keccak_f of a hash.
+///
+/// `block` holds the raw input bytes absorbed by this keccak_f, `bytes_left` the number of input
+/// bytes of the hash not absorbed before it. The block is the final one of the hash (and gets padded)
+/// when fewer than `RATE` bytes are left. The state `s` is updated in place.
+fn keccak_f<F: Field>(
     rows: &mut Vec<KeccakRow<F>>,
-    squeeze_digests: &mut Vec<[F; NUM_WORDS_TO_SQUEEZE]>,
-    bytes: &[u8],
+    s: &mut [[F; 5]; 5],
+    block: &[u8],
+    bytes_left: usize,
     parameters: KeccakConfigParams,
-) {
+) -> [F; NUM_WORDS_TO_SQUEEZE] {
     let k = parameters.k;
     let num_rows_per_round = parameters.rows_per_round;
 
-    let mut bits = into_bits(bytes);
-    let mut s = [[F::ZERO; 5]; 5];
+    let is_final_block = bytes_left < RATE;
+    let mut bits = into_bits(block);
     let absorb_positions = get_absorb_positions();
-    let num_bytes_in_last_block = bytes.len() % RATE;
     let two = F::from(2u64);
 
     // Padding
-    bits.push(1);
-    while (bits.len() + 1) % RATE_IN_BITS != 0 {
-        bits.push(0);
+    if is_final_block {
+        bits.push(1);
+        while (bits.len() + 1) % RATE_IN_BITS != 0 {
+            bits.push(0);
+        }
+        bits.push(1);
     }
-    bits.push(1);
+    debug_assert_eq!(bits.len(), RATE_IN_BITS);
 
     // running length of absorbed input in bytes
     let mut length = 0;
-    let chunks = bits.chunks(RATE_IN_BITS);
-    let num_chunks = chunks.len();
 
     let mut cell_managers = Vec::with_capacity(NUM_ROUNDS + 1);
     let mut regions = Vec::with_capacity(NUM_ROUNDS + 1);
@@ -78,23 +326,16 @@ fn keccak<F: Field>(
     let mut hash_words = [F::ZERO; NUM_WORDS_TO_SQUEEZE];
     let mut hash = Word::default();
 
-    for (idx, chunk) in chunks.enumerate() {
-        let is_final_block = idx == num_chunks - 1;
-
+    {
         let mut absorb_rows = Vec::new();
         // Absorb
         for (idx, &(i, j)) in absorb_positions.iter().enumerate() {
-            let absorb = pack(&chunk[idx * 64..(idx + 1) * 64]);
+            let absorb = pack(&bits[idx * 64..(idx + 1) * 64]);
             let from = s[i][j];
             s[i][j] = field_xor(s[i][j], absorb);
             absorb_rows.push(AbsorbData { from, absorb, result: s[i][j] });
         }
 
-        // better memory management to clear already allocated Vecs
-        cell_managers.clear();
-        regions.clear();
-        round_lengths.clear();
-
         for round in 0..NUM_ROUNDS + 1 {
             let mut cell_manager = CellManager::new(num_rows_per_round);
             let mut region = KeccakRegion::new();
@@ -105,7 +346,7 @@ fn keccak<F: Field>(
             }
 
             // State data
-            for s in &s {
+            for s in s.iter() {
                 for s in s {
                     let cell = cell_manager.query_cell_value();
                     cell.assign(&mut region, 0, *s);
@@ -152,7 +393,7 @@ fn keccak<F: Field>(
             if round < NUM_WORDS_TO_ABSORB {
                 for (padding_idx, is_padding) in is_paddings.iter().enumerate() {
                     let byte_idx = round * NUM_BYTES_PER_WORD + padding_idx;
-                    let padding = if is_final_block && byte_idx >= num_bytes_in_last_block {
+                    let padding = if is_final_block && byte_idx >= block.len() {
                         true
                     } else {
                         length += 1;
@@ -167,7 +408,7 @@ fn keccak<F: Field>(
                 // Theta
                 let part_size = get_num_bits_per_theta_c_lookup(k);
                 let mut bcf = Vec::new();
-                for s in &s {
+                for s in s.iter() {
                     let c = s[0] + s[1] + s[2] + s[3] + s[4];
                     let bc_fat =
                         split::value(&mut cell_manager, &mut region, c, 1, part_size, false, None);
@@ -195,7 +436,7 @@ fn keccak<F: Field>(
                         os[i][j] = s[i][j] + t;
                     }
                 }
-                s = os;
+                *s = os;
                 cell_manager.start_region();
 
                 // Rho/Pi
@@ -276,7 +517,7 @@ fn keccak<F: Field>(
                         ));
                     }
                 }
-                s = os;
+                *s = os;
                 cell_manager.start_region();
 
                 // iota
@@ -306,7 +547,7 @@ fn keccak<F: Field>(
             let is_final = is_final_block && round == NUM_ROUNDS;
             hash = if is_final {
                 let hash_bytes_le = s
-                    .into_iter()
+                    .iter()
                     .take(4)
                     .flat_map(|a| to_bytes::value(&unpack(a[0])))
                     .rev()
@@ -347,31 +588,31 @@ fn keccak<F: Field>(
             cell_manager.start_region();
             transform::value(cell_manager, region, packed, false, |v| *v, true);
         }
-        squeeze_digests.push(hash_words);
 
         for round in 0..NUM_ROUNDS + 1 {
             let round_cst = pack_u64(ROUND_CST[round]);
 
             for row_idx in 0..num_rows_per_round {
                 let word_value = if round < NUM_WORDS_TO_ABSORB && row_idx == 0 {
-                    let byte_idx = (idx * NUM_WORDS_TO_ABSORB + round) * NUM_BYTES_PER_WORD;
-                    if byte_idx >= bytes.len() {
+                    let byte_idx = round * NUM_BYTES_PER_WORD;
+                    if byte_idx >= block.len() {
                         0
                     } else {
-                        let end = std::cmp::min(byte_idx + NUM_BYTES_PER_WORD, bytes.len());
-                        let mut word_bytes = bytes[byte_idx..end].to_vec().clone();
-                        word_bytes.resize(NUM_BYTES_PER_WORD, 0);
-                        u64::from_le_bytes(word_bytes.try_into().unwrap())
+                        let end = std::cmp::min(byte_idx + NUM_BYTES_PER_WORD, block.len());
+                        let mut word_bytes = [0u8; NUM_BYTES_PER_WORD];
+                        word_bytes[..end - byte_idx].copy_from_slice(&block[byte_idx..end]);
+                        u64::from_le_bytes(word_bytes)
                     }
                 } else {
                     0
                 };
+                // Position within the block, `bytes_left` counts from the start of the block
                 let byte_idx = if round < NUM_WORDS_TO_ABSORB {
                     round * NUM_BYTES_PER_WORD + std::cmp::min(row_idx, NUM_BYTES_PER_WORD - 1)
                 } else {
                     NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD
-                } + idx * NUM_WORDS_TO_ABSORB * NUM_BYTES_PER_WORD;
-                let bytes_left = if byte_idx >= bytes.len() { 0 } else { bytes.len() - byte_idx };
+                };
+                let bytes_left = if byte_idx >= bytes_left { 0 } else { bytes_left - byte_idx };
                 rows.push(KeccakRow {
                     q_enable: row_idx == 0,
                     q_round: row_idx == 0 && round < NUM_ROUNDS,
@@ -379,6 +620,7 @@ fn keccak<F: Field>(
                     q_round_last: row_idx == 0 && round == NUM_ROUNDS,
                     q_input: row_idx == 0 && round < NUM_WORDS_TO_ABSORB,
                     q_input_last: row_idx == 0 && round == NUM_WORDS_TO_ABSORB - 1,
+                    q_continue: false,
                     round_cst,
                     is_final: is_final_block && round == NUM_ROUNDS && row_idx == 0,
                     cell_values: regions[round].rows.get(row_idx).unwrap_or(&vec![]).clone(),
@@ -395,23 +637,27 @@ fn keccak<F: Field>(
             }
             log::trace!(" = = = = = = round {} end", round);
         }
-        log::trace!(" ====================== chunk {} end", idx);
+        log::trace!(" ====================== chunk end");
     }
 
     #[cfg(debug_assertions)]
     {
-        let hash_bytes = s
-            .into_iter()
-            .take(4)
-            .map(|a| {
-                pack_with_base::<F>(&unpack(a[0]), 2)
-                    .to_bytes_le()
-                    .into_iter()
-                    .take(8)
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-        log::debug!("hash: {:x?}", &(hash_bytes[0..4].concat()));
-        assert_eq!(length, bytes.len());
+        if is_final_block {
+            let hash_bytes = s
+                .iter()
+                .take(4)
+                .map(|a| {
+                    pack_with_base::<F>(&unpack(a[0]), 2)
+                        .to_bytes_le()
+                        .into_iter()
+                        .take(8)
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            log::debug!("hash: {:x?}", &(hash_bytes[0..4].concat()));
+        }
+        assert_eq!(length, block.len());
     }
+
+    hash_words
 }